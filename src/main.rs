@@ -3,8 +3,39 @@ mod project_1;
 mod project_2;
 mod project_3;
 mod project_4;
+mod storage;
 use project_3::*;
 
 fn main() {
-    run();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("project_1") => project_1::run(),
+        Some("project_4") => project_4::run(),
+        Some("convert") => convert(&args[2..]),
+        _ => run(),
+    }
+}
+
+// convert <源编码> <源文件> <目标编码> <目标文件>：在 text、binary、varint、delta 之间转换数据文件
+fn convert(args: &[String]) {
+    let [from_codec, from, to_codec, to] = args else {
+        eprintln!(
+            "用法：convert <源编码> <源文件> <目标编码> <目标文件>（编码为 text、binary、varint 或 delta）"
+        );
+        return;
+    };
+    let result = storage::codec_by_name(from_codec).and_then(|from_codec| {
+        let to_codec = storage::codec_by_name(to_codec)?;
+        storage::convert(
+            &storage::IoStats::default(),
+            std::path::Path::new(from),
+            from_codec.as_ref(),
+            std::path::Path::new(to),
+            to_codec.as_ref(),
+        )
+    });
+    match result {
+        Ok(count) => println!("> 已转换 {} 个元素：{} -> {}", count, from, to),
+        Err(err) => eprintln!("> 转换失败：{}", err),
+    }
 }
@@ -0,0 +1,317 @@
+#![allow(unused)]
+use super::REGION_SPAN;
+use collections::{BTreeMap, HashMap, VecDeque};
+use fs::*;
+use io::*;
+use std::*;
+
+/// 缓冲池的页面置换策略
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PagePolicy {
+    Lru,
+    /// 二次机会（时钟）算法
+    Clock,
+    /// 淘汰倒数第 k 次访问最早的页；访问不足 k 次的页优先淘汰，其间按 LRU
+    LruK {
+        k: usize,
+    },
+    /// 2Q：首次访问的页进入 FIFO 队列 A1in，被淘汰后只在 A1out 中留下页号，
+    /// 在 A1out 中再次被访问的页才进入 LRU 队列 Am
+    TwoQ,
+}
+
+impl PagePolicy {
+    pub fn label(&self) -> String {
+        match self {
+            PagePolicy::Lru => "lru".to_string(),
+            PagePolicy::Clock => "clock".to_string(),
+            PagePolicy::LruK { k } => format!("lru{}", k),
+            PagePolicy::TwoQ => "2q".to_string(),
+        }
+    }
+}
+
+/// 按时间戳排序的页队列：按页号删除与取出最早的页都是 O(log n)
+#[derive(Clone, Debug, Default)]
+struct StampQueue {
+    by_stamp: BTreeMap<u64, u64>,
+    by_page: HashMap<u64, u64>,
+}
+
+impl StampQueue {
+    fn len(&self) -> usize {
+        self.by_stamp.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.by_stamp.is_empty()
+    }
+
+    // 以时间戳 stamp 放入页，页已在队列中时移到新位置
+    fn push(&mut self, page: u64, stamp: u64) {
+        self.remove(page);
+        self.by_stamp.insert(stamp, page);
+        self.by_page.insert(page, stamp);
+    }
+
+    fn remove(&mut self, page: u64) -> bool {
+        match self.by_page.remove(&page) {
+            Some(stamp) => {
+                self.by_stamp.remove(&stamp);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pop_oldest(&mut self) -> Option<u64> {
+        let (_, page) = self.by_stamp.pop_first()?;
+        self.by_page.remove(&page);
+        Some(page)
+    }
+}
+
+/// 磁盘页缓冲池。位于矩阵文件与 CPU Cache 之间：Cache 未命中时按页访问缓冲池，
+/// 页不在缓冲池中即为一次缺页（从文件读入一页）。地址划分与 `Cache::touch` 相同，
+/// 各存储区相隔 REGION_SPAN 字节。
+#[derive(Clone, Debug)]
+pub struct BufferPool {
+    /// 页大小（字节）
+    pub page_size: usize,
+    pub frame_count: usize,
+    pub policy: PagePolicy,
+    pub page_hits: u64,
+    pub page_faults: u64,
+    pub evictions: u64,
+    // 每个页框中的页号
+    frames: Vec<Option<u64>>,
+    page_table: HashMap<u64, usize>,
+    // 逻辑时钟，每次访问加一
+    time: u64,
+    // LRU：页框最近一次访问的时间
+    last_used: Vec<u64>,
+    // CLOCK：访问位与时钟指针
+    referenced: Vec<bool>,
+    hand: usize,
+    // LRU-K：每个页最近 k 次访问的时间。页被淘汰后历史仍保留，
+    // 但只保留最近被淘汰的 frame_count 个页，按淘汰时间记在 retired 中
+    history: HashMap<u64, VecDeque<u64>>,
+    retired: StampQueue,
+    // 2Q 的三个队列，最早的页最先被淘汰；Am 按最近一次访问的时间排序
+    a1_in: VecDeque<u64>,
+    a1_out: StampQueue,
+    am: StampQueue,
+}
+
+impl BufferPool {
+    pub fn new(page_size: usize, frame_count: usize, policy: PagePolicy) -> BufferPool {
+        if page_size == 0 || frame_count == 0 {
+            panic!("页大小和页框数必须大于0");
+        }
+        if let PagePolicy::LruK { k: 0 } = policy {
+            panic!("LRU-K 的 k 必须大于0");
+        }
+        BufferPool {
+            page_size,
+            frame_count,
+            policy,
+            page_hits: 0,
+            page_faults: 0,
+            evictions: 0,
+            frames: vec![None; frame_count],
+            page_table: HashMap::new(),
+            time: 0,
+            last_used: vec![0; frame_count],
+            referenced: vec![false; frame_count],
+            hand: 0,
+            history: HashMap::new(),
+            retired: StampQueue::default(),
+            a1_in: VecDeque::new(),
+            a1_out: StampQueue::default(),
+            am: StampQueue::default(),
+        }
+    }
+
+    /// 访问第 region 个存储区中字节地址为 address 所在的页，返回是否命中
+    pub fn access(&mut self, region: u32, address: usize) -> bool {
+        let page = ((region as usize * REGION_SPAN + address) / self.page_size) as u64;
+        self.time += 1;
+        if let PagePolicy::LruK { k } = self.policy {
+            self.retired.remove(page);
+            let history = self.history.entry(page).or_default();
+            history.push_back(self.time);
+            if history.len() > k {
+                history.pop_front();
+            }
+        }
+
+        if let Some(&frame) = self.page_table.get(&page) {
+            self.page_hits += 1;
+            self.last_used[frame] = self.time;
+            self.referenced[frame] = true;
+            // 2Q：A1in 中的页命中时不调整位置
+            if self.am.remove(page) {
+                self.am.push(page, self.time);
+            }
+            return true;
+        }
+
+        self.page_faults += 1;
+        let frame = match self.frames.iter().position(Option::is_none) {
+            Some(frame) => frame,
+            None => {
+                let frame = self.victim();
+                let old = self.frames[frame].take().unwrap();
+                self.page_table.remove(&old);
+                self.evictions += 1;
+                if let PagePolicy::LruK { .. } = self.policy {
+                    self.retired.push(old, self.time);
+                    if self.retired.len() > self.frame_count {
+                        let forgotten = self.retired.pop_oldest().unwrap();
+                        self.history.remove(&forgotten);
+                    }
+                }
+                frame
+            }
+        };
+        self.frames[frame] = Some(page);
+        self.page_table.insert(page, frame);
+        self.last_used[frame] = self.time;
+        self.referenced[frame] = true;
+        if self.policy == PagePolicy::TwoQ {
+            if self.a1_out.remove(page) {
+                self.am.push(page, self.time);
+            } else {
+                self.a1_in.push_back(page);
+            }
+        }
+        false
+    }
+
+    /// 访问 [address, address + len) 覆盖的每一页，返回缺页数
+    pub fn access_range(&mut self, region: u32, address: usize, len: usize) -> u64 {
+        let first = address / self.page_size;
+        let last = (address + len.max(1) - 1) / self.page_size;
+        (first..=last)
+            .filter(|&p| !self.access(region, p * self.page_size))
+            .count() as u64
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.page_hits + self.page_faults;
+        if total == 0 {
+            0.0
+        } else {
+            self.page_hits as f64 / total as f64
+        }
+    }
+
+    // 缓冲池已满时选出被淘汰的页框
+    fn victim(&mut self) -> usize {
+        match self.policy {
+            PagePolicy::Lru => (0..self.frame_count)
+                .min_by_key(|&f| self.last_used[f])
+                .unwrap(),
+            PagePolicy::Clock => loop {
+                let frame = self.hand;
+                self.hand = (self.hand + 1) % self.frame_count;
+                if self.referenced[frame] {
+                    self.referenced[frame] = false;
+                } else {
+                    return frame;
+                }
+            },
+            PagePolicy::LruK { k } => (0..self.frame_count)
+                .min_by_key(|&f| {
+                    let history = &self.history[&self.frames[f].unwrap()];
+                    // 访问不足 k 次的页倒数第 k 次访问时间视为 0
+                    let kth = if history.len() < k { 0 } else { history[0] };
+                    (kth, self.last_used[f])
+                })
+                .unwrap(),
+            PagePolicy::TwoQ => {
+                // A1in 的容量取页框数的 1/4，A1out 记住页框数一半的页号
+                let in_capacity = usize::max(1, self.frame_count / 4);
+                let out_capacity = usize::max(1, self.frame_count / 2);
+                let page = if self.a1_in.len() > in_capacity || self.am.is_empty() {
+                    let page = self.a1_in.pop_front().unwrap();
+                    self.a1_out.push(page, self.time);
+                    if self.a1_out.len() > out_capacity {
+                        self.a1_out.pop_oldest();
+                    }
+                    page
+                } else {
+                    self.am.pop_oldest().unwrap()
+                };
+                self.page_table[&page]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 页大小为 1 字节，地址即页号；返回每次访问命中（H）或缺页（F）
+    fn run(policy: PagePolicy, frame_count: usize, trace: &[usize]) -> (String, BufferPool) {
+        let mut pool = BufferPool::new(1, frame_count, policy);
+        let result = trace
+            .iter()
+            .map(|&page| if pool.access(0, page) { 'H' } else { 'F' })
+            .collect();
+        (result, pool)
+    }
+
+    const TRACE: [usize; 12] = [1, 2, 3, 1, 4, 1, 2, 5, 1, 2, 3, 4];
+
+    #[test]
+    fn lru_trace() {
+        let (result, pool) = run(PagePolicy::Lru, 3, &TRACE);
+        assert_eq!(result, "FFFHFHFFHHFF");
+        assert_eq!(
+            (pool.page_hits, pool.page_faults, pool.evictions),
+            (4, 8, 5)
+        );
+    }
+
+    #[test]
+    fn clock_trace() {
+        // 第 5 次访问时所有访问位都被清零，指针转一圈后淘汰页 1
+        let (result, pool) = run(PagePolicy::Clock, 3, &TRACE);
+        assert_eq!(result, "FFFHFFFFHHFF");
+        assert_eq!(
+            (pool.page_hits, pool.page_faults, pool.evictions),
+            (3, 9, 6)
+        );
+    }
+
+    #[test]
+    fn lru_k_trace() {
+        let (result, _) = run(PagePolicy::LruK { k: 2 }, 3, &TRACE);
+        assert_eq!(result, "FFFHFHFFHHFF");
+        // 页 1 访问过两次，页 2 只访问过一次，LRU-2 淘汰页 2 而 LRU 淘汰页 1
+        let trace = [1, 1, 2, 3, 1];
+        assert_eq!(run(PagePolicy::Lru, 2, &trace).0, "FHFFF");
+        assert_eq!(run(PagePolicy::LruK { k: 2 }, 2, &trace).0, "FHFFH");
+    }
+
+    #[test]
+    fn two_q_trace() {
+        // 4 个页框：A1in 容量 1，A1out 容量 2。
+        // 1、2、3 从 A1out 回到 Am；6 淘汰 Am 队首的 1；2 命中后排到 3 之后，8 淘汰 3
+        let trace = [1, 2, 3, 4, 5, 1, 2, 3, 6, 2, 7, 5, 8, 3, 2];
+        let (result, pool) = run(PagePolicy::TwoQ, 4, &trace);
+        assert_eq!(result, "FFFFFFFFFHFFFFH");
+        assert_eq!(pool.evictions, 9);
+    }
+
+    #[test]
+    fn lru_k_history_is_bounded() {
+        let trace: Vec<usize> = (0..1000).chain(0..1000).collect();
+        let (_, pool) = run(PagePolicy::LruK { k: 2 }, 3, &trace);
+        // 驻留页与最近淘汰的页各至多 frame_count 个
+        assert!(pool.history.len() <= 2 * pool.frame_count);
+        assert_eq!(pool.retired.len(), pool.frame_count);
+    }
+}
@@ -0,0 +1,134 @@
+use rand::Rng;
+use std::fmt::{Debug, Display};
+use std::ops::{Add, AddAssign, Mul};
+use std::str::FromStr;
+
+/// 矩阵元素类型。地址计算按 `SIZE` 字节进行，因此 f64 的一条 Cache 行只能放下 f32 的一半元素。
+pub trait Element:
+    Copy
+    + Default
+    + PartialEq
+    + PartialOrd
+    + Debug
+    + Display
+    + FromStr
+    + Add<Output = Self>
+    + Mul<Output = Self>
+    + AddAssign
+    + Send
+    + Sync
+    + 'static
+{
+    /// 类型名，用于结果文件名
+    const NAME: &'static str;
+    /// 元素占用的字节数
+    const SIZE: usize = std::mem::size_of::<Self>();
+    /// 二进制矩阵文件头中的类型编号
+    const TYPE_CODE: u8;
+    /// Matrix Market 文件头中的 field
+    const MARKET_FIELD: &'static str;
+    const ONE: Self;
+
+    /// 与 `Matrix::new` 原有取值范围一致的随机元素（0 到 100）
+    fn random(rng: &mut impl Rng) -> Self;
+    fn to_le_bytes_vec(self) -> Vec<u8>;
+    fn from_le_slice(bytes: &[u8]) -> Self;
+    /// 校验结果时使用：整数要求相等，浮点数允许累加顺序带来的舍入误差
+    fn approx_eq(self, other: Self) -> bool;
+
+    fn is_zero(self) -> bool {
+        self == Self::default()
+    }
+}
+
+impl Element for u32 {
+    const NAME: &'static str = "u32";
+    const TYPE_CODE: u8 = 1;
+    const MARKET_FIELD: &'static str = "integer";
+    const ONE: Self = 1;
+
+    fn random(rng: &mut impl Rng) -> Self {
+        rng.random_range(0..100)
+    }
+
+    fn to_le_bytes_vec(self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        Self::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn approx_eq(self, other: Self) -> bool {
+        self == other
+    }
+}
+
+impl Element for i64 {
+    const NAME: &'static str = "i64";
+    const TYPE_CODE: u8 = 2;
+    const MARKET_FIELD: &'static str = "integer";
+    const ONE: Self = 1;
+
+    fn random(rng: &mut impl Rng) -> Self {
+        rng.random_range(0..100)
+    }
+
+    fn to_le_bytes_vec(self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        Self::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn approx_eq(self, other: Self) -> bool {
+        self == other
+    }
+}
+
+impl Element for f32 {
+    const NAME: &'static str = "f32";
+    const TYPE_CODE: u8 = 3;
+    const MARKET_FIELD: &'static str = "real";
+    const ONE: Self = 1.0;
+
+    fn random(rng: &mut impl Rng) -> Self {
+        rng.random_range(0.0..100.0)
+    }
+
+    fn to_le_bytes_vec(self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        Self::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn approx_eq(self, other: Self) -> bool {
+        (self - other).abs() <= 1e-4 * f32::max(1.0, f32::max(self.abs(), other.abs()))
+    }
+}
+
+impl Element for f64 {
+    const NAME: &'static str = "f64";
+    const TYPE_CODE: u8 = 4;
+    const MARKET_FIELD: &'static str = "real";
+    const ONE: Self = 1.0;
+
+    fn random(rng: &mut impl Rng) -> Self {
+        rng.random_range(0.0..100.0)
+    }
+
+    fn to_le_bytes_vec(self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        Self::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn approx_eq(self, other: Self) -> bool {
+        (self - other).abs() <= 1e-9 * f64::max(1.0, f64::max(self.abs(), other.abs()))
+    }
+}
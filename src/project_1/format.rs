@@ -0,0 +1,581 @@
+#![allow(unused)]
+use fs::*;
+use io::*;
+use std::path::Path;
+use std::*;
+
+use super::Element;
+
+/// 二进制矩阵文件的魔数
+const BINARY_MAGIC: &[u8; 4] = b"P1MX";
+const BINARY_VERSION: u8 = 1;
+
+/// 二进制文件中元素的排列方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    RowMajor,
+    ColMajor,
+}
+
+/// 矩阵文件格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixFormat {
+    /// 空白分隔的文本，一行对应矩阵的一行，维度由行数推断
+    Text,
+    /// 16 字节文件头（魔数、版本、元素类型编号 `Element::TYPE_CODE`、排列方式、行数、列数）+ 小端序数据
+    Binary(Layout),
+    /// Matrix Market 稠密格式（array，按列优先存放）
+    MarketArray,
+    /// Matrix Market 坐标格式（coordinate，下标从 1 开始）
+    MarketCoordinate,
+}
+
+impl MatrixFormat {
+    /// 按扩展名推断格式：`.bin` 为二进制，`.mtx` 为 Matrix Market，其余为文本。
+    /// 读取 `.mtx` 时以文件头为准，这里的 array/coordinate 只决定写出的格式。
+    pub fn from_path(path: impl AsRef<Path>) -> MatrixFormat {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("bin") => MatrixFormat::Binary(Layout::RowMajor),
+            Some("mtx") => MatrixFormat::MarketArray,
+            _ => MatrixFormat::Text,
+        }
+    }
+}
+
+/// Matrix Market 文件的内容
+pub enum MarketData<T: Element = u32> {
+    Dense {
+        rows: u32,
+        cols: u32,
+        data: Vec<Vec<T>>,
+    },
+    Coordinate {
+        rows: u32,
+        cols: u32,
+        entries: Vec<(u32, u32, T)>,
+    },
+}
+
+impl<T: Element> MarketData<T> {
+    pub fn into_dense(self) -> Vec<Vec<T>> {
+        match self {
+            MarketData::Dense { data, .. } => data,
+            MarketData::Coordinate {
+                rows,
+                cols,
+                entries,
+            } => {
+                let mut data = vec![vec![T::default(); cols as usize]; rows as usize];
+                for (i, j, v) in entries {
+                    data[i as usize][j as usize] = v;
+                }
+                data
+            }
+        }
+    }
+}
+
+pub(crate) fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// 读取稠密矩阵，格式由扩展名（`.mtx` 再由文件头）决定
+pub fn read_dense<T: Element>(path: impl AsRef<Path>) -> io::Result<Vec<Vec<T>>> {
+    let path = path.as_ref();
+    match MatrixFormat::from_path(path) {
+        MatrixFormat::Text => read_text(path),
+        MatrixFormat::Binary(_) => read_binary(path),
+        MatrixFormat::MarketArray | MatrixFormat::MarketCoordinate => {
+            Ok(read_market(path)?.into_dense())
+        }
+    }
+}
+
+pub fn write_dense<T: Element>(
+    path: impl AsRef<Path>,
+    data: &[Vec<T>],
+    format: MatrixFormat,
+) -> io::Result<()> {
+    match format {
+        MatrixFormat::Text => write_text(path, data),
+        MatrixFormat::Binary(layout) => write_binary(path, data, layout),
+        MatrixFormat::MarketArray => write_market_array(path, data),
+        MatrixFormat::MarketCoordinate => {
+            let mut entries = Vec::new();
+            for (i, row) in data.iter().enumerate() {
+                for (j, &v) in row.iter().enumerate() {
+                    if !v.is_zero() {
+                        entries.push((i as u32, j as u32, v));
+                    }
+                }
+            }
+            let cols = data.first().map_or(0, |row| row.len()) as u32;
+            write_market_coordinate(path, data.len() as u32, cols, &entries)
+        }
+    }
+}
+
+fn parse_value<T: Element>(token: &str) -> io::Result<T> {
+    token
+        .parse::<T>()
+        .map_err(|_| invalid_data(format!("无法把 \"{}\" 解析为 {}", token, T::NAME)))
+}
+
+fn read_text<T: Element>(path: &Path) -> io::Result<Vec<Vec<T>>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut data = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let row: Vec<T> = line
+            .split_whitespace()
+            .map(parse_value)
+            .collect::<io::Result<_>>()
+            .map_err(|e| invalid_data(format!("第 {} 行: {}", line_no + 1, e)))?;
+        data.push(row);
+    }
+    Ok(data)
+}
+
+fn write_text<T: Element>(path: impl AsRef<Path>, data: &[Vec<T>]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for row in data {
+        let line = row
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        writeln!(writer, "{}", line)?;
+    }
+    writer.flush()
+}
+
+/// 二进制矩阵文件头
+#[derive(Clone, Copy, Debug)]
+pub struct BinaryHeader {
+    pub type_code: u8,
+    pub layout: Layout,
+    pub rows: u32,
+    pub cols: u32,
+}
+
+impl BinaryHeader {
+    /// 文件头长度（字节），数据紧随其后
+    pub const SIZE: u64 = 16;
+
+    pub fn new<T: Element>(rows: u32, cols: u32, layout: Layout) -> BinaryHeader {
+        BinaryHeader {
+            type_code: T::TYPE_CODE,
+            layout,
+            rows,
+            cols,
+        }
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> io::Result<BinaryHeader> {
+        let mut header = [0u8; Self::SIZE as usize];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != BINARY_MAGIC {
+            return Err(invalid_data("不是二进制矩阵文件".to_string()));
+        }
+        if header[4] != BINARY_VERSION {
+            return Err(invalid_data(format!("不支持的文件版本: {}", header[4])));
+        }
+        let layout = match header[6] {
+            0 => Layout::RowMajor,
+            1 => Layout::ColMajor,
+            other => return Err(invalid_data(format!("未知的排列方式: {}", other))),
+        };
+        Ok(BinaryHeader {
+            type_code: header[5],
+            layout,
+            rows: u32::from_le_bytes(header[8..12].try_into().unwrap()),
+            cols: u32::from_le_bytes(header[12..16].try_into().unwrap()),
+        })
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(BINARY_MAGIC)?;
+        let layout_code = match self.layout {
+            Layout::RowMajor => 0,
+            Layout::ColMajor => 1,
+        };
+        writer.write_all(&[BINARY_VERSION, self.type_code, layout_code, 0])?;
+        writer.write_all(&self.rows.to_le_bytes())?;
+        writer.write_all(&self.cols.to_le_bytes())
+    }
+
+    /// 检查文件中的元素类型是否为 T
+    pub fn check_type<T: Element>(&self) -> io::Result<()> {
+        if self.type_code != T::TYPE_CODE {
+            return Err(invalid_data(format!(
+                "文件中的元素类型编号为 {}，而读取的类型 {} 编号为 {}",
+                self.type_code,
+                T::NAME,
+                T::TYPE_CODE
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn read_binary<T: Element>(path: &Path) -> io::Result<Vec<Vec<T>>> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let header = BinaryHeader::read_from(&mut reader)
+        .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?;
+    header.check_type::<T>()?;
+    let layout = header.layout;
+    let (rows, cols) = (header.rows as usize, header.cols as usize);
+
+    // 按文件头分配内存之前先核对文件长度，损坏或被截断的文件不会触发巨大的分配
+    let body_len = (rows as u64)
+        .checked_mul(cols as u64)
+        .and_then(|n| n.checked_mul(T::SIZE as u64));
+    if body_len != Some(file_len.saturating_sub(BinaryHeader::SIZE)) {
+        return Err(invalid_data(format!(
+            "{}: 文件头声明 {}×{} 个 {}，与数据长度 {} 字节不符",
+            path.display(),
+            rows,
+            cols,
+            T::NAME,
+            file_len.saturating_sub(BinaryHeader::SIZE)
+        )));
+    }
+    let mut body = vec![0u8; rows * cols * T::SIZE];
+    reader.read_exact(&mut body)?;
+    let mut data = vec![vec![T::default(); cols]; rows];
+    for (n, chunk) in body.chunks_exact(T::SIZE).enumerate() {
+        let value = T::from_le_slice(chunk);
+        let (i, j) = match layout {
+            Layout::RowMajor => (n / cols, n % cols),
+            Layout::ColMajor => (n % rows, n / rows),
+        };
+        data[i][j] = value;
+    }
+    Ok(data)
+}
+
+fn write_binary<T: Element>(
+    path: impl AsRef<Path>,
+    data: &[Vec<T>],
+    layout: Layout,
+) -> io::Result<()> {
+    let rows = data.len();
+    let cols = data.first().map_or(0, |row| row.len());
+    let mut writer = BufWriter::new(File::create(path)?);
+    BinaryHeader::new::<T>(rows as u32, cols as u32, layout).write_to(&mut writer)?;
+    match layout {
+        Layout::RowMajor => {
+            for row in data {
+                for v in row {
+                    writer.write_all(&v.to_le_bytes_vec())?;
+                }
+            }
+        }
+        Layout::ColMajor => {
+            for j in 0..cols {
+                for row in data {
+                    writer.write_all(&row[j].to_le_bytes_vec())?;
+                }
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// 读取 Matrix Market 文件。支持 array / coordinate，integer / real / pattern，
+/// general / symmetric（对称矩阵只存下三角，读入时补全）。数值按 T 解析，
+/// 因此 real 文件只能读成 f32 / f64。
+pub fn read_market<T: Element>(path: impl AsRef<Path>) -> io::Result<MarketData<T>> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let reader = BufReader::new(file);
+    let mut lines = reader.lines();
+
+    let banner = lines
+        .next()
+        .ok_or_else(|| invalid_data(format!("文件为空: {}", path.display())))??;
+    let banner: Vec<String> = banner
+        .split_whitespace()
+        .map(|s| s.to_ascii_lowercase())
+        .collect();
+    if banner.len() != 5 || banner[0] != "%%matrixmarket" || banner[1] != "matrix" {
+        return Err(invalid_data(format!(
+            "缺少 Matrix Market 文件头: {}",
+            path.display()
+        )));
+    }
+    let coordinate = match banner[2].as_str() {
+        "coordinate" => true,
+        "array" => false,
+        other => return Err(invalid_data(format!("未知的存储方式: {}", other))),
+    };
+    let pattern = match banner[3].as_str() {
+        "integer" | "real" => false,
+        "pattern" if coordinate => true,
+        other => return Err(invalid_data(format!("不支持的元素类型: {}", other))),
+    };
+    let symmetric = match banner[4].as_str() {
+        "general" => false,
+        "symmetric" => true,
+        other => return Err(invalid_data(format!("不支持的对称性: {}", other))),
+    };
+
+    // 跳过注释与空行后，剩下的每一行都是若干个字段
+    let mut data_lines = lines.filter(|line| match line {
+        Ok(line) => !line.trim().is_empty() && !line.starts_with('%'),
+        Err(_) => true,
+    });
+    let mut next_fields = || -> io::Result<Option<Vec<String>>> {
+        match data_lines.next() {
+            None => Ok(None),
+            Some(line) => Ok(Some(
+                line?.split_whitespace().map(|s| s.to_string()).collect(),
+            )),
+        }
+    };
+    let parse_u32 = |token: &str| {
+        token
+            .parse::<u32>()
+            .map_err(|e| invalid_data(format!("无法解析下标 \"{}\": {}", token, e)))
+    };
+
+    let size = next_fields()?.ok_or_else(|| invalid_data("缺少尺寸行".to_string()))?;
+    if symmetric && size.len() >= 2 && size[0] != size[1] {
+        return Err(invalid_data("对称矩阵必须是方阵".to_string()));
+    }
+
+    if coordinate {
+        if size.len() != 3 {
+            return Err(invalid_data(
+                "坐标格式的尺寸行应为 \"rows cols nnz\"".to_string(),
+            ));
+        }
+        let (rows, cols) = (parse_u32(&size[0])?, parse_u32(&size[1])?);
+        let nnz = parse_u32(&size[2])? as usize;
+        // 每个非零元至少占 "i j\n" 4 个字节
+        if nnz as u64 > file_len / 4 {
+            return Err(invalid_data(format!(
+                "声明了 {} 个非零元，文件只有 {} 字节",
+                nnz, file_len
+            )));
+        }
+        let mut entries = Vec::with_capacity(nnz);
+        for _ in 0..nnz {
+            let fields = next_fields()?
+                .ok_or_else(|| invalid_data(format!("应有 {} 个非零元，文件提前结束", nnz)))?;
+            let expected = if pattern { 2 } else { 3 };
+            if fields.len() != expected {
+                return Err(invalid_data(format!("非零元应有 {} 个字段", expected)));
+            }
+            let (i, j) = (parse_u32(&fields[0])?, parse_u32(&fields[1])?);
+            if i == 0 || j == 0 || i > rows || j > cols {
+                return Err(invalid_data(format!("下标越界: ({}, {})", i, j)));
+            }
+            let v = if pattern {
+                T::ONE
+            } else {
+                parse_value(&fields[2])?
+            };
+            entries.push((i - 1, j - 1, v));
+            if symmetric && i != j {
+                entries.push((j - 1, i - 1, v));
+            }
+        }
+        Ok(MarketData::Coordinate {
+            rows,
+            cols,
+            entries,
+        })
+    } else {
+        if size.len() != 2 {
+            return Err(invalid_data(
+                "稠密格式的尺寸行应为 \"rows cols\"".to_string(),
+            ));
+        }
+        let (rows, cols) = (parse_u32(&size[0])?, parse_u32(&size[1])?);
+        // 每个元素至少占 "v\n" 2 个字节；对称矩阵只存下三角，但分配的仍是整个矩阵
+        let elements = rows as u64 * cols as u64;
+        let stored = if symmetric {
+            elements.div_ceil(2)
+        } else {
+            elements
+        };
+        if stored > file_len / 2 {
+            return Err(invalid_data(format!(
+                "声明了 {}×{} 的稠密矩阵，文件只有 {} 字节",
+                rows, cols, file_len
+            )));
+        }
+        let mut data = vec![vec![T::default(); cols as usize]; rows as usize];
+        // 按列优先存放；对称矩阵只存放下三角（含对角线）
+        let positions = (0..cols as usize)
+            .flat_map(|j| (if symmetric { j } else { 0 }..rows as usize).map(move |i| (i, j)));
+        for (i, j) in positions {
+            let fields =
+                next_fields()?.ok_or_else(|| invalid_data("稠密矩阵的元素个数不足".to_string()))?;
+            if fields.len() != 1 {
+                return Err(invalid_data("稠密格式每行只能有一个元素".to_string()));
+            }
+            let v = parse_value(&fields[0])?;
+            data[i][j] = v;
+            if symmetric {
+                data[j][i] = v;
+            }
+        }
+        Ok(MarketData::Dense { rows, cols, data })
+    }
+}
+
+fn write_market_array<T: Element>(path: impl AsRef<Path>, data: &[Vec<T>]) -> io::Result<()> {
+    let rows = data.len();
+    let cols = data.first().map_or(0, |row| row.len());
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "%%MatrixMarket matrix array {} general",
+        T::MARKET_FIELD
+    )?;
+    writeln!(writer, "{} {}", rows, cols)?;
+    for j in 0..cols {
+        for row in data {
+            writeln!(writer, "{}", row[j])?;
+        }
+    }
+    writer.flush()
+}
+
+/// 写出 Matrix Market 坐标格式，entries 中的下标从 0 开始
+pub fn write_market_coordinate<T: Element>(
+    path: impl AsRef<Path>,
+    rows: u32,
+    cols: u32,
+    entries: &[(u32, u32, T)],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "%%MatrixMarket matrix coordinate {} general",
+        T::MARKET_FIELD
+    )?;
+    writeln!(writer, "{} {} {}", rows, cols, entries.len())?;
+    for &(i, j, v) in entries {
+        writeln!(writer, "{} {} {}", i + 1, j + 1, v)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("project_1_format_{}_{}", process::id(), name))
+    }
+
+    fn round_trip<T: Element>(name: &str, data: &[Vec<T>], format: MatrixFormat) -> Vec<Vec<T>> {
+        let path = temp_path(name);
+        write_dense(&path, data, format).unwrap();
+        let read = read_dense(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        read
+    }
+
+    #[test]
+    fn dense_formats_round_trip() {
+        let ints: Vec<Vec<u32>> = vec![vec![1, 0, 3], vec![0, 5, 6]];
+        let reals: Vec<Vec<f64>> = vec![vec![1.5, 0.0, -3.25], vec![4.0, 0.5, 6.0]];
+        assert_eq!(round_trip("text.txt", &ints, MatrixFormat::Text), ints);
+        for layout in [Layout::RowMajor, Layout::ColMajor] {
+            let name = format!("{:?}.bin", layout);
+            assert_eq!(
+                round_trip(&name, &reals, MatrixFormat::Binary(layout)),
+                reals
+            );
+            assert_eq!(round_trip(&name, &ints, MatrixFormat::Binary(layout)), ints);
+        }
+        assert_eq!(
+            round_trip("array.mtx", &reals, MatrixFormat::MarketArray),
+            reals
+        );
+        assert_eq!(
+            round_trip("coordinate.mtx", &ints, MatrixFormat::MarketCoordinate),
+            ints
+        );
+    }
+
+    #[test]
+    fn binary_layouts_differ_on_disk() {
+        let data: Vec<Vec<u32>> = vec![vec![1, 2], vec![3, 4]];
+        let path = temp_path("layout.bin");
+        write_dense(&path, &data, MatrixFormat::Binary(Layout::ColMajor)).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let body: Vec<u32> = bytes[BinaryHeader::SIZE as usize..]
+            .chunks_exact(4)
+            .map(u32::from_le_slice)
+            .collect();
+        assert_eq!(body, [1, 3, 2, 4]);
+    }
+
+    #[test]
+    fn reads_symmetric_market_files() {
+        let expected: Vec<Vec<i64>> = vec![vec![1, 2, 0], vec![2, 0, 4], vec![0, 4, 5]];
+        let path = temp_path("symmetric_array.mtx");
+        // 按列存放下三角：(0,0) (1,0) (2,0) (1,1) (2,1) (2,2)
+        fs::write(
+            &path,
+            "%%MatrixMarket matrix array integer symmetric\n% comment\n3 3\n1\n2\n0\n0\n4\n5\n",
+        )
+        .unwrap();
+        assert_eq!(read_dense::<i64>(&path).unwrap(), expected);
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_path("symmetric_coordinate.mtx");
+        fs::write(
+            &path,
+            "%%MatrixMarket matrix coordinate integer symmetric\n3 3 4\n1 1 1\n2 1 2\n3 2 4\n3 3 5\n",
+        )
+        .unwrap();
+        match read_market::<i64>(&path).unwrap() {
+            market @ MarketData::Coordinate { .. } => {
+                assert_eq!(market.into_dense(), expected)
+            }
+            MarketData::Dense { .. } => panic!("应读成坐标格式"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_headers_larger_than_the_file() {
+        let path = temp_path("huge.bin");
+        let mut bytes = Vec::new();
+        BinaryHeader::new::<f64>(100_000, 100_000, Layout::RowMajor)
+            .write_to(&mut bytes)
+            .unwrap();
+        bytes.extend_from_slice(&[0; 64]);
+        fs::write(&path, &bytes).unwrap();
+        let err = read_dense::<f64>(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+
+        for (name, content) in [
+            (
+                "huge_coordinate.mtx",
+                "%%MatrixMarket matrix coordinate integer general\n10 10 4000000000\n1 1 1\n",
+            ),
+            (
+                "huge_array.mtx",
+                "%%MatrixMarket matrix array integer general\n100000 100000\n1\n",
+            ),
+        ] {
+            let path = temp_path(name);
+            fs::write(&path, content).unwrap();
+            let err = read_market::<u32>(&path).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", name);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,839 @@
+#![allow(unused)]
+use super::format::invalid_data;
+use super::*;
+use fs::*;
+use io::*;
+use std::path::Path;
+use std::*;
+
+/// 评测网格中的计算内核：稠密乘法的循环顺序、稀疏内核，
+/// 或在与 Cache 同样容量的片上存储上按某个循环顺序做分块乘法
+#[derive(Clone, Debug)]
+pub enum GridKernel {
+    Dense(Sequence),
+    Sparse(SparseKernel),
+    Scratchpad(Sequence),
+}
+
+impl GridKernel {
+    pub fn label(&self) -> String {
+        match self {
+            GridKernel::Dense(sequence) => sequence.to_string().to_string(),
+            GridKernel::Sparse(kernel) => kernel.to_string().to_string(),
+            GridKernel::Scratchpad(sequence) => format!("spm_{}", sequence.to_string()),
+        }
+    }
+}
+
+/// 配置文件中一个 `[名称]` 段定义的实验。除 output 外每个键都是逗号分隔的列表，
+/// 评测时展开为所有取值的笛卡尔积，写入同一个 CSV，每个参数一列。
+///
+/// ```text
+/// # 注释
+/// [loop_orders]
+/// output = origin_data/grid_loop_orders.csv   # 相对 data/project_1
+/// elements = u32, f64
+/// dimensions = 3, 6, 10
+/// caches = 8x32, 16x64                        # 行数x行大小（字节）
+/// cache_line_numbers = 1, 2, 4                # 未给出 caches 时与 cache_line_sizes 组合
+/// cache_line_sizes = 16, 32
+/// ways = 1, 2, 4                             # 相联度，默认直接映射
+/// partitions = shared, ways_1_2_1, split_4_8_4   # 按 A、B、C 划分路或行，默认共享
+/// sector_sizes = 8, 16                        # 扇区大小（字节），大于行大小时按整行，默认不分扇区
+/// kernels = Sijk, Sikj, SpmvCsr, spm_Sijk     # spm_ 前缀：容量为行数×行大小的片上存储
+/// register_models = off, scalar, tile2x2      # 只对稠密内核有效
+/// patterns = random_0.1, banded_2, powerlaw_8_2  # 只对稀疏内核有效
+/// page_sizes = 256                            # 不给出时不使用缓冲池
+/// frame_counts = 4, 8
+/// policies = lru, clock, lru2, 2q
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExperimentGrid {
+    pub name: String,
+    pub output: String,
+    pub elements: Vec<String>,
+    pub dimensions: Vec<u32>,
+    /// (行数, 行大小)
+    pub caches: Vec<(u32, u32)>,
+    pub ways: Vec<u32>,
+    pub partitions: Vec<CachePartition>,
+    /// 为空时不分扇区
+    pub sector_sizes: Vec<u32>,
+    pub kernels: Vec<GridKernel>,
+    pub register_models: Vec<RegisterModel>,
+    pub patterns: Vec<SparsePattern>,
+    pub page_sizes: Vec<usize>,
+    pub frame_counts: Vec<usize>,
+    pub policies: Vec<PagePolicy>,
+}
+
+impl ExperimentGrid {
+    fn new(name: &str) -> ExperimentGrid {
+        ExperimentGrid {
+            name: name.to_string(),
+            output: format!("origin_data/grid_{}.csv", name),
+            elements: vec![u32::NAME.to_string()],
+            dimensions: Vec::new(),
+            caches: Vec::new(),
+            ways: vec![1],
+            partitions: vec![CachePartition::Shared],
+            sector_sizes: Vec::new(),
+            kernels: Vec::new(),
+            register_models: vec![RegisterModel::Off],
+            patterns: vec![SparsePattern::Random { density: 0.1 }],
+            page_sizes: Vec::new(),
+            frame_counts: Vec::new(),
+            policies: vec![PagePolicy::Lru],
+        }
+    }
+
+    /// 读取配置文件中的全部实验
+    pub fn from_file(path: &str) -> io::Result<Vec<ExperimentGrid>> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|e| invalid_data(format!("{}: {}", path, e)))
+    }
+
+    pub fn parse(content: &str) -> io::Result<Vec<ExperimentGrid>> {
+        let mut grids: Vec<ExperimentGrid> = Vec::new();
+        // 只给出 cache_line_numbers / cache_line_sizes 时，在段结束后再组合
+        let mut line_numbers: Vec<u32> = Vec::new();
+        let mut line_sizes: Vec<u32> = Vec::new();
+
+        for (n, raw) in content.lines().enumerate() {
+            let line = raw.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |msg: String| invalid_data(format!("第 {} 行: {}", n + 1, msg));
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if let Some(grid) = grids.last_mut() {
+                    combine_caches(grid, &mut line_numbers, &mut line_sizes);
+                }
+                grids.push(ExperimentGrid::new(name.trim()));
+                continue;
+            }
+            let Some(grid) = grids.last_mut() else {
+                return Err(error("键值对之前缺少 [实验名称]".to_string()));
+            };
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("无法解析: {}", line)));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let items: Vec<&str> = value
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .collect();
+            match key {
+                "output" => grid.output = value.to_string(),
+                "elements" => {
+                    grid.elements = items.iter().map(|s| s.to_string()).collect();
+                    if let Some(bad) = grid
+                        .elements
+                        .iter()
+                        .find(|e| !["u32", "i64", "f32", "f64"].contains(&e.as_str()))
+                    {
+                        return Err(error(format!("不支持的元素类型: {}", bad)));
+                    }
+                }
+                "dimensions" => grid.dimensions = parse_list(&items).map_err(error)?,
+                "cache_line_numbers" => line_numbers = parse_list(&items).map_err(error)?,
+                "cache_line_sizes" => line_sizes = parse_list(&items).map_err(error)?,
+                "caches" => grid.caches = parse_with(&items, parse_cache).map_err(error)?,
+                "ways" => grid.ways = parse_list(&items).map_err(error)?,
+                "sector_sizes" => grid.sector_sizes = parse_list(&items).map_err(error)?,
+                "partitions" => {
+                    grid.partitions = parse_with(&items, parse_partition).map_err(error)?;
+                }
+                "kernels" => {
+                    grid.kernels = parse_with(&items, parse_kernel).map_err(error)?;
+                }
+                "register_models" => {
+                    grid.register_models =
+                        parse_with(&items, parse_register_model).map_err(error)?;
+                }
+                "patterns" => grid.patterns = parse_with(&items, parse_pattern).map_err(error)?,
+                "page_sizes" => grid.page_sizes = parse_list(&items).map_err(error)?,
+                "frame_counts" => grid.frame_counts = parse_list(&items).map_err(error)?,
+                "policies" => grid.policies = parse_with(&items, parse_policy).map_err(error)?,
+                _ => return Err(error(format!("未知的键: {}", key))),
+            }
+        }
+        if let Some(grid) = grids.last_mut() {
+            combine_caches(grid, &mut line_numbers, &mut line_sizes);
+        }
+
+        for grid in &grids {
+            if grid.dimensions.is_empty() || grid.caches.is_empty() || grid.kernels.is_empty() {
+                return Err(invalid_data(format!(
+                    "实验 {} 缺少 dimensions、caches 或 kernels",
+                    grid.name
+                )));
+            }
+            // 各元素类型中最大的元素大小（字节）
+            let element_size = grid
+                .elements
+                .iter()
+                .map(|e| match e.as_str() {
+                    "u32" | "f32" => 4,
+                    _ => 8,
+                })
+                .max()
+                .unwrap_or(4);
+            let in_grid = |e: io::Error| invalid_data(format!("实验 {}: {}", grid.name, e));
+            for &dimension in &grid.dimensions {
+                check_dimension(dimension, element_size).map_err(in_grid)?;
+            }
+            let has_scratchpad = grid
+                .kernels
+                .iter()
+                .any(|k| matches!(k, GridKernel::Scratchpad(_)));
+            for config in grid.cache_configs() {
+                check_line_size(config.line_size, element_size).map_err(in_grid)?;
+                let capacity = config.line_number as usize * config.line_size as usize;
+                if has_scratchpad && capacity < 3 * element_size {
+                    return Err(invalid_data(format!(
+                        "实验 {}: 片上存储容量 {} 字节放不下 A、B、C 各一个元素",
+                        grid.name, capacity
+                    )));
+                }
+                Cache::<u32>::check(config.line_number, config.ways, &config.partition)
+                    .map_err(in_grid)?;
+                if config.sector_size == 0 || !config.line_size.is_multiple_of(config.sector_size) {
+                    return Err(invalid_data(format!(
+                        "实验 {}: 扇区大小 {} 字节不能整除行大小 {} 字节",
+                        grid.name, config.sector_size, config.line_size
+                    )));
+                }
+            }
+            if grid.page_sizes.is_empty() != grid.frame_counts.is_empty() {
+                return Err(invalid_data(format!(
+                    "实验 {} 的 page_sizes 与 frame_counts 必须同时给出",
+                    grid.name
+                )));
+            }
+            if grid.page_sizes.contains(&0) || grid.frame_counts.contains(&0) {
+                return Err(invalid_data(format!(
+                    "实验 {} 的页大小和页框数必须大于 0",
+                    grid.name
+                )));
+            }
+            if grid.policies.contains(&PagePolicy::LruK { k: 0 }) {
+                return Err(invalid_data(format!(
+                    "实验 {} 的 LRU-K 策略 k 必须大于 0",
+                    grid.name
+                )));
+            }
+        }
+        Ok(grids)
+    }
+
+    // Cache 参数的组合
+    fn cache_configs(&self) -> Vec<CacheConfig> {
+        let mut configs = Vec::new();
+        for &(line_number, line_size) in &self.caches {
+            // 大于行大小的扇区按整行处理，重复的组合只保留一个
+            let mut sector_sizes: Vec<u32> = if self.sector_sizes.is_empty() {
+                vec![line_size]
+            } else {
+                self.sector_sizes
+                    .iter()
+                    .map(|&s| u32::min(s, line_size))
+                    .collect()
+            };
+            sector_sizes.dedup();
+            for &ways in &self.ways {
+                for partition in &self.partitions {
+                    for &sector_size in &sector_sizes {
+                        configs.push(CacheConfig {
+                            line_number,
+                            line_size,
+                            ways,
+                            partition: partition.clone(),
+                            sector_size,
+                        });
+                    }
+                }
+            }
+        }
+        configs
+    }
+
+    // 缓冲池参数的组合，未配置缓冲池时只有一个 None
+    fn buffer_pools(&self) -> Vec<Option<(usize, usize, PagePolicy)>> {
+        if self.page_sizes.is_empty() {
+            return vec![None];
+        }
+        let mut pools = Vec::new();
+        for &page_size in &self.page_sizes {
+            for &frame_count in &self.frame_counts {
+                for policy in &self.policies {
+                    pools.push(Some((page_size, frame_count, policy.clone())));
+                }
+            }
+        }
+        pools
+    }
+}
+
+// 一组 Cache 参数
+struct CacheConfig {
+    line_number: u32,
+    line_size: u32,
+    ways: u32,
+    partition: CachePartition,
+    sector_size: u32,
+}
+
+impl CacheConfig {
+    fn build<T: Element>(&self) -> Cache<T> {
+        Cache::with_ways(
+            self.line_number,
+            self.line_size,
+            self.ways,
+            self.partition.clone(),
+        )
+        .with_sectors(self.sector_size)
+    }
+}
+
+fn combine_caches(
+    grid: &mut ExperimentGrid,
+    line_numbers: &mut Vec<u32>,
+    line_sizes: &mut Vec<u32>,
+) {
+    if grid.caches.is_empty() {
+        for &line_number in line_numbers.iter() {
+            for &line_size in line_sizes.iter() {
+                grid.caches.push((line_number, line_size));
+            }
+        }
+    }
+    line_numbers.clear();
+    line_sizes.clear();
+}
+
+fn parse_list<V: str::FromStr>(items: &[&str]) -> result::Result<Vec<V>, String> {
+    items
+        .iter()
+        .map(|item| item.parse().map_err(|_| format!("无效的数值: {}", item)))
+        .collect()
+}
+
+fn parse_with<V>(items: &[&str], parse: fn(&str) -> Option<V>) -> result::Result<Vec<V>, String> {
+    items
+        .iter()
+        .map(|item| parse(item).ok_or_else(|| format!("无效的取值: {}", item)))
+        .collect()
+}
+
+// 行数x行大小，例如 16x64
+pub(super) fn parse_cache(s: &str) -> Option<(u32, u32)> {
+    let (line_number, line_size) = s.split_once('x')?;
+    Some((line_number.parse().ok()?, line_size.parse().ok()?))
+}
+
+pub(super) fn parse_kernel(s: &str) -> Option<GridKernel> {
+    if let Some(sequence) = s.strip_prefix("spm_") {
+        return match parse_kernel(sequence)? {
+            GridKernel::Dense(sequence) => Some(GridKernel::Scratchpad(sequence)),
+            _ => None,
+        };
+    }
+    let sequences = [
+        Sequence::Sijk,
+        Sequence::Sikj,
+        Sequence::Sjik,
+        Sequence::Sjki,
+        Sequence::Skij,
+        Sequence::Skji,
+    ];
+    let kernels = [
+        SparseKernel::SpmvCsr,
+        SparseKernel::SpmvCsc,
+        SparseKernel::SpmvCoo,
+        SparseKernel::SpgemmCsr,
+    ];
+    // 循环顺序也可以省略前缀 S，例如 ikj
+    if let Some(sequence) = sequences.into_iter().find(|q| {
+        q.to_string().eq_ignore_ascii_case(s) || q.to_string()[1..].eq_ignore_ascii_case(s)
+    }) {
+        return Some(GridKernel::Dense(sequence));
+    }
+    kernels
+        .into_iter()
+        .find(|k| k.to_string().eq_ignore_ascii_case(s))
+        .map(GridKernel::Sparse)
+}
+
+// 与 RegisterModel::label 互逆
+pub(super) fn parse_register_model(s: &str) -> Option<RegisterModel> {
+    match s {
+        "off" => Some(RegisterModel::Off),
+        "scalar" => Some(RegisterModel::ScalarReplacement),
+        _ => {
+            let (rows, cols) = s.strip_prefix("tile")?.split_once('x')?;
+            Some(RegisterModel::RegisterTiling {
+                rows: rows.parse().ok()?,
+                cols: cols.parse().ok()?,
+            })
+        }
+    }
+}
+
+// 与 SparsePattern::label 互逆
+fn parse_pattern(s: &str) -> Option<SparsePattern> {
+    let parts: Vec<&str> = s.split('_').collect();
+    match parts.as_slice() {
+        ["random", density] => Some(SparsePattern::Random {
+            density: density.parse().ok()?,
+        }),
+        ["banded", bandwidth] => Some(SparsePattern::Banded {
+            bandwidth: bandwidth.parse().ok()?,
+        }),
+        ["powerlaw", avg, exponent] => Some(SparsePattern::PowerLaw {
+            avg_nnz_per_row: avg.parse().ok()?,
+            exponent: exponent.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+// 与 CachePartition::label 互逆
+fn parse_partition(s: &str) -> Option<CachePartition> {
+    if s == "shared" {
+        return Some(CachePartition::Shared);
+    }
+    let (kind, list) = s.split_once('_')?;
+    let list: Vec<u32> = list
+        .split('_')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    match kind {
+        "ways" => Some(CachePartition::Ways(list)),
+        "split" => Some(CachePartition::Split(list)),
+        _ => None,
+    }
+}
+
+/// 检查 dimension 阶、元素大小为 element_size 字节的矩阵能否放进每个矩阵的地址空间
+pub fn check_dimension(dimension: u32, element_size: usize) -> io::Result<()> {
+    if (dimension as usize).pow(2) * element_size > REGION_SPAN {
+        return Err(invalid_input(format!(
+            "{} 阶矩阵超出了每个矩阵的地址空间 REGION_SPAN（{} 字节）",
+            dimension, REGION_SPAN
+        )));
+    }
+    Ok(())
+}
+
+/// 检查 Cache 行大小：必须是 2 的幂，且不小于元素大小
+pub fn check_line_size(line_size: u32, element_size: usize) -> io::Result<()> {
+    if !line_size.is_power_of_two() || (line_size as usize) < element_size {
+        return Err(invalid_input(format!(
+            "行大小 {} 字节必须是 2 的幂，且不小于元素大小 {} 字节",
+            line_size, element_size
+        )));
+    }
+    Ok(())
+}
+
+// 与 PagePolicy::label 互逆
+fn parse_policy(s: &str) -> Option<PagePolicy> {
+    match s {
+        "lru" => Some(PagePolicy::Lru),
+        "clock" => Some(PagePolicy::Clock),
+        "2q" => Some(PagePolicy::TwoQ),
+        _ => Some(PagePolicy::LruK {
+            k: s.strip_prefix("lru")?.parse().ok()?,
+        }),
+    }
+}
+
+pub struct GridEvaluator;
+
+impl GridEvaluator {
+    /// 依次运行配置文件中的每个实验
+    pub fn evaluate_file(config_path: &str) -> io::Result<()> {
+        for grid in ExperimentGrid::from_file(config_path)? {
+            Self::evaluate(&grid)?;
+        }
+        Ok(())
+    }
+
+    pub fn evaluate(grid: &ExperimentGrid) -> io::Result<()> {
+        let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let file_path = format!("{}/data/project_1/{}", cargo_manifest_dir, grid.output);
+        if let Some(parent) = Path::new(&file_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(&file_path)?);
+        println!("> 开始评测实验 {}", grid.name);
+        Self::write_rows(grid, &mut writer)?;
+        writer.flush()?;
+        println!("> 实验 {} 的评测结果已保存到文件: {}", grid.name, file_path);
+        Ok(())
+    }
+
+    /// 写出表头和参数笛卡尔积中每个组合的一行
+    pub fn write_rows(grid: &ExperimentGrid, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", GridRow::HEADER)?;
+        for element in &grid.elements {
+            match element.as_str() {
+                "u32" => Self::evaluate_element::<u32>(grid, writer)?,
+                "i64" => Self::evaluate_element::<i64>(grid, writer)?,
+                "f32" => Self::evaluate_element::<f32>(grid, writer)?,
+                _ => Self::evaluate_element::<f64>(grid, writer)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate_element<T: Element>(
+        grid: &ExperimentGrid,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        let suffix = if T::NAME != u32::NAME {
+            format!("_{}", T::NAME)
+        } else {
+            String::new()
+        };
+        let has_dense = grid
+            .kernels
+            .iter()
+            .any(|k| matches!(k, GridKernel::Dense(_) | GridKernel::Scratchpad(_)));
+        let has_sparse = grid
+            .kernels
+            .iter()
+            .any(|k| matches!(k, GridKernel::Sparse(_)));
+        let mut rng = rand::rng();
+
+        for &dimension in &grid.dimensions {
+            // 同一维度的所有组合使用同一组随机数据
+            let dense = has_dense.then(|| {
+                (
+                    Matrix::<T>::new(
+                        0,
+                        dimension,
+                        &format!("origin_data/matrix_a_{}{}.txt", dimension, suffix),
+                    ),
+                    Matrix::<T>::new(
+                        1,
+                        dimension,
+                        &format!("origin_data/matrix_b_{}{}.txt", dimension, suffix),
+                    ),
+                )
+            });
+            let sparse: Vec<_> = if has_sparse {
+                grid.patterns
+                    .iter()
+                    .map(|pattern| {
+                        let coo_a = CooMatrix::<T>::generate(dimension, dimension, pattern);
+                        let coo_b = CooMatrix::<T>::generate(dimension, dimension, pattern);
+                        let x: Vec<T> = (0..dimension).map(|_| T::random(&mut rng)).collect();
+                        (pattern, coo_a, coo_b, x)
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            for config in grid.cache_configs() {
+                let new_cache = || config.build::<T>();
+                for kernel in &grid.kernels {
+                    for (pool_index, pool) in grid.buffer_pools().into_iter().enumerate() {
+                        let buffer_pool = pool.as_ref().map(|(size, frames, policy)| {
+                            BufferPool::new(*size, *frames, policy.clone())
+                        });
+                        let mut row = GridRow {
+                            grid,
+                            element: T::NAME,
+                            dimension,
+                            cache: &config,
+                            kernel,
+                            register_model: String::new(),
+                            sparse: None,
+                            pool: &pool,
+                            cache_access: 0,
+                            cache_miss: 0,
+                            bytes_fetched: 0,
+                            utilisation: 0.0,
+                            matrix_miss: None,
+                            page_counts: None,
+                            dma: None,
+                        };
+                        match kernel {
+                            GridKernel::Dense(sequence) => {
+                                let (matrix_a, matrix_b) = dense.as_ref().unwrap();
+                                for register_model in &grid.register_models {
+                                    let mut calculator = Calculator::new(
+                                        matrix_a.clone(),
+                                        matrix_b.clone(),
+                                        new_cache(),
+                                        &format!(
+                                            "origin_data/matrix_c_{}{}.txt",
+                                            dimension, suffix
+                                        ),
+                                    );
+                                    calculator.register_model = register_model.clone();
+                                    calculator.buffer_pool = buffer_pool.clone();
+                                    calculator.calculate(sequence.clone());
+                                    row.register_model = register_model.label();
+                                    row.cache_access = calculator.cache_access;
+                                    row.cache_miss = calculator.cache_miss;
+                                    row.bytes_fetched = calculator.cache.bytes_fetched;
+                                    row.utilisation = calculator.cache.utilisation().average();
+                                    row.matrix_miss = Some(calculator.matrix_miss);
+                                    row.page_counts = calculator
+                                        .buffer_pool
+                                        .as_ref()
+                                        .map(|p| (p.page_hits, p.page_faults));
+                                    row.write(writer)?;
+                                }
+                            }
+                            // 片上存储不经过缓冲池，每个 Cache 组合只写一行
+                            GridKernel::Scratchpad(sequence) if pool_index == 0 => {
+                                let (matrix_a, matrix_b) = dense.as_ref().unwrap();
+                                let capacity =
+                                    config.line_number as usize * config.line_size as usize;
+                                let mut spm = ScratchpadCalculator::new(
+                                    matrix_a.clone(),
+                                    matrix_b.clone(),
+                                    capacity,
+                                )?;
+                                spm.calculate(sequence.clone())?;
+                                let no_pool = None;
+                                row.pool = &no_pool;
+                                row.bytes_fetched = spm.scratchpad.bytes_in;
+                                row.dma = Some((spm.scratchpad.dma_ops, spm.scratchpad.bytes_out));
+                                row.write(writer)?;
+                            }
+                            GridKernel::Scratchpad(_) => {}
+                            GridKernel::Sparse(sparse_kernel) => {
+                                for (pattern, coo_a, coo_b, x) in &sparse {
+                                    let mut calculator = SparseCalculator::new(new_cache());
+                                    calculator.buffer_pool = buffer_pool.clone();
+                                    match sparse_kernel {
+                                        SparseKernel::SpmvCsr => {
+                                            calculator.spmv_csr(&CsrMatrix::from_coo(coo_a), x);
+                                        }
+                                        SparseKernel::SpmvCsc => {
+                                            calculator.spmv_csc(&CscMatrix::from_coo(coo_a), x);
+                                        }
+                                        SparseKernel::SpmvCoo => {
+                                            calculator.spmv_coo(coo_a, x);
+                                        }
+                                        SparseKernel::SpgemmCsr => {
+                                            calculator.spgemm_csr(
+                                                &CsrMatrix::from_coo(coo_a),
+                                                &CsrMatrix::from_coo(coo_b),
+                                            );
+                                        }
+                                    }
+                                    row.sparse = Some((pattern, coo_a.nnz()));
+                                    row.cache_access = calculator.cache_access;
+                                    row.cache_miss = calculator.cache_miss;
+                                    row.bytes_fetched = calculator.cache.bytes_fetched;
+                                    row.utilisation = calculator.cache.utilisation().average();
+                                    row.page_counts = calculator
+                                        .buffer_pool
+                                        .as_ref()
+                                        .map(|p| (p.page_hits, p.page_faults));
+                                    row.write(writer)?;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// 结果文件中的一行，不适用的参数留空
+struct GridRow<'a> {
+    grid: &'a ExperimentGrid,
+    element: &'a str,
+    dimension: u32,
+    cache: &'a CacheConfig,
+    kernel: &'a GridKernel,
+    register_model: String,
+    sparse: Option<(&'a SparsePattern, usize)>,
+    pool: &'a Option<(usize, usize, PagePolicy)>,
+    cache_access: u32,
+    cache_miss: u32,
+    bytes_fetched: u64,
+    /// 平均行利用率
+    utilisation: f64,
+    matrix_miss: Option<[u32; 3]>,
+    page_counts: Option<(u64, u64)>,
+    /// 片上存储的 (DMA 次数, 写回字节数)；此时 bytes_fetched 为调入的字节数，Cache 计数留空
+    dma: Option<(u64, u64)>,
+}
+
+impl GridRow<'_> {
+    const HEADER: &'static str = "experiment,element,dimension,cache_line_number,cache_line_size,ways,partition,sector_size,kernel,register_model,pattern,nnz,page_size,frame_count,policy,cache_access,cache_miss,miss_a,miss_b,miss_c,bytes_fetched,line_utilisation,page_hits,page_faults,dma_ops,dma_bytes_out";
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let cache = self.cache;
+        let (pattern, nnz) = match self.sparse {
+            Some((pattern, nnz)) => (pattern.label(), nnz.to_string()),
+            None => (String::new(), String::new()),
+        };
+        let (page_size, frame_count, policy) = match self.pool {
+            Some((size, frames, policy)) => (size.to_string(), frames.to_string(), policy.label()),
+            None => (String::new(), String::new(), String::new()),
+        };
+        let matrix_miss = match self.matrix_miss {
+            Some([a, b, c]) => format!("{},{},{}", a, b, c),
+            None => ",,".to_string(),
+        };
+        let (page_hits, page_faults) = match self.page_counts {
+            Some((hits, faults)) => (hits.to_string(), faults.to_string()),
+            None => (String::new(), String::new()),
+        };
+        let (cache_access, cache_miss, utilisation, dma_ops, dma_bytes_out) = match self.dma {
+            Some((ops, bytes_out)) => (
+                String::new(),
+                String::new(),
+                String::new(),
+                ops.to_string(),
+                bytes_out.to_string(),
+            ),
+            None => (
+                self.cache_access.to_string(),
+                self.cache_miss.to_string(),
+                format!("{:.4}", self.utilisation),
+                String::new(),
+                String::new(),
+            ),
+        };
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.grid.name,
+            self.element,
+            self.dimension,
+            cache.line_number,
+            cache.line_size,
+            cache.ways,
+            cache.partition.label(),
+            cache.sector_size,
+            self.kernel.label(),
+            self.register_model,
+            pattern,
+            nnz,
+            page_size,
+            frame_count,
+            policy,
+            cache_access,
+            cache_miss,
+            matrix_miss,
+            self.bytes_fetched,
+            utilisation,
+            page_hits,
+            page_faults,
+            dma_ops,
+            dma_bytes_out
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::HashSet;
+
+    #[test]
+    fn expands_the_cartesian_product() {
+        // 稀疏内核不读写矩阵文件；8x8 的两个扇区大小都截为整行，只保留一个
+        let grids = ExperimentGrid::parse(
+            "[sparse]\n\
+             elements = u32, f64\n\
+             dimensions = 4, 6\n\
+             caches = 4x16, 8x8\n\
+             ways = 1, 2\n\
+             sector_sizes = 8, 16\n\
+             kernels = SpmvCsr, SpmvCoo\n\
+             patterns = random_0.5, banded_1\n\
+             page_sizes = 64\n\
+             frame_counts = 2, 4\n\
+             policies = lru, 2q\n",
+        )
+        .unwrap();
+        let grid = &grids[0];
+        assert_eq!(grid.cache_configs().len(), 2 * 2 + 2);
+        assert_eq!(grid.buffer_pools().len(), 4);
+
+        let mut output = Vec::new();
+        GridEvaluator::write_rows(grid, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        for column in [
+            "element",
+            "dimension",
+            "cache_line_number",
+            "cache_line_size",
+            "ways",
+            "sector_size",
+            "kernel",
+            "pattern",
+            "page_size",
+            "frame_count",
+            "policy",
+        ] {
+            assert!(header.contains(&column), "缺少列 {}", column);
+        }
+        let parameter = |row: &[&str], name: &str| -> String {
+            row[header.iter().position(|&c| c == name).unwrap()].to_string()
+        };
+
+        let rows: Vec<Vec<&str>> = lines.map(|l| l.split(',').collect()).collect();
+        // 元素类型 × 维度 × Cache 组合 × 内核 × 缓冲池组合 × 稀疏模式
+        assert_eq!(rows.len(), 2 * 2 * 6 * 2 * 4 * 2);
+        let mut combinations = HashSet::new();
+        for row in &rows {
+            assert_eq!(row.len(), header.len());
+            // 寄存器模型只对稠密内核有效
+            assert_eq!(parameter(row, "register_model"), "");
+            combinations.insert(
+                header[1..15]
+                    .iter()
+                    .map(|c| parameter(row, c))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(combinations.len(), rows.len());
+    }
+
+    #[test]
+    fn parses_scratchpad_kernels() {
+        let grids = ExperimentGrid::parse(
+            "[spm]\ndimensions = 4\ncaches = 4x16\nkernels = Sikj, spm_Sikj, spm_kji\n",
+        )
+        .unwrap();
+        let labels: Vec<String> = grids[0].kernels.iter().map(GridKernel::label).collect();
+        assert_eq!(labels, ["Sikj", "spm_Sikj", "spm_Skji"]);
+        assert!(parse_kernel("spm_SpmvCsr").is_none());
+    }
+
+    #[test]
+    fn rejects_unusable_parameters() {
+        for extra in [
+            "page_sizes = 0\nframe_counts = 4\n",
+            "page_sizes = 64\nframe_counts = 0\n",
+            "page_sizes = 64\nframe_counts = 4\npolicies = lru0\n",
+            // 片上存储容量 1×8 字节放不下三个 u32
+            "caches = 1x8\nkernels = spm_Sijk\n",
+        ] {
+            let config = format!(
+                "[bad]\ndimensions = 4\ncaches = 4x16\nkernels = SpmvCsr\n{}",
+                extra
+            );
+            let err = ExperimentGrid::parse(&config).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", extra);
+        }
+    }
+}
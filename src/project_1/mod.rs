@@ -4,6 +4,26 @@ use io::*;
 use rand::Rng;
 use std::*;
 
+mod buffer_pool;
+mod element;
+mod format;
+mod grid;
+mod out_of_core;
+mod scratchpad;
+mod sparse;
+mod trace;
+pub use buffer_pool::*;
+pub use element::*;
+pub use format::*;
+pub use grid::*;
+pub use out_of_core::*;
+pub use scratchpad::*;
+pub use sparse::*;
+pub use trace::*;
+
+/// `Cache::touch` 中每个存储区占用的地址空间（字节）
+pub const REGION_SPAN: usize = 1 << 26;
+
 #[derive(Clone, Debug)]
 pub enum Sequence {
     Sijk,
@@ -25,44 +45,191 @@ impl Sequence {
             Sequence::Skji => "Skji",
         }
     }
+
+    // 三层循环从外到内的循环变量
+    fn loop_order(&self) -> [Loop; 3] {
+        match self {
+            Sequence::Sijk => [Loop::I, Loop::J, Loop::K],
+            Sequence::Sikj => [Loop::I, Loop::K, Loop::J],
+            Sequence::Sjik => [Loop::J, Loop::I, Loop::K],
+            Sequence::Sjki => [Loop::J, Loop::K, Loop::I],
+            Sequence::Skij => [Loop::K, Loop::I, Loop::J],
+            Sequence::Skji => [Loop::K, Loop::J, Loop::I],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Loop {
+    I,
+    J,
+    K,
+}
+
+/// 寄存器层面的访存模型
+#[derive(Clone, Debug)]
+pub enum RegisterModel {
+    /// 不做寄存器优化：每次迭代都经过 Cache 读取 A、B、C 并写回 C，共 4n³ 次访问
+    Off,
+    /// 标量替换：最内层循环不变的操作数只读写一次，其余时间保存在寄存器中
+    ScalarReplacement,
+    /// 寄存器分块：i 方向 rows 个、j 方向 cols 个元素组成的微内核，
+    /// 最内层循环不变的那一块（A 的列片段、B 的行片段或 C 的 rows×cols 块）驻留寄存器
+    RegisterTiling { rows: u32, cols: u32 },
+}
+
+impl RegisterModel {
+    pub fn label(&self) -> String {
+        match self {
+            RegisterModel::Off => "off".to_string(),
+            RegisterModel::ScalarReplacement => "scalar".to_string(),
+            RegisterModel::RegisterTiling { rows, cols } => format!("tile{}x{}", rows, cols),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Address {
     pub tag: u32,
+    /// 组号，直接映射时即行号
     pub index: u32,
+    /// 行内的字节偏移
     pub offset: u32,
 }
 
 #[derive(Clone, Debug)]
-pub struct CacheLine {
+pub struct CacheLine<T: Element = u32> {
+    /// 行大小（字节），必须是 2 的幂且不小于元素大小
     pub cache_line_size: u32,
     pub valid: bool,
     pub tag: u32,
-    pub data: Vec<u32>,
+    pub data: Vec<T>,
+    /// 最近一次访问的时间，组相联时按 LRU 替换
+    pub last_used: u64,
+    /// 每个扇区的有效位，不分扇区时只有一个
+    pub sector_valid: Vec<bool>,
+    /// 调入以来被访问过的字节，用于统计行利用率
+    pub touched: Vec<bool>,
+}
+
+/// 行利用率直方图的桶数
+pub const UTILISATION_BINS: usize = 10;
+
+/// 行利用率：每个调入 Cache 的行在被替换前（或计算结束时）访问过的字节所占比例
+#[derive(Clone, Debug, Default)]
+pub struct LineUtilisation {
+    /// 第 b 个桶统计利用率落在 (b/10, (b+1)/10] 的行数
+    pub histogram: [u64; UTILISATION_BINS],
+    pub lines: u64,
+    pub fraction_sum: f64,
+}
+
+impl LineUtilisation {
+    fn record(&mut self, fraction: f64) {
+        let bin = ((fraction * UTILISATION_BINS as f64).ceil() as usize).clamp(1, UTILISATION_BINS);
+        self.histogram[bin - 1] += 1;
+        self.lines += 1;
+        self.fraction_sum += fraction;
+    }
+
+    pub fn average(&self) -> f64 {
+        if self.lines == 0 {
+            0.0
+        } else {
+            self.fraction_sum / self.lines as f64
+        }
+    }
+}
+
+/// Cache 的划分方式。划分的归属者默认是存储区，稠密乘法中即矩阵编号（A=0, B=1, C=2）；
+/// 模拟多核时可以用 `Cache::lookup_as` 把核号作为归属者。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CachePartition {
+    /// 所有归属者共享全部的行
+    Shared,
+    /// 路划分：第 i 个归属者独占每组中的 ways[i] 路，未列出的归属者共享剩余的路
+    Ways(Vec<u32>),
+    /// 分离 Cache：第 i 个归属者使用独立的 lines[i] 行（同样按 ways 路组相联），
+    /// 未列出的归属者共享剩余的行
+    Split(Vec<u32>),
+}
+
+impl CachePartition {
+    pub fn label(&self) -> String {
+        let join = |list: &Vec<u32>| {
+            list.iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join("_")
+        };
+        match self {
+            CachePartition::Shared => "shared".to_string(),
+            CachePartition::Ways(ways) => format!("ways_{}", join(ways)),
+            CachePartition::Split(lines) => format!("split_{}", join(lines)),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
-pub struct Cache {
+pub struct Cache<T: Element = u32> {
     pub line_number: u32,
-    pub lines: Vec<CacheLine>,
+    /// 相联度，1 为直接映射
+    pub ways: u32,
+    pub partition: CachePartition,
+    /// 第 s 组的第 w 路为 lines[base + s * ways + w]，base 为分离 Cache 中该归属者的起始行
+    pub lines: Vec<CacheLine<T>>,
+    /// 扇区大小（字节），等于行大小时不分扇区
+    pub sector_size: u32,
+    /// 标签命中但扇区无效的次数（也计入未命中）
+    pub sector_misses: u64,
+    /// 从下一级存储调入的字节数
+    pub bytes_fetched: u64,
+    time: u64,
+    // 已被替换的行的利用率
+    evicted_utilisation: LineUtilisation,
+}
+
+/// 一次 Cache 查找的结果
+#[derive(Clone, Debug)]
+pub struct Lookup {
+    pub address: Address,
+    /// 命中或被填入的行
+    pub line: usize,
+    pub hit: bool,
+    /// 未命中时被替换掉的有效行的标签
+    pub evicted: Option<u32>,
+}
+
+// 某个归属者可以使用的行：从 base 开始的 sets 组，每组中的 ways.0..ways.1 路
+struct Segment {
+    base: usize,
+    sets: usize,
+    ways: (usize, usize),
 }
 
 #[derive(Clone, Debug)]
-pub struct Matrix {
+pub struct Matrix<T: Element = u32> {
     pub id: u32,
     pub dimension: u32,
     pub file_path: String,
-    pub data: Vec<Vec<u32>>,
+    pub data: Vec<Vec<T>>,
 }
 
 #[derive(Clone, Debug)]
-pub struct Calculator {
-    pub matrix_a: Matrix,
-    pub matrix_b: Matrix,
-    pub matrix_c: Matrix,
-    pub cache: Cache,
+pub struct Calculator<T: Element = u32> {
+    pub matrix_a: Matrix<T>,
+    pub matrix_b: Matrix<T>,
+    pub matrix_c: Matrix<T>,
+    pub cache: Cache<T>,
     pub cache_miss: u32,
+    pub cache_access: u32,
+    /// A、B、C 各自的未命中次数
+    pub matrix_miss: [u32; 3],
+    pub register_model: RegisterModel,
+    /// 可选的磁盘页缓冲池，Cache 未命中时经过它读取整行
+    pub buffer_pool: Option<BufferPool>,
+    /// 不为 None 时记录每次访问的轨迹，见 `CacheTracer`
+    pub trace: Option<Vec<TraceEvent<T>>>,
 }
 
 pub struct Evaluator;
@@ -72,10 +239,11 @@ pub struct EvalResult {
     pub cache_line_size: u32,
     pub cache_line_number: u32,
     pub cache_miss: u32,
+    pub cache_access: u32,
 }
 
-impl Matrix {
-    pub fn new(id: u32, dimension: u32, file_path: &str) -> Matrix {
+impl<T: Element> Matrix<T> {
+    pub fn new(id: u32, dimension: u32, file_path: &str) -> Matrix<T> {
         let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         let file_path = format!("{}/data/project_1/{}", cargo_manifest_dir, file_path);
         let file_path_str = file_path.clone(); // 修复：提前 clone 一份
@@ -83,22 +251,12 @@ impl Matrix {
         let mut rng = rand::rng();
         println!("> 开始生成随机矩阵...");
         for _ in 0..dimension {
-            let row: Vec<u32> = (0..dimension).map(|_| rng.random_range(0..100)).collect();
+            let row: Vec<T> = (0..dimension).map(|_| T::random(&mut rng)).collect();
             data.push(row);
         }
 
-        // 将矩阵写入文件
-        let file = File::create(file_path).expect("无法创建文件");
-        let mut writer = BufWriter::new(file);
-        for row in &data {
-            let line = row
-                .iter()
-                .map(|v| v.to_string())
-                .collect::<Vec<String>>()
-                .join(" ");
-            writeln!(writer, "{}", line).expect("无法写入文件");
-        }
-        writer.flush().expect("无法刷新缓冲区");
+        // 将矩阵写入文件，格式由扩展名决定
+        write_dense(&file_path, &data, MatrixFormat::from_path(&file_path)).expect("无法写入文件");
 
         println!("> 随机矩阵生成完毕，已保存到文件: {}", file_path_str);
 
@@ -113,90 +271,376 @@ impl Matrix {
         }
     }
 
-    pub fn from_file(id: u32, file_path: &str) -> Matrix {
+    /// 从文件读取矩阵，格式由扩展名决定（`.bin` 二进制，`.mtx` Matrix Market，其余为文本）
+    pub fn from_file(id: u32, file_path: &str) -> io::Result<Matrix<T>> {
         let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         let file_path = format!("{}/data/project_1/{}", cargo_manifest_dir, file_path);
-        let file = File::open(file_path.clone()).expect("无法打开文件");
-        let reader = BufReader::new(file);
-        let mut data = Vec::new();
-        for line in reader.lines() {
-            let line = line.expect("无法读取行");
-            let row: Vec<u32> = line
-                .split_whitespace()
-                .map(|v| v.parse().expect("无法解析数字"))
-                .collect();
-            data.push(row);
-        }
+        let data = read_dense(&file_path)?;
+        check_square(&data, &file_path)?;
         let dimension = data.len() as u32;
-        Matrix {
+        Ok(Matrix {
             id,
             dimension,
-            file_path: file_path.to_string(),
+            file_path,
             data,
-        }
+        })
     }
 
-    pub fn data_to_file(&self) {
-        let file = File::create(self.file_path.clone()).expect("无法创建文件");
-        let mut writer = BufWriter::new(file);
-        for row in &self.data {
-            let line = row
-                .iter()
-                .map(|v| v.to_string())
-                .collect::<Vec<String>>()
-                .join(" ");
-            writeln!(writer, "{}", line).expect("无法写入文件");
-        }
-        writer.flush().expect("无法刷新缓冲区");
-        println!("> 矩阵已保存到文件: {}", self.file_path);
-    }
-
-    pub fn file_to_data(&mut self) {
-        let file = File::open(&self.file_path).expect("无法打开文件");
-        let reader = BufReader::new(file);
-        self.data.clear();
-        for line in reader.lines() {
-            let line = line.expect("无法读取行");
-            let row: Vec<u32> = line
-                .split_whitespace()
-                .map(|v| v.parse().expect("无法解析数字"))
-                .collect();
-            self.data.push(row);
-        }
+    pub fn data_to_file(&self) -> io::Result<()> {
+        self.to_file(&self.file_path, MatrixFormat::from_path(&self.file_path))
     }
+
+    /// 以指定格式导出到 path（不做 data/project_1 前缀处理）
+    pub fn to_file(&self, path: &str, format: MatrixFormat) -> io::Result<()> {
+        write_dense(path, &self.data, format)?;
+        println!("> 矩阵已保存到文件: {}", path);
+        Ok(())
+    }
+
+    pub fn file_to_data(&mut self) -> io::Result<()> {
+        let data = read_dense(&self.file_path)?;
+        check_square(&data, &self.file_path)?;
+        self.dimension = data.len() as u32;
+        self.data = data;
+        Ok(())
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// Calculator 只处理方阵，读入后检查每一行的长度
+fn check_square<T>(data: &[Vec<T>], file_path: &str) -> io::Result<()> {
+    if let Some(row) = data.iter().position(|row| row.len() != data.len()) {
+        return Err(invalid_data(format!(
+            "{} 不是方阵：共 {} 行，第 {} 行有 {} 个元素",
+            file_path,
+            data.len(),
+            row + 1,
+            data[row].len()
+        )));
+    }
+    Ok(())
 }
 
-impl CacheLine {
-    pub fn new(cache_line_size: u32) -> CacheLine {
+impl<T: Element> CacheLine<T> {
+    pub fn new(cache_line_size: u32) -> CacheLine<T> {
+        // 行大小为 2 的幂时，各存储区的起始地址（REGION_SPAN 的整数倍）都与行对齐
+        if !cache_line_size.is_power_of_two() || (cache_line_size as usize) < T::SIZE {
+            panic!(
+                "Cache 行大小 {} 字节必须是 2 的幂，且不小于 {} 元素大小 {} 字节",
+                cache_line_size,
+                T::NAME,
+                T::SIZE
+            );
+        }
         CacheLine {
             cache_line_size,
             valid: false,
             tag: 0,
-            data: vec![0; cache_line_size as usize],
+            data: vec![T::default(); cache_line_size as usize / T::SIZE],
+            last_used: 0,
+            sector_valid: vec![false],
+            touched: vec![false; cache_line_size as usize],
         }
     }
 }
 
-impl Cache {
-    pub fn new(line_number: u32, cache_line_size: u32) -> Cache {
+impl<T: Element> Cache<T> {
+    /// 直接映射 Cache，cache_line_size 以字节为单位
+    pub fn new(line_number: u32, cache_line_size: u32) -> Cache<T> {
+        Self::with_ways(line_number, cache_line_size, 1, CachePartition::Shared)
+    }
+
+    /// ways 路组相联 Cache，组内按 LRU 替换
+    pub fn with_ways(
+        line_number: u32,
+        cache_line_size: u32,
+        ways: u32,
+        partition: CachePartition,
+    ) -> Cache<T> {
+        if let Err(err) = Self::check(line_number, ways, &partition) {
+            panic!("{}", err);
+        }
         let mut lines = Vec::with_capacity(line_number as usize);
         for _ in 0..line_number {
             lines.push(CacheLine::new(cache_line_size));
         }
-        Cache { line_number, lines }
+        Cache {
+            line_number,
+            ways,
+            partition,
+            lines,
+            sector_size: cache_line_size,
+            sector_misses: 0,
+            bytes_fetched: 0,
+            time: 0,
+            evicted_utilisation: LineUtilisation::default(),
+        }
+    }
+
+    /// 把每行分成若干 sector_size 字节的扇区，未命中时只调入被访问的扇区
+    pub fn with_sectors(mut self, sector_size: u32) -> Cache<T> {
+        let cache_line_size = self.lines[0].cache_line_size;
+        if sector_size == 0 || !cache_line_size.is_multiple_of(sector_size) {
+            panic!(
+                "扇区大小 {} 字节不能整除行大小 {} 字节",
+                sector_size, cache_line_size
+            );
+        }
+        self.sector_size = sector_size;
+        for line in &mut self.lines {
+            line.sector_valid = vec![false; (cache_line_size / sector_size) as usize];
+        }
+        self
+    }
+
+    /// 已被替换的行与当前驻留的行合在一起的利用率统计
+    pub fn utilisation(&self) -> LineUtilisation {
+        let mut utilisation = self.evicted_utilisation.clone();
+        for line in self.lines.iter().filter(|l| l.valid) {
+            utilisation.record(Self::touched_fraction(line));
+        }
+        utilisation
+    }
+
+    fn touched_fraction(line: &CacheLine<T>) -> f64 {
+        line.touched.iter().filter(|&&t| t).count() as f64 / line.touched.len() as f64
+    }
+
+    /// 检查行数、相联度与划分方式是否相容
+    pub fn check(line_number: u32, ways: u32, partition: &CachePartition) -> io::Result<()> {
+        if line_number == 0 || ways == 0 || ways > line_number {
+            return Err(invalid_input(format!(
+                "Cache 行数 {} 与相联度 {} 都必须大于 0，且相联度不能超过行数",
+                line_number, ways
+            )));
+        }
+        if !line_number.is_multiple_of(ways) {
+            return Err(invalid_input(format!(
+                "Cache 行数 {} 不是相联度 {} 的整数倍",
+                line_number, ways
+            )));
+        }
+        match partition {
+            CachePartition::Shared => {}
+            CachePartition::Ways(list) => {
+                if list.iter().sum::<u32>() > ways || list.contains(&0) {
+                    return Err(invalid_input(format!(
+                        "路划分 {:?} 与相联度 {} 不符",
+                        list, ways
+                    )));
+                }
+            }
+            CachePartition::Split(list) => {
+                if list.iter().sum::<u32>() > line_number
+                    || list.iter().any(|&n| n == 0 || !n.is_multiple_of(ways))
+                {
+                    return Err(invalid_input(format!(
+                        "分离 Cache 的行数 {:?} 与总行数 {}、相联度 {} 不符",
+                        list, line_number, ways
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn segment(&self, owner: u32) -> Segment {
+        let ways = self.ways as usize;
+        // 列表中第 owner 项对应的区间，未列出的归属者使用剩余部分
+        let range = |list: &Vec<u32>, total: usize| {
+            let owner = usize::min(owner as usize, list.len());
+            let lo = list[..owner].iter().sum::<u32>() as usize;
+            let hi = list.get(owner).map_or(total, |&n| lo + n as usize);
+            if lo == hi {
+                panic!(
+                    "归属者 {} 没有可用的 Cache 行（划分 {}）",
+                    owner,
+                    self.partition.label()
+                );
+            }
+            (lo, hi)
+        };
+        match &self.partition {
+            CachePartition::Shared => Segment {
+                base: 0,
+                sets: self.line_number as usize / ways,
+                ways: (0, ways),
+            },
+            CachePartition::Ways(list) => Segment {
+                base: 0,
+                sets: self.line_number as usize / ways,
+                ways: range(list, ways),
+            },
+            CachePartition::Split(list) => {
+                let (lo, hi) = range(list, self.line_number as usize);
+                Segment {
+                    base: lo,
+                    sets: (hi - lo) / ways,
+                    ways: (0, ways),
+                }
+            }
+        }
+    }
+
+    /// 把第 region 个存储区中的字节地址 address 拆分为标签、组号和行内偏移
+    pub fn locate(&self, owner: u32, region: u32, address: usize) -> Address {
+        let cache_line_size = self.lines[0].cache_line_size as usize;
+        let sets = self.segment(owner).sets;
+        if address >= REGION_SPAN {
+            panic!(
+                "存储区 {} 中的地址 {} 超出了 REGION_SPAN（{} 字节）",
+                region, address, REGION_SPAN
+            );
+        }
+        let address = region as usize * REGION_SPAN + address;
+        let block = address / cache_line_size;
+        Address {
+            tag: (block / sets) as u32,
+            index: (block % sets) as u32,
+            offset: (address % cache_line_size) as u32,
+        }
+    }
+
+    /// 查找并在未命中时分配一行（只更新有效位和标签，数据由调用者填入），
+    /// 按访问一个元素计算行利用率
+    pub fn lookup(&mut self, region: u32, address: usize) -> Lookup {
+        self.lookup_as(region, region, address, T::SIZE)
+    }
+
+    /// 以 owner 的身份查找 [address, address + len)，只能使用 owner 分得的路或行
+    pub fn lookup_as(&mut self, owner: u32, region: u32, address: usize, len: usize) -> Lookup {
+        let segment = self.segment(owner);
+        let address = self.locate(owner, region, address);
+        let first = segment.base + address.index as usize * self.ways as usize;
+        let candidates = first + segment.ways.0..first + segment.ways.1;
+        let sector = (address.offset / self.sector_size) as usize;
+        self.time += 1;
+
+        let (line, hit, evicted) = match candidates
+            .clone()
+            .find(|&l| self.lines[l].valid && self.lines[l].tag == address.tag)
+        {
+            Some(line) => {
+                // 标签命中时还要检查扇区，扇区无效只调入该扇区
+                let hit = self.lines[line].sector_valid[sector];
+                if !hit {
+                    self.sector_misses += 1;
+                }
+                (line, hit, None)
+            }
+            None => {
+                // 未命中：优先填入无效行，否则替换组内最久未使用的行
+                let line = candidates
+                    .clone()
+                    .find(|&l| !self.lines[l].valid)
+                    .unwrap_or_else(|| {
+                        candidates.min_by_key(|&l| self.lines[l].last_used).unwrap()
+                    });
+                let target = &self.lines[line];
+                let evicted = target.valid.then_some(target.tag);
+                if target.valid {
+                    self.evicted_utilisation
+                        .record(Self::touched_fraction(target));
+                }
+                let target = &mut self.lines[line];
+                target.valid = true;
+                target.tag = address.tag;
+                target.sector_valid.fill(false);
+                target.touched.fill(false);
+                (line, false, evicted)
+            }
+        };
+
+        let target = &mut self.lines[line];
+        target.last_used = self.time;
+        if !hit {
+            target.sector_valid[sector] = true;
+            self.bytes_fetched += self.sector_size as u64;
+        }
+        let offset = address.offset as usize;
+        let end = usize::min(offset + len, target.touched.len());
+        target.touched[offset..end].fill(true);
+        Lookup {
+            address,
+            line,
+            hit,
+            evicted,
+        }
+    }
+
+    /// 地址所在的块当前在哪一行，不更新替换信息
+    pub fn find(&self, region: u32, address: usize) -> Option<usize> {
+        let segment = self.segment(region);
+        let address = self.locate(region, region, address);
+        let first = segment.base + address.index as usize * self.ways as usize;
+        (first + segment.ways.0..first + segment.ways.1)
+            .find(|&l| self.lines[l].valid && self.lines[l].tag == address.tag)
+    }
+
+    /// 访问第 region 个存储区中字节地址为 address 的数据，只记录命中与否，不搬运数据。
+    /// 各存储区相隔 REGION_SPAN 字节，互不重叠。返回是否命中。
+    pub fn touch(&mut self, region: u32, address: usize) -> bool {
+        self.lookup(region, address).hit
+    }
+
+    /// 与 `touch` 相同，但访问的数据长度为 len 字节（用于元素类型以外的下标数组）
+    pub fn touch_bytes(&mut self, region: u32, address: usize, len: usize) -> bool {
+        self.lookup_as(region, region, address, len).hit
+    }
+
+    /// 第 line 行保存标签为 tag 的块时，该块在全局地址空间中的起始字节地址
+    pub fn line_address(&self, line: usize, tag: u32) -> usize {
+        let ways = self.ways as usize;
+        let (base, sets) = match &self.partition {
+            CachePartition::Split(list) => {
+                let mut base = 0;
+                let mut sets =
+                    (self.line_number as usize - list.iter().sum::<u32>() as usize) / ways;
+                for &n in list {
+                    if line < base + n as usize {
+                        sets = n as usize / ways;
+                        break;
+                    }
+                    base += n as usize;
+                }
+                (base, sets)
+            }
+            _ => (0, self.line_number as usize / ways),
+        };
+        let set = (line - base) / ways;
+        (tag as usize * sets + set) * self.lines[0].cache_line_size as usize
     }
 }
 
-impl Calculator {
-    pub fn new(matrix_a: Matrix, matrix_b: Matrix, cache: Cache, c_file_path: &str) -> Calculator {
+impl<T: Element> Calculator<T> {
+    pub fn new(
+        matrix_a: Matrix<T>,
+        matrix_b: Matrix<T>,
+        cache: Cache<T>,
+        c_file_path: &str,
+    ) -> Calculator<T> {
+        let dimension = matrix_a.dimension;
+        // 每个矩阵独占一个存储区，放不下时与下一个矩阵的地址重叠
+        let bytes = (dimension as usize).pow(2) * T::SIZE;
+        if bytes > REGION_SPAN {
+            panic!(
+                "{} 阶 {} 矩阵占 {} 字节，超出了每个矩阵的地址空间 REGION_SPAN（{} 字节）",
+                dimension,
+                T::NAME,
+                bytes,
+                REGION_SPAN
+            );
+        }
         let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         let c_file_path = format!("{}/data/project_1/{}", cargo_manifest_dir, c_file_path);
-        let dimension = matrix_a.dimension;
         let matrix_c = Matrix {
             id: 2,
             dimension,
             file_path: c_file_path.to_string(),
-            data: vec![vec![0; dimension as usize]; dimension as usize],
+            data: vec![vec![T::default(); dimension as usize]; dimension as usize],
         };
         Calculator {
             matrix_a,
@@ -204,55 +648,94 @@ impl Calculator {
             matrix_c,
             cache,
             cache_miss: 0,
+            cache_access: 0,
+            matrix_miss: [0; 3],
+            register_model: RegisterModel::Off,
+            buffer_pool: None,
+            trace: None,
         }
     }
 
-    pub fn parse_address(&self, matrix: &Matrix, i: usize, j: usize) -> Address {
-        // 元素的一维索引 = (i * dimension + j)
-        let address = i * matrix.dimension as usize + j;
-        // 偏移 = address % cache_line_size
-        let offset = (address % (self.cache.lines[0].cache_line_size as usize)) as u32;
-        // 索引 = (address / cache_line_size) % line_number
-        let index = ((address / (self.cache.lines[0].cache_line_size as usize))
-            % self.cache.line_number as usize) as u32;
-        // 标签 = (address / cache_line_size) / line_number + matrix.id * line_number
-        let tag = ((address / (self.cache.lines[0].cache_line_size as usize))
-            / self.cache.line_number as usize) as u32
-            + (matrix.id * self.cache.line_number) as u32;
-
-        Address { tag, index, offset }
+    pub fn parse_address(&self, matrix: &Matrix<T>, i: usize, j: usize) -> Address {
+        // 元素的字节地址 = matrix.id * REGION_SPAN + (i * dimension + j) * 元素大小，
+        // 每个矩阵占用独立的地址区间，不同矩阵的标签不会相同。
+        // 偏移 = 地址 % 行大小，组号 = (地址 / 行大小) % 组数，标签 = (地址 / 行大小) / 组数，
+        // 组数由相联度和该矩阵分得的行数决定
+        self.cache.locate(
+            matrix.id,
+            matrix.id,
+            (i * matrix.dimension as usize + j) * T::SIZE,
+        )
     }
 
-    pub fn get_data(&mut self, matrix: &Matrix, i: usize, j: usize) -> Option<u32> {
+    pub fn get_data(&mut self, matrix: &Matrix<T>, i: usize, j: usize) -> Option<T> {
         // 越界检查
         if i >= matrix.dimension as usize || j >= matrix.dimension as usize {
             return None;
         }
-        // 解析地址
-        let address = self.parse_address(matrix, i, j);
-        let line = &mut self.cache.lines[address.index as usize];
-        if line.valid && line.tag == address.tag {
-            // Cache命中
-            Some(line.data[address.offset as usize])
-        } else {
+        self.cache_access += 1;
+        // 查找所在的组，未命中时分配一行
+        let lookup = self
+            .cache
+            .lookup(matrix.id, (i * matrix.dimension as usize + j) * T::SIZE);
+        let slot = lookup.address.offset as usize / T::SIZE;
+        let line = &mut self.cache.lines[lookup.line];
+        if !lookup.hit {
             // Cache未命中
             self.cache_miss += 1;
+            if let Some(miss) = self.matrix_miss.get_mut(matrix.id as usize) {
+                *miss += 1;
+            }
+            // 行首元素的一维索引
+            let start = i * matrix.dimension as usize + j - slot;
+            // 缓冲池只看到实际调入的扇区（不分扇区时即整行）
+            if let Some(pool) = &mut self.buffer_pool {
+                let sector_size = self.cache.sector_size as usize;
+                let address = (i * matrix.dimension as usize + j) * T::SIZE;
+                pool.access_range(matrix.id, address - address % sector_size, sector_size);
+            }
             // 从矩阵中加载数据到Cache行
-            line.valid = true;
-            line.tag = address.tag;
-            let start = (address.index * line.cache_line_size) as usize;
-            for o in 0..line.cache_line_size as usize {
+            for o in 0..line.data.len() {
                 let idx = start + o;
                 let row = idx / matrix.dimension as usize;
                 let col = idx % matrix.dimension as usize;
                 if row < matrix.dimension as usize && col < matrix.dimension as usize {
                     line.data[o] = matrix.data[row][col];
                 } else {
-                    line.data[o] = 0; // 超出矩阵范围，填充0
+                    line.data[o] = T::default(); // 超出矩阵范围，填充0
                 }
             }
-            Some(line.data[address.offset as usize])
         }
+        let value = line.data[slot];
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceEvent {
+                matrix: matrix.id,
+                i,
+                j,
+                address: lookup.address,
+                line: lookup.line,
+                hit: lookup.hit,
+                write: false,
+                evicted: lookup.evicted,
+                line_state: self.cache.lines[lookup.line].clone(),
+            });
+        }
+        Some(value)
+    }
+
+    /// 写分配：未命中时先把整行调入 Cache，再同时更新 Cache 行与矩阵
+    pub fn set_data(&mut self, matrix: &mut Matrix<T>, i: usize, j: usize, value: T) -> Option<()> {
+        self.get_data(matrix, i, j)?;
+        let address = (i * matrix.dimension as usize + j) * T::SIZE;
+        let line = self.cache.find(matrix.id, address).unwrap();
+        let slot = address % self.cache.lines[0].cache_line_size as usize / T::SIZE;
+        self.cache.lines[line].data[slot] = value;
+        matrix.data[i][j] = value;
+        if let Some(event) = self.trace.as_mut().and_then(|trace| trace.last_mut()) {
+            event.write = true;
+            event.line_state.data[slot] = value;
+        }
+        Some(())
     }
 
     pub fn calculate(&mut self, sequence: Sequence) {
@@ -261,6 +744,22 @@ impl Calculator {
         }
         let n = self.matrix_a.dimension as usize;
         println!("> 开始进行矩阵乘法计算...");
+        match self.register_model {
+            RegisterModel::Off => {}
+            RegisterModel::ScalarReplacement => {
+                self.calculate_with_registers(n, &sequence, 1, 1);
+                self.finish_calculation();
+                return;
+            }
+            RegisterModel::RegisterTiling { rows, cols } => {
+                if rows == 0 || cols == 0 {
+                    panic!("寄存器分块的行数和列数必须大于0");
+                }
+                self.calculate_with_registers(n, &sequence, rows as usize, cols as usize);
+                self.finish_calculation();
+                return;
+            }
+        }
         match sequence {
             Sequence::Sijk => self.calculate_ijk(n),
             Sequence::Sikj => self.calculate_ikj(n),
@@ -269,9 +768,139 @@ impl Calculator {
             Sequence::Skij => self.calculate_kij(n),
             Sequence::Skji => self.calculate_kji(n),
         }
+        self.finish_calculation();
+    }
+
+    fn finish_calculation(&mut self) {
         println!("> 矩阵乘法计算完毕，计算结果为:\n{:?}", self.matrix_c.data);
+        println!("> 计算过程中Cache访问次数: {}", self.cache_access);
         println!("> 计算过程中Cache未命中次数: {}", self.cache_miss);
-        self.matrix_c.data_to_file();
+        if let Some(pool) = &self.buffer_pool {
+            println!(
+                "> 缓冲池（{}，{} 个 {} 字节的页框）命中 {} 次，缺页 {} 次",
+                pool.policy.label(),
+                pool.frame_count,
+                pool.page_size,
+                pool.page_hits,
+                pool.page_faults
+            );
+        }
+        if let Err(err) = self.matrix_c.data_to_file() {
+            eprintln!("> 无法保存结果矩阵 {}: {}", self.matrix_c.file_path, err);
+        }
+    }
+
+    /// 用不经过 Cache 的 ijk 乘法校验 matrix_c（浮点数允许舍入误差）
+    pub fn verify(&self) -> bool {
+        let n = self.matrix_a.dimension as usize;
+        for i in 0..n {
+            for j in 0..n {
+                let mut sum = T::default();
+                for k in 0..n {
+                    sum += self.matrix_a.data[i][k] * self.matrix_b.data[k][j];
+                }
+                if !sum.approx_eq(self.matrix_c.data[i][j]) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // 按 rows×cols 的寄存器微内核计算：i、j 两个循环按块推进，k 逐个推进，
+    // 三个循环的嵌套顺序仍由 sequence 决定。最内层循环不变的操作数在进入最内层循环前
+    // 读入寄存器（C 块还要在退出后写回），其余操作数每次迭代都经过 Cache。
+    // rows = cols = 1 时即为标量替换。
+    fn calculate_with_registers(
+        &mut self,
+        n: usize,
+        sequence: &Sequence,
+        rows: usize,
+        cols: usize,
+    ) {
+        let temp_matrix_a = self.matrix_a.clone();
+        let temp_matrix_b = self.matrix_b.clone();
+        let mut temp_matrix_c = self.matrix_c.clone();
+
+        let [outer, middle, inner] = sequence.loop_order();
+        let extent = |l: Loop| match l {
+            Loop::I => n.div_ceil(rows),
+            Loop::J => n.div_ceil(cols),
+            Loop::K => n,
+        };
+
+        // 寄存器：A 的列片段、B 的行片段、C 的块
+        let mut reg_a = vec![T::default(); rows];
+        let mut reg_b = vec![T::default(); cols];
+        let mut reg_c = vec![vec![T::default(); cols]; rows];
+
+        for x in 0..extent(outer) {
+            for y in 0..extent(middle) {
+                // 按 I、J、K 的顺序保存当前块号 / k 值
+                let mut pos = [0usize; 3];
+                pos[outer as usize] = x;
+                pos[middle as usize] = y;
+                for z in 0..extent(inner) {
+                    pos[inner as usize] = z;
+                    let (i0, i1) = (pos[0] * rows, usize::min(pos[0] * rows + rows, n));
+                    let (j0, j1) = (pos[1] * cols, usize::min(pos[1] * cols + cols, n));
+                    let k = pos[2];
+
+                    // 最内层循环不变的操作数只在第一次迭代时读入
+                    if inner != Loop::J || z == 0 {
+                        for i in i0..i1 {
+                            reg_a[i - i0] = self.get_data(&temp_matrix_a, i, k).unwrap();
+                        }
+                    }
+                    if inner != Loop::I || z == 0 {
+                        for j in j0..j1 {
+                            reg_b[j - j0] = self.get_data(&temp_matrix_b, k, j).unwrap();
+                        }
+                    }
+                    if inner != Loop::K || z == 0 {
+                        for i in i0..i1 {
+                            for j in j0..j1 {
+                                reg_c[i - i0][j - j0] =
+                                    self.get_data(&temp_matrix_c, i, j).unwrap();
+                            }
+                        }
+                    }
+
+                    for i in 0..i1 - i0 {
+                        for j in 0..j1 - j0 {
+                            reg_c[i][j] += reg_a[i] * reg_b[j];
+                        }
+                    }
+
+                    // C 块在最内层循环结束后才写回
+                    if inner != Loop::K || z + 1 == extent(inner) {
+                        for i in i0..i1 {
+                            for j in j0..j1 {
+                                self.set_data(&mut temp_matrix_c, i, j, reg_c[i - i0][j - j0])
+                                    .unwrap();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.matrix_c = temp_matrix_c;
+    }
+
+    // 不做寄存器优化时的一次迭代：经过 Cache 读 C[i][j]、A[i][k]、B[k][j]，再写回 C[i][j]
+    fn multiply_add(
+        &mut self,
+        matrix_a: &Matrix<T>,
+        matrix_b: &Matrix<T>,
+        matrix_c: &mut Matrix<T>,
+        i: usize,
+        j: usize,
+        k: usize,
+    ) {
+        let sum = self.get_data(matrix_c, i, j).unwrap()
+            + self.get_data(matrix_a, i, k).unwrap() * self.get_data(matrix_b, k, j).unwrap();
+        self.set_data(matrix_c, i, j, sum).unwrap();
     }
 
     fn calculate_ijk(&mut self, n: usize) {
@@ -282,8 +911,7 @@ impl Calculator {
         for i in 0..n {
             for j in 0..n {
                 for k in 0..n {
-                    temp_matrix_c.data[i][j] += self.get_data(&temp_matrix_a, i, k).unwrap()
-                        * self.get_data(&temp_matrix_b, k, j).unwrap();
+                    self.multiply_add(&temp_matrix_a, &temp_matrix_b, &mut temp_matrix_c, i, j, k);
                 }
             }
         }
@@ -299,8 +927,7 @@ impl Calculator {
         for i in 0..n {
             for k in 0..n {
                 for j in 0..n {
-                    temp_matrix_c.data[i][j] += self.get_data(&temp_matrix_a, i, k).unwrap()
-                        * self.get_data(&temp_matrix_b, k, j).unwrap();
+                    self.multiply_add(&temp_matrix_a, &temp_matrix_b, &mut temp_matrix_c, i, j, k);
                 }
             }
         }
@@ -316,8 +943,7 @@ impl Calculator {
         for j in 0..n {
             for i in 0..n {
                 for k in 0..n {
-                    temp_matrix_c.data[i][j] += self.get_data(&temp_matrix_a, i, k).unwrap()
-                        * self.get_data(&temp_matrix_b, k, j).unwrap();
+                    self.multiply_add(&temp_matrix_a, &temp_matrix_b, &mut temp_matrix_c, i, j, k);
                 }
             }
         }
@@ -333,8 +959,7 @@ impl Calculator {
         for j in 0..n {
             for k in 0..n {
                 for i in 0..n {
-                    temp_matrix_c.data[i][j] += self.get_data(&temp_matrix_a, i, k).unwrap()
-                        * self.get_data(&temp_matrix_b, k, j).unwrap();
+                    self.multiply_add(&temp_matrix_a, &temp_matrix_b, &mut temp_matrix_c, i, j, k);
                 }
             }
         }
@@ -350,8 +975,7 @@ impl Calculator {
         for k in 0..n {
             for i in 0..n {
                 for j in 0..n {
-                    temp_matrix_c.data[i][j] += self.get_data(&temp_matrix_a, i, k).unwrap()
-                        * self.get_data(&temp_matrix_b, k, j).unwrap();
+                    self.multiply_add(&temp_matrix_a, &temp_matrix_b, &mut temp_matrix_c, i, j, k);
                 }
             }
         }
@@ -367,8 +991,7 @@ impl Calculator {
         for k in 0..n {
             for j in 0..n {
                 for i in 0..n {
-                    temp_matrix_c.data[i][j] += self.get_data(&temp_matrix_a, i, k).unwrap()
-                        * self.get_data(&temp_matrix_b, k, j).unwrap();
+                    self.multiply_add(&temp_matrix_a, &temp_matrix_b, &mut temp_matrix_c, i, j, k);
                 }
             }
         }
@@ -378,36 +1001,62 @@ impl Calculator {
 }
 
 impl Evaluator {
+    /// cache_line_sizes 以字节为单位
     pub fn evaluate(
         dimensions: Vec<u32>,
         cache_line_sizes: Vec<u32>,
         cache_line_numbers: Vec<u32>,
         sequences: Vec<Sequence>,
+    ) {
+        Self::evaluate_with_register_model::<u32>(
+            dimensions,
+            cache_line_sizes,
+            cache_line_numbers,
+            sequences,
+            RegisterModel::Off,
+        );
+    }
+
+    pub fn evaluate_with_register_model<T: Element>(
+        dimensions: Vec<u32>,
+        cache_line_sizes: Vec<u32>,
+        cache_line_numbers: Vec<u32>,
+        sequences: Vec<Sequence>,
+        register_model: RegisterModel,
     ) {
         for sequence in sequences {
             let results: Vec<EvalResult> = Vec::new();
             let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+            // u32 且不做寄存器优化时沿用原来的文件名
+            let mut suffix = match register_model {
+                RegisterModel::Off => String::new(),
+                _ => format!("_{}", register_model.label()),
+            };
+            if T::NAME != u32::NAME {
+                suffix.push_str(&format!("_{}", T::NAME));
+            }
             let file_path = format!(
-                "{}/data/project_1/origin_data/evaluation_{}.csv",
+                "{}/data/project_1/origin_data/evaluation_{}{}.csv",
                 cargo_manifest_dir,
-                sequence.to_string()
+                sequence.to_string(),
+                suffix
             );
             let file = File::create(&file_path).expect("无法创建评测结果文件");
             let mut writer = BufWriter::new(file);
             writeln!(
                 writer,
-                "dimension,cache_line_size,cache_line_number,cache_miss"
+                "dimension,cache_line_size,cache_line_number,cache_miss,cache_access"
             )
             .expect("无法写入评测结果文件");
             for &dimension in &dimensions {
                 for &cache_line_size in &cache_line_sizes {
                     for &cache_line_number in &cache_line_numbers {
-                        let matrix_a = Matrix::new(
+                        let matrix_a = Matrix::<T>::new(
                             0,
                             dimension,
                             &format!("./data/matrix_a_{}.txt", dimension),
                         );
-                        let matrix_b = Matrix::new(
+                        let matrix_b = Matrix::<T>::new(
                             1,
                             dimension,
                             &format!("./data/matrix_b_{}.txt", dimension),
@@ -419,11 +1068,16 @@ impl Evaluator {
                             cache,
                             &format!("./data/matrix_c_{}.txt", dimension),
                         );
+                        calculator.register_model = register_model.clone();
                         calculator.calculate(sequence.clone());
                         writeln!(
                             writer,
-                            "{},{},{},{}",
-                            dimension, cache_line_size, cache_line_number, calculator.cache_miss
+                            "{},{},{},{},{}",
+                            dimension,
+                            cache_line_size,
+                            cache_line_number,
+                            calculator.cache_miss,
+                            calculator.cache_access
                         )
                         .expect("无法写入评测结果文件");
                     }
@@ -433,13 +1087,185 @@ impl Evaluator {
             println!("> 评测结果已保存到文件: {}", file_path);
         }
     }
+
+    /// 对每种循环顺序输出一个 CSV，记录各行大小、扇区大小下的未命中、调入字节数和行利用率直方图。
+    /// 不超过行大小的 sector_sizes 才会参与组合，行大小本身（不分扇区）总会参与；均以字节为单位。
+    pub fn evaluate_utilisation<T: Element>(
+        dimensions: Vec<u32>,
+        cache_line_sizes: Vec<u32>,
+        sector_sizes: Vec<u32>,
+        cache_line_number: u32,
+        sequences: Vec<Sequence>,
+    ) -> io::Result<()> {
+        let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let output_dir = format!("{}/data/project_1/origin_data", cargo_manifest_dir);
+        fs::create_dir_all(&output_dir)?;
+        let suffix = if T::NAME != u32::NAME {
+            format!("_{}", T::NAME)
+        } else {
+            String::new()
+        };
+        let matrices: Vec<(Matrix<T>, Matrix<T>)> = dimensions
+            .iter()
+            .map(|&dimension| {
+                (
+                    Matrix::new(
+                        0,
+                        dimension,
+                        &format!("origin_data/matrix_a_{}{}.txt", dimension, suffix),
+                    ),
+                    Matrix::new(
+                        1,
+                        dimension,
+                        &format!("origin_data/matrix_b_{}{}.txt", dimension, suffix),
+                    ),
+                )
+            })
+            .collect();
+        let bins: Vec<String> = (0..UTILISATION_BINS)
+            .map(|b| {
+                format!(
+                    "util_{}_{}",
+                    b * 100 / UTILISATION_BINS,
+                    (b + 1) * 100 / UTILISATION_BINS
+                )
+            })
+            .collect();
+
+        for sequence in sequences {
+            let file_path = format!(
+                "{}/utilisation_{}{}.csv",
+                output_dir,
+                sequence.to_string(),
+                suffix
+            );
+            let mut writer = BufWriter::new(File::create(&file_path)?);
+            writeln!(
+                writer,
+                "dimension,cache_line_size,sector_size,cache_line_number,cache_miss,sector_misses,bytes_fetched,lines,avg_utilisation,{}",
+                bins.join(",")
+            )?;
+            for (matrix_a, matrix_b) in &matrices {
+                let dimension = matrix_a.dimension;
+                for &cache_line_size in &cache_line_sizes {
+                    let mut sizes: Vec<u32> = sector_sizes
+                        .iter()
+                        .copied()
+                        .filter(|&s| s < cache_line_size && cache_line_size.is_multiple_of(s))
+                        .collect();
+                    sizes.push(cache_line_size);
+                    for sector_size in sizes {
+                        let cache = Cache::new(cache_line_number, cache_line_size)
+                            .with_sectors(sector_size);
+                        let mut calculator = Calculator::new(
+                            matrix_a.clone(),
+                            matrix_b.clone(),
+                            cache,
+                            &format!("origin_data/matrix_c_{}{}.txt", dimension, suffix),
+                        );
+                        calculator.calculate(sequence.clone());
+                        let utilisation = calculator.cache.utilisation();
+                        let histogram: Vec<String> = utilisation
+                            .histogram
+                            .iter()
+                            .map(|n| n.to_string())
+                            .collect();
+                        writeln!(
+                            writer,
+                            "{},{},{},{},{},{},{},{},{:.4},{}",
+                            dimension,
+                            cache_line_size,
+                            sector_size,
+                            cache_line_number,
+                            calculator.cache_miss,
+                            calculator.cache.sector_misses,
+                            calculator.cache.bytes_fetched,
+                            utilisation.lines,
+                            utilisation.average(),
+                            histogram.join(",")
+                        )?;
+                    }
+                }
+            }
+            writer.flush()?;
+            println!("> 行利用率评测结果已保存到文件: {}", file_path);
+        }
+        Ok(())
+    }
 }
 
+/// 评测参数从配置文件读取：`cargo run -- project_1 [配置文件]`，
+/// 默认使用 data/project_1/experiments.conf，格式见 `ExperimentGrid`。
+///
+/// 逐步查看 Cache 状态：
+/// `cargo run -- project_1 trace <循环顺序> <维度> <行数x行大小[x相联度]> [plain|ansi|html] [输出文件] [寄存器模型]`
 pub fn run() {
-    let dimensions = vec![3, 6, 10, 20, 50, 100];
-    let cache_line_sizes = vec![1, 2, 4, 8, 16, 32, 64];
-    let cache_line_numbers = vec![1, 2, 4, 8, 16, 32, 64];
-    let sequences = vec![
+    let args: Vec<String> = env::args().skip(2).collect();
+    if args.first().map(String::as_str) == Some("trace") {
+        if let Err(err) = run_trace(&args[1..]) {
+            eprintln!("> 无法生成访问轨迹: {}", err);
+        }
+        return;
+    }
+    let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let config_path = args
+        .first()
+        .cloned()
+        .unwrap_or_else(|| format!("{}/data/project_1/experiments.conf", cargo_manifest_dir));
+    if let Err(err) = GridEvaluator::evaluate_file(&config_path) {
+        eprintln!("> 评测失败: {}", err);
+    }
+}
+
+fn run_trace(args: &[String]) -> io::Result<()> {
+    let usage = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "用法: trace <循环顺序> <维度> <行数x行大小[x相联度]> [plain|ansi|html] [输出文件] [寄存器模型]",
+        )
+    };
+    let sequence = match args.first().and_then(|s| grid::parse_kernel(s)) {
+        Some(GridKernel::Dense(sequence)) => sequence,
+        _ => return Err(usage()),
+    };
+    let dimension: u32 = args.get(1).and_then(|s| s.parse().ok()).ok_or_else(usage)?;
+    let cache_spec = args.get(2).ok_or_else(usage)?;
+    let (cache_spec, ways) = match cache_spec.matches('x').count() {
+        2 => {
+            let (spec, ways) = cache_spec.rsplit_once('x').unwrap();
+            (spec, ways.parse().map_err(|_| usage())?)
+        }
+        _ => (cache_spec.as_str(), 1),
+    };
+    let (line_number, line_size) = grid::parse_cache(cache_spec).ok_or_else(usage)?;
+    grid::check_dimension(dimension, u32::SIZE)?;
+    grid::check_line_size(line_size, u32::SIZE)?;
+    Cache::<u32>::check(line_number, ways, &CachePartition::Shared)?;
+    let format = match args.get(3).map(String::as_str) {
+        None | Some("ansi") => TraceFormat::Ansi,
+        Some("plain") => TraceFormat::Plain,
+        Some("html") => TraceFormat::Html,
+        Some(_) => return Err(usage()),
+    };
+    let register_model = match args.get(5) {
+        Some(s) => grid::parse_register_model(s).ok_or_else(usage)?,
+        None => RegisterModel::Off,
+    };
+    CacheTracer::trace::<u32>(
+        dimension,
+        Cache::with_ways(line_number, line_size, ways, CachePartition::Shared),
+        sequence,
+        register_model,
+        format,
+        args.get(4).map(String::as_str),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEQUENCES: [Sequence; 6] = [
         Sequence::Sijk,
         Sequence::Sikj,
         Sequence::Sjik,
@@ -447,5 +1273,196 @@ pub fn run() {
         Sequence::Skij,
         Sequence::Skji,
     ];
-    Evaluator::evaluate(dimensions, cache_line_sizes, cache_line_numbers, sequences);
+
+    fn matrix(id: u32, n: u32) -> Matrix<u32> {
+        Matrix {
+            id,
+            dimension: n,
+            file_path: String::new(),
+            data: (0..n)
+                .map(|i| (0..n).map(|j| (i * n + j + id) % 7).collect())
+                .collect(),
+        }
+    }
+
+    // 结果矩阵写到临时目录，不碰 data/project_1
+    fn calculator(n: u32, cache: Cache<u32>, name: &str) -> Calculator<u32> {
+        let mut calculator = Calculator::new(matrix(0, n), matrix(1, n), cache, "unused.txt");
+        calculator.matrix_c.file_path = env::temp_dir()
+            .join(format!("project_1_{}_{}.txt", name, process::id()))
+            .to_string_lossy()
+            .into_owned();
+        calculator
+    }
+
+    #[test]
+    fn access_counts_per_register_model() {
+        // n = 4：不优化时每次迭代读 A、B、C 并写回 C，共 4n³ 次；
+        // 标量替换省掉最内层循环不变的操作数，寄存器分块再按块复用 A、B
+        let cases = [
+            (RegisterModel::Off, [256, 256, 256, 256, 256, 256]),
+            (
+                RegisterModel::ScalarReplacement,
+                [160, 208, 160, 208, 208, 208],
+            ),
+            (
+                RegisterModel::RegisterTiling { rows: 2, cols: 2 },
+                [96, 176, 96, 176, 176, 176],
+            ),
+        ];
+        for (model, expected) in cases {
+            for (sequence, expected) in SEQUENCES.iter().zip(expected) {
+                let mut calculator = calculator(4, Cache::new(8, 16), "access_counts");
+                calculator.register_model = model.clone();
+                calculator.calculate(sequence.clone());
+                assert_eq!(
+                    calculator.cache_access,
+                    expected,
+                    "{} {}",
+                    model.label(),
+                    sequence.to_string()
+                );
+                assert!(calculator.verify());
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "2 的幂")]
+    fn rejects_line_sizes_that_are_not_powers_of_two() {
+        CacheLine::<u32>::new(12);
+    }
+
+    #[test]
+    fn f64_lines_hold_half_as_many_elements() {
+        assert_eq!(CacheLine::<u32>::new(16).data.len(), 4);
+        assert_eq!(CacheLine::<f64>::new(16).data.len(), 2);
+        // 每个存储区的起点都与行对齐，B[0][0] 的行内偏移为 0
+        let cache = Cache::<u32>::new(4, 64);
+        assert_eq!(cache.locate(1, 1, 0).offset, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "REGION_SPAN")]
+    fn rejects_matrices_larger_than_their_region() {
+        // 2897² 个 f64 超过 64 MiB；只检查维度，不需要真的分配矩阵
+        let matrix = |id| Matrix::<f64> {
+            id,
+            dimension: 2897,
+            file_path: String::new(),
+            data: Vec::new(),
+        };
+        Calculator::new(matrix(0), matrix(1), Cache::new(4, 64), "unused.txt");
+    }
+
+    #[test]
+    fn check_rejects_empty_caches() {
+        for (line_number, ways) in [(0, 1), (0, 4), (4, 0), (4, 8)] {
+            let err = Cache::<u32>::check(line_number, ways, &CachePartition::Shared).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(Cache::<u32>::check(4, 4, &CachePartition::Shared).is_ok());
+        let err = ExperimentGrid::parse("[empty]\ndimensions = 4\ncaches = 0x64\nkernels = Sijk\n")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn way_partition_isolates_reserved_ways() {
+        // 2 组 × 4 路：A 独占第 0 路，B 独占第 1、2 路，C 使用第 3 路
+        let stream = |cache: &mut Cache<u32>| {
+            // B 的两行都映射到第 0 组，随后 A 顺序扫过 64 行
+            assert!(!cache.lookup(1, 0).hit);
+            assert!(!cache.lookup(1, 32).hit);
+            for address in (0..1024).step_by(16) {
+                let lookup = cache.lookup(0, address);
+                assert!(!lookup.hit);
+                if let CachePartition::Ways(_) = cache.partition {
+                    assert_eq!(lookup.line % 4, 0, "A 只能使用第 0 路");
+                }
+            }
+            [cache.lookup(1, 0).hit, cache.lookup(1, 32).hit]
+        };
+        let mut partitioned =
+            Cache::<u32>::with_ways(8, 16, 4, CachePartition::Ways(vec![1, 2, 1]));
+        assert_eq!(stream(&mut partitioned), [true, true]);
+        // 共享时 A 的扫描把 B 的行替换出去
+        let mut shared = Cache::<u32>::with_ways(8, 16, 4, CachePartition::Shared);
+        assert_eq!(stream(&mut shared), [false, false]);
+    }
+
+    #[test]
+    fn sectors_are_fetched_on_demand() {
+        // 2 行 × 32 字节，每行 4 个 8 字节扇区
+        let mut cache = Cache::<u32>::new(2, 32).with_sectors(8);
+        let first = cache.lookup(0, 0);
+        assert!(!first.hit);
+        assert_eq!(
+            cache.lines[first.line].sector_valid,
+            [true, false, false, false]
+        );
+        assert!(cache.lookup(0, 4).hit);
+        // 标签命中但扇区无效：只调入第 2 个扇区，不替换行
+        let sector_miss = cache.lookup(0, 20);
+        assert!(!sector_miss.hit && sector_miss.evicted.is_none());
+        assert_eq!(sector_miss.line, first.line);
+        assert_eq!(
+            cache.lines[first.line].sector_valid,
+            [true, false, true, false]
+        );
+        assert!(cache.lookup(0, 16).hit);
+        assert_eq!((cache.sector_misses, cache.bytes_fetched), (1, 16));
+
+        // 映射到同一行的块替换整行，扇区全部失效后只调入被访问的扇区
+        let conflict = cache.lookup(0, 64 + 24);
+        assert_eq!(conflict.line, first.line);
+        assert_eq!(conflict.evicted, Some(first.address.tag));
+        assert_eq!(
+            cache.lines[first.line].sector_valid,
+            [false, false, false, true]
+        );
+        // 被替换的行访问过 0..8 与 16..24 共 16 字节，利用率 0.5
+        let utilisation = cache.utilisation();
+        assert_eq!(utilisation.histogram[4], 1);
+        assert_eq!(utilisation.lines, 2);
+    }
+
+    #[test]
+    fn utilisation_counts_every_evicted_and_resident_line() {
+        let cache = Cache::with_ways(8, 16, 2, CachePartition::Shared).with_sectors(8);
+        let mut calculator = calculator(6, cache, "utilisation");
+        calculator.trace = Some(Vec::new());
+        calculator.calculate(Sequence::Sjki);
+        let events = calculator.trace.take().unwrap();
+        let evicted = events.iter().filter(|e| e.evicted.is_some()).count() as u64;
+        assert!(evicted > 0);
+        let resident = calculator.cache.lines.iter().filter(|l| l.valid).count() as u64;
+
+        let replaced = &calculator.cache.evicted_utilisation;
+        assert_eq!(replaced.histogram.iter().sum::<u64>(), evicted);
+        let utilisation = calculator.cache.utilisation();
+        assert_eq!(utilisation.lines, evicted + resident);
+        assert_eq!(utilisation.histogram.iter().sum::<u64>(), utilisation.lines);
+        assert!(utilisation.average() > 0.0 && utilisation.average() <= 1.0);
+        fs::remove_file(&calculator.matrix_c.file_path).unwrap();
+    }
+
+    #[test]
+    fn trace_rejects_unusable_caches() {
+        // 行大小不是 2 的幂、小于元素大小，以及超出 REGION_SPAN 的维度
+        for (dimension, cache) in [("3", "16x24"), ("3", "16x2"), ("100000", "16x16")] {
+            let args: Vec<String> = ["Sijk", dimension, cache, "plain"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            let err = run_trace(&args).unwrap_err();
+            assert_eq!(
+                err.kind(),
+                io::ErrorKind::InvalidInput,
+                "{} {}",
+                dimension,
+                cache
+            );
+        }
+    }
 }
@@ -0,0 +1,418 @@
+#![allow(unused)]
+use super::format::invalid_data;
+use super::{BinaryHeader, Element, Layout};
+use fs::*;
+use io::*;
+use std::marker::PhantomData;
+use std::time::Instant;
+use std::*;
+
+/// 可以按块随机读写的二进制矩阵文件（格式与 `MatrixFormat::Binary` 相同）
+pub struct TiledMatrixFile<T: Element = u32> {
+    file: File,
+    pub header: BinaryHeader,
+    _element: PhantomData<T>,
+}
+
+impl<T: Element> TiledMatrixFile<T> {
+    pub fn open(path: &str) -> io::Result<TiledMatrixFile<T>> {
+        // 输入矩阵只读打开，乘法不会写到 A、B 上
+        let mut file = File::open(path)?;
+        let header = BinaryHeader::read_from(&mut file)
+            .map_err(|e| invalid_data(format!("{}: {}", path, e)))?;
+        header.check_type::<T>()?;
+        Ok(TiledMatrixFile {
+            file,
+            header,
+            _element: PhantomData,
+        })
+    }
+
+    /// 创建一个全 0 的矩阵文件
+    pub fn create(
+        path: &str,
+        rows: u32,
+        cols: u32,
+        layout: Layout,
+    ) -> io::Result<TiledMatrixFile<T>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let header = BinaryHeader::new::<T>(rows, cols, layout);
+        header.write_to(&mut file)?;
+        file.set_len(BinaryHeader::SIZE + rows as u64 * cols as u64 * T::SIZE as u64)?;
+        Ok(TiledMatrixFile {
+            file,
+            header,
+            _element: PhantomData,
+        })
+    }
+
+    /// 逐行生成随机矩阵文件，内存中只保留一行
+    pub fn generate(path: &str, rows: u32, cols: u32) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        BinaryHeader::new::<T>(rows, cols, Layout::RowMajor).write_to(&mut writer)?;
+        let mut rng = rand::rng();
+        for _ in 0..rows {
+            for _ in 0..cols {
+                writer.write_all(&T::random(&mut rng).to_le_bytes_vec())?;
+            }
+        }
+        writer.flush()
+    }
+
+    // 元素 (i, j) 在文件中的字节位置
+    fn offset(&self, i: usize, j: usize) -> u64 {
+        let (rows, cols) = (self.header.rows as u64, self.header.cols as u64);
+        let n = match self.header.layout {
+            Layout::RowMajor => i as u64 * cols + j as u64,
+            Layout::ColMajor => j as u64 * rows + i as u64,
+        };
+        BinaryHeader::SIZE + n * T::SIZE as u64
+    }
+
+    /// 读取从 (r0, c0) 开始的 h×w 块，按行优先返回
+    pub fn read_tile(&mut self, r0: usize, c0: usize, h: usize, w: usize) -> io::Result<Vec<T>> {
+        let mut tile = vec![T::default(); h * w];
+        match self.header.layout {
+            Layout::RowMajor => {
+                let mut bytes = vec![0u8; w * T::SIZE];
+                for i in 0..h {
+                    self.file.seek(SeekFrom::Start(self.offset(r0 + i, c0)))?;
+                    self.file.read_exact(&mut bytes)?;
+                    for (j, chunk) in bytes.chunks_exact(T::SIZE).enumerate() {
+                        tile[i * w + j] = T::from_le_slice(chunk);
+                    }
+                }
+            }
+            Layout::ColMajor => {
+                let mut bytes = vec![0u8; h * T::SIZE];
+                for j in 0..w {
+                    self.file.seek(SeekFrom::Start(self.offset(r0, c0 + j)))?;
+                    self.file.read_exact(&mut bytes)?;
+                    for (i, chunk) in bytes.chunks_exact(T::SIZE).enumerate() {
+                        tile[i * w + j] = T::from_le_slice(chunk);
+                    }
+                }
+            }
+        }
+        Ok(tile)
+    }
+
+    /// 把按行优先存放的 h×w 块写到 (r0, c0)
+    pub fn write_tile(
+        &mut self,
+        r0: usize,
+        c0: usize,
+        h: usize,
+        w: usize,
+        tile: &[T],
+    ) -> io::Result<()> {
+        match self.header.layout {
+            Layout::RowMajor => {
+                for i in 0..h {
+                    let bytes: Vec<u8> = tile[i * w..(i + 1) * w]
+                        .iter()
+                        .flat_map(|v| v.to_le_bytes_vec())
+                        .collect();
+                    self.file.seek(SeekFrom::Start(self.offset(r0 + i, c0)))?;
+                    self.file.write_all(&bytes)?;
+                }
+            }
+            Layout::ColMajor => {
+                for j in 0..w {
+                    let bytes: Vec<u8> = (0..h)
+                        .flat_map(|i| tile[i * w + j].to_le_bytes_vec())
+                        .collect();
+                    self.file.seek(SeekFrom::Start(self.offset(r0, c0 + j)))?;
+                    self.file.write_all(&bytes)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 外存分块乘法的 I/O 统计。predicted_* 为按分块理论算出的值，用于与实测对比。
+#[derive(Clone, Debug, Default)]
+pub struct OutOfCoreStats {
+    pub tile_size: usize,
+    pub tile_reads: u64,
+    pub tile_writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub predicted_tile_reads: u64,
+    pub predicted_tile_writes: u64,
+    pub predicted_bytes: u64,
+    pub elapsed_ms: u128,
+}
+
+/// 外存分块矩阵乘法：C = A·B，A、B、C 都是二进制矩阵文件。
+/// 内存中同时只保留 A、B、C 各一个 t×t 的块，t 由内存预算决定：3·t²·元素大小 <= memory_budget。
+/// 块循环按 ijk 顺序进行，C 块在 k 循环中常驻内存，因此每个 C 块只写一次。
+pub struct OutOfCoreMultiplier<T: Element = u32> {
+    pub a_path: String,
+    pub b_path: String,
+    pub c_path: String,
+    /// 内存预算（字节）
+    pub memory_budget: usize,
+    pub stats: OutOfCoreStats,
+    _element: PhantomData<T>,
+}
+
+impl<T: Element> OutOfCoreMultiplier<T> {
+    pub fn new(
+        a_path: &str,
+        b_path: &str,
+        c_path: &str,
+        memory_budget: usize,
+    ) -> OutOfCoreMultiplier<T> {
+        let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let resolve = |p: &str| format!("{}/data/project_1/{}", cargo_manifest_dir, p);
+        OutOfCoreMultiplier {
+            a_path: resolve(a_path),
+            b_path: resolve(b_path),
+            c_path: resolve(c_path),
+            memory_budget,
+            stats: OutOfCoreStats::default(),
+            _element: PhantomData,
+        }
+    }
+
+    /// 三个块都放得下的最大块边长
+    pub fn tile_size(&self) -> io::Result<usize> {
+        let t = ((self.memory_budget / (3 * T::SIZE)) as f64).sqrt() as usize;
+        if t == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "内存预算 {} 字节放不下 3 个 {} 元素",
+                    self.memory_budget,
+                    T::NAME
+                ),
+            ));
+        }
+        Ok(t)
+    }
+
+    pub fn multiply(&mut self) -> io::Result<()> {
+        // 创建 C 会清空文件，必须在读 A、B 之前确认它不是其中之一
+        if let Ok(c) = fs::canonicalize(&self.c_path) {
+            for input in [&self.a_path, &self.b_path] {
+                if fs::canonicalize(input).is_ok_and(|input| input == c) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("结果文件 {} 与输入文件相同", self.c_path),
+                    ));
+                }
+            }
+        }
+        let mut a = TiledMatrixFile::<T>::open(&self.a_path)?;
+        let mut b = TiledMatrixFile::<T>::open(&self.b_path)?;
+        let (n, p) = (a.header.rows as usize, a.header.cols as usize);
+        let m = b.header.cols as usize;
+        if b.header.rows as usize != p {
+            return Err(invalid_data(format!(
+                "矩阵维度不匹配，无法相乘: {}x{} · {}x{}",
+                n, p, b.header.rows, m
+            )));
+        }
+        let mut c =
+            TiledMatrixFile::<T>::create(&self.c_path, n as u32, m as u32, Layout::RowMajor)?;
+
+        let t = self.tile_size()?;
+        let (tiles_i, tiles_k, tiles_j) = (n.div_ceil(t), p.div_ceil(t), m.div_ceil(t));
+        self.stats = OutOfCoreStats {
+            tile_size: t,
+            predicted_tile_reads: 2 * (tiles_i * tiles_k * tiles_j) as u64,
+            predicted_tile_writes: (tiles_i * tiles_j) as u64,
+            predicted_bytes: ((n * p * tiles_j + p * m * tiles_i + n * m) * T::SIZE) as u64,
+            ..OutOfCoreStats::default()
+        };
+        println!(
+            "> 开始外存分块乘法: {}x{} · {}x{}，块大小 {}，内存预算 {} 字节",
+            n, p, p, m, t, self.memory_budget
+        );
+
+        let start_time = Instant::now();
+        for i0 in (0..n).step_by(t) {
+            let h = usize::min(t, n - i0);
+            for j0 in (0..m).step_by(t) {
+                let w = usize::min(t, m - j0);
+                let mut tile_c = vec![T::default(); h * w];
+                for k0 in (0..p).step_by(t) {
+                    let d = usize::min(t, p - k0);
+                    let tile_a = a.read_tile(i0, k0, h, d)?;
+                    let tile_b = b.read_tile(k0, j0, d, w)?;
+                    self.stats.tile_reads += 2;
+                    self.stats.bytes_read += ((h * d + d * w) * T::SIZE) as u64;
+                    for i in 0..h {
+                        for k in 0..d {
+                            let a_ik = tile_a[i * d + k];
+                            for j in 0..w {
+                                tile_c[i * w + j] += a_ik * tile_b[k * w + j];
+                            }
+                        }
+                    }
+                }
+                c.write_tile(i0, j0, h, w, &tile_c)?;
+                self.stats.tile_writes += 1;
+                self.stats.bytes_written += (h * w * T::SIZE) as u64;
+            }
+        }
+        c.file.sync_all()?;
+        self.stats.elapsed_ms = start_time.elapsed().as_millis();
+
+        println!(
+            "> 外存乘法完成: 读块 {} 次（理论 {}），写块 {} 次（理论 {}），读写 {} 字节（理论 {}），耗时 {} 毫秒",
+            self.stats.tile_reads,
+            self.stats.predicted_tile_reads,
+            self.stats.tile_writes,
+            self.stats.predicted_tile_writes,
+            self.stats.bytes_read + self.stats.bytes_written,
+            self.stats.predicted_bytes,
+            self.stats.elapsed_ms
+        );
+        Ok(())
+    }
+
+    /// 对每个维度生成一对随机方阵，在不同内存预算下做外存乘法，
+    /// 结果写入 data/project_1/origin_data/out_of_core.csv
+    pub fn evaluate(dimensions: Vec<u32>, memory_budgets: Vec<usize>) -> io::Result<()> {
+        let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let output_dir = format!("{}/data/project_1/origin_data", cargo_manifest_dir);
+        fs::create_dir_all(&output_dir)?;
+        let suffix = if T::NAME != u32::NAME {
+            format!("_{}", T::NAME)
+        } else {
+            String::new()
+        };
+        let file_path = format!("{}/out_of_core{}.csv", output_dir, suffix);
+        let mut writer = BufWriter::new(File::create(&file_path)?);
+        writeln!(
+            writer,
+            "dimension,memory_budget,tile_size,tile_reads,tile_writes,bytes_read,bytes_written,predicted_tile_reads,predicted_tile_writes,predicted_bytes,elapsed_ms"
+        )?;
+
+        for &dimension in &dimensions {
+            let a_name = format!("origin_data/ooc_a_{}.bin", dimension);
+            let b_name = format!("origin_data/ooc_b_{}.bin", dimension);
+            let c_name = format!("origin_data/ooc_c_{}.bin", dimension);
+            for name in [&a_name, &b_name] {
+                TiledMatrixFile::<T>::generate(
+                    &format!("{}/data/project_1/{}", cargo_manifest_dir, name),
+                    dimension,
+                    dimension,
+                )?;
+            }
+            for &memory_budget in &memory_budgets {
+                let mut multiplier =
+                    OutOfCoreMultiplier::<T>::new(&a_name, &b_name, &c_name, memory_budget);
+                multiplier.multiply()?;
+                let s = &multiplier.stats;
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{},{},{},{},{}",
+                    dimension,
+                    memory_budget,
+                    s.tile_size,
+                    s.tile_reads,
+                    s.tile_writes,
+                    s.bytes_read,
+                    s.bytes_written,
+                    s.predicted_tile_reads,
+                    s.predicted_tile_writes,
+                    s.predicted_bytes,
+                    s.elapsed_ms
+                )?;
+            }
+        }
+        writer.flush()?;
+        println!("> 外存乘法评测结果已保存到文件: {}", file_path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MatrixFormat, read_dense, write_dense};
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        env::temp_dir()
+            .join(format!("project_1_ooc_{}_{}", process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn multiplier(a: &str, b: &str, c: &str, memory_budget: usize) -> OutOfCoreMultiplier<u32> {
+        OutOfCoreMultiplier {
+            a_path: a.to_string(),
+            b_path: b.to_string(),
+            c_path: c.to_string(),
+            memory_budget,
+            stats: OutOfCoreStats::default(),
+            _element: PhantomData,
+        }
+    }
+
+    #[test]
+    fn matches_in_memory_product() {
+        // 5×7 · 7×3，块边长 2 不能整除任何一维；B 按列优先存放
+        let a: Vec<Vec<u32>> = (0..5)
+            .map(|i| (0..7).map(|k| (i * 7 + k) % 5).collect())
+            .collect();
+        let b: Vec<Vec<u32>> = (0..7)
+            .map(|k| (0..3).map(|j| (k + 2 * j) % 4).collect())
+            .collect();
+        let (a_path, b_path, c_path) = (temp_path("a.bin"), temp_path("b.bin"), temp_path("c.bin"));
+        write_dense(&a_path, &a, MatrixFormat::Binary(Layout::RowMajor)).unwrap();
+        write_dense(&b_path, &b, MatrixFormat::Binary(Layout::ColMajor)).unwrap();
+        // 输入只需要读权限
+        for path in [&a_path, &b_path] {
+            let mut permissions = fs::metadata(path).unwrap().permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(path, permissions).unwrap();
+        }
+
+        let mut multiplier = multiplier(&a_path, &b_path, &c_path, 3 * 2 * 2 * 4);
+        multiplier.multiply().unwrap();
+        let c: Vec<Vec<u32>> = read_dense(&c_path).unwrap();
+        let expected: Vec<Vec<u32>> = (0..5)
+            .map(|i| {
+                (0..3)
+                    .map(|j| (0..7).map(|k| a[i][k] * b[k][j]).sum())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(c, expected);
+
+        let s = &multiplier.stats;
+        assert_eq!(s.tile_size, 2);
+        // 3×4×2 个 (i, k, j) 块组合，每个读 A、B 各一块；C 有 3×2 块
+        assert_eq!(s.predicted_tile_reads, 48);
+        assert_eq!(s.tile_reads, s.predicted_tile_reads);
+        assert_eq!(s.tile_writes, s.predicted_tile_writes);
+        assert_eq!(s.tile_writes, 6);
+        assert_eq!(s.bytes_read + s.bytes_written, s.predicted_bytes);
+        for path in [a_path, b_path, c_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_to_overwrite_an_input() {
+        let a: Vec<Vec<u32>> = vec![vec![1, 2], vec![3, 4]];
+        let a_path = temp_path("same.bin");
+        write_dense(&a_path, &a, MatrixFormat::Binary(Layout::RowMajor)).unwrap();
+        let err = multiplier(&a_path, &a_path, &a_path, 1024)
+            .multiply()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(read_dense::<u32>(&a_path).unwrap(), a);
+        fs::remove_file(a_path).unwrap();
+    }
+}
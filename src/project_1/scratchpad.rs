@@ -0,0 +1,344 @@
+#![allow(unused)]
+use super::*;
+use fs::*;
+use io::*;
+use std::*;
+
+// 三个操作数在片上存储中各占一个块缓冲区
+const SLOT_A: usize = 0;
+const SLOT_B: usize = 1;
+const SLOT_C: usize = 2;
+
+/// 片上存储中的一个块：矩阵中从 (r0, c0) 开始的 h×w 个元素，按行优先存放
+#[derive(Clone, Debug)]
+pub struct TileBuffer<T: Element = u32> {
+    pub r0: usize,
+    pub c0: usize,
+    pub h: usize,
+    pub w: usize,
+    pub data: Vec<T>,
+    /// 调入后被修改过，换出时需要写回
+    pub dirty: bool,
+}
+
+/// 软件管理的片上存储（scratchpad）。与 `Cache` 不同，数据的调入调出全部由程序显式发起，
+/// 每次二维块传输计为一次 DMA 操作。
+#[derive(Clone, Debug)]
+pub struct Scratchpad<T: Element = u32> {
+    /// 容量（字节）
+    pub capacity: usize,
+    pub dma_ops: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// 同时驻留的最大字节数
+    pub peak_usage: usize,
+    pub slots: Vec<Option<TileBuffer<T>>>,
+}
+
+impl<T: Element> Scratchpad<T> {
+    pub fn new(capacity: usize, slot_number: usize) -> Scratchpad<T> {
+        Scratchpad {
+            capacity,
+            dma_ops: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            peak_usage: 0,
+            slots: vec![None; slot_number],
+        }
+    }
+
+    pub fn usage(&self) -> usize {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|tile| tile.data.len() * T::SIZE)
+            .sum()
+    }
+
+    // 在 slot 中放入一个块，替换掉原有的块。容量不足或原有的块尚未写回时返回错误，slot 保持不变
+    fn place(&mut self, slot: usize, tile: TileBuffer<T>) -> io::Result<()> {
+        let old = match &self.slots[slot] {
+            Some(old) if old.dirty => {
+                return Err(invalid_input(format!(
+                    "片上存储第 {} 个缓冲区中 ({}, {}) 处的块被修改过，尚未写回",
+                    slot, old.r0, old.c0
+                )));
+            }
+            Some(old) => old.data.len() * T::SIZE,
+            None => 0,
+        };
+        let usage = self.usage() - old + tile.data.len() * T::SIZE;
+        if usage > self.capacity {
+            return Err(invalid_input(format!(
+                "片上存储容量 {} 字节不足，需要 {} 字节",
+                self.capacity, usage
+            )));
+        }
+        self.peak_usage = usize::max(self.peak_usage, usage);
+        self.slots[slot] = Some(tile);
+        Ok(())
+    }
+
+    /// 把 matrix 中从 (r0, c0) 开始的 h×w 块 DMA 到 slot
+    pub fn dma_in(
+        &mut self,
+        slot: usize,
+        matrix: &Matrix<T>,
+        r0: usize,
+        c0: usize,
+        h: usize,
+        w: usize,
+    ) -> io::Result<()> {
+        let data = (r0..r0 + h)
+            .flat_map(|i| matrix.data[i][c0..c0 + w].iter().copied())
+            .collect();
+        self.place(
+            slot,
+            TileBuffer {
+                r0,
+                c0,
+                h,
+                w,
+                data,
+                dirty: false,
+            },
+        )?;
+        self.dma_ops += 1;
+        self.bytes_in += (h * w * T::SIZE) as u64;
+        Ok(())
+    }
+
+    /// 在 slot 中分配一个全 0 的块，不产生传输
+    pub fn alloc(
+        &mut self,
+        slot: usize,
+        r0: usize,
+        c0: usize,
+        h: usize,
+        w: usize,
+    ) -> io::Result<()> {
+        self.place(
+            slot,
+            TileBuffer {
+                r0,
+                c0,
+                h,
+                w,
+                data: vec![T::default(); h * w],
+                dirty: false,
+            },
+        )
+    }
+
+    /// 把 slot 中被修改过的块 DMA 回 matrix，并释放 slot
+    pub fn dma_out(&mut self, slot: usize, matrix: &mut Matrix<T>) {
+        if let Some(tile) = self.slots[slot].take()
+            && tile.dirty
+        {
+            for i in 0..tile.h {
+                matrix.data[tile.r0 + i][tile.c0..tile.c0 + tile.w]
+                    .copy_from_slice(&tile.data[i * tile.w..(i + 1) * tile.w]);
+            }
+            self.dma_ops += 1;
+            self.bytes_out += (tile.h * tile.w * T::SIZE) as u64;
+        }
+    }
+
+    // slot 中是否正好是 (r0, c0) 处的块
+    fn holds(&self, slot: usize, r0: usize, c0: usize) -> bool {
+        matches!(&self.slots[slot], Some(tile) if tile.r0 == r0 && tile.c0 == c0)
+    }
+}
+
+/// 在片上存储上做分块矩阵乘法：A、B、C 各一个 tile×tile 的块缓冲区，
+/// 块的遍历顺序由 Sequence 决定。块已经驻留时不再传输，因此最内层循环不变的那个块只传输一次。
+#[derive(Clone, Debug)]
+pub struct ScratchpadCalculator<T: Element = u32> {
+    pub matrix_a: Matrix<T>,
+    pub matrix_b: Matrix<T>,
+    pub matrix_c: Matrix<T>,
+    pub scratchpad: Scratchpad<T>,
+    /// 块边长（元素个数）
+    pub tile: usize,
+}
+
+impl<T: Element> ScratchpadCalculator<T> {
+    /// tile 取三个块能同时放入片上存储的最大边长
+    pub fn new(
+        matrix_a: Matrix<T>,
+        matrix_b: Matrix<T>,
+        capacity: usize,
+    ) -> io::Result<ScratchpadCalculator<T>> {
+        let tile = ((capacity / (3 * T::SIZE)) as f64).sqrt() as usize;
+        Self::with_tile(matrix_a, matrix_b, capacity, tile)
+    }
+
+    pub fn with_tile(
+        matrix_a: Matrix<T>,
+        matrix_b: Matrix<T>,
+        capacity: usize,
+        tile: usize,
+    ) -> io::Result<ScratchpadCalculator<T>> {
+        if tile == 0 || 3 * tile * tile * T::SIZE > capacity {
+            return Err(invalid_input(format!(
+                "片上存储容量 {} 字节放不下 3 个 {}×{} 的 {} 块",
+                capacity,
+                tile,
+                tile,
+                T::NAME
+            )));
+        }
+        let dimension = matrix_a.dimension;
+        let matrix_c = Matrix {
+            id: 2,
+            dimension,
+            file_path: String::new(),
+            data: vec![vec![T::default(); dimension as usize]; dimension as usize],
+        };
+        Ok(ScratchpadCalculator {
+            matrix_a,
+            matrix_b,
+            matrix_c,
+            scratchpad: Scratchpad::new(capacity, 3),
+            tile,
+        })
+    }
+
+    pub fn calculate(&mut self, sequence: Sequence) -> io::Result<()> {
+        if self.matrix_a.dimension != self.matrix_b.dimension {
+            return Err(invalid_input("矩阵维度不匹配，无法相乘".to_string()));
+        }
+        let n = self.matrix_a.dimension as usize;
+        let t = self.tile;
+        let tiles = n.div_ceil(t);
+        // C 的块是否已经写回过部分和，再次调入时需要 DMA 而不是清零
+        let mut c_started = vec![false; tiles * tiles];
+
+        let [outer, middle, inner] = sequence.loop_order();
+        for x in 0..tiles {
+            for y in 0..tiles {
+                for z in 0..tiles {
+                    let mut pos = [0usize; 3];
+                    pos[outer as usize] = x;
+                    pos[middle as usize] = y;
+                    pos[inner as usize] = z;
+                    let (i0, j0, k0) = (pos[0] * t, pos[1] * t, pos[2] * t);
+                    let (h, w, d) = (
+                        usize::min(t, n - i0),
+                        usize::min(t, n - j0),
+                        usize::min(t, n - k0),
+                    );
+
+                    if !self.scratchpad.holds(SLOT_C, i0, j0) {
+                        self.scratchpad.dma_out(SLOT_C, &mut self.matrix_c);
+                        let started = &mut c_started[pos[0] * tiles + pos[1]];
+                        if *started {
+                            self.scratchpad
+                                .dma_in(SLOT_C, &self.matrix_c, i0, j0, h, w)?;
+                        } else {
+                            self.scratchpad.alloc(SLOT_C, i0, j0, h, w)?;
+                            *started = true;
+                        }
+                    }
+                    if !self.scratchpad.holds(SLOT_A, i0, k0) {
+                        self.scratchpad
+                            .dma_in(SLOT_A, &self.matrix_a, i0, k0, h, d)?;
+                    }
+                    if !self.scratchpad.holds(SLOT_B, k0, j0) {
+                        self.scratchpad
+                            .dma_in(SLOT_B, &self.matrix_b, k0, j0, d, w)?;
+                    }
+
+                    let [Some(a), Some(b), Some(c)] = &mut self.scratchpad.slots[..] else {
+                        unreachable!();
+                    };
+                    for i in 0..h {
+                        for k in 0..d {
+                            let a_ik = a.data[i * d + k];
+                            for j in 0..w {
+                                c.data[i * w + j] += a_ik * b.data[k * w + j];
+                            }
+                        }
+                    }
+                    c.dirty = true;
+                }
+            }
+        }
+        self.scratchpad.dma_out(SLOT_C, &mut self.matrix_c);
+
+        println!(
+            "> 片上存储分块乘法完成: 块大小 {}，DMA {} 次，调入 {} 字节，写回 {} 字节",
+            t, self.scratchpad.dma_ops, self.scratchpad.bytes_in, self.scratchpad.bytes_out
+        );
+        Ok(())
+    }
+
+    /// 用不经过片上存储的 ijk 乘法校验 matrix_c
+    pub fn verify(&self) -> bool {
+        let n = self.matrix_a.dimension as usize;
+        (0..n).all(|i| {
+            (0..n).all(|j| {
+                let mut sum = T::default();
+                for k in 0..n {
+                    sum += self.matrix_a.data[i][k] * self.matrix_b.data[k][j];
+                }
+                sum.approx_eq(self.matrix_c.data[i][j])
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(id: u32, n: u32) -> Matrix<u32> {
+        Matrix {
+            id,
+            dimension: n,
+            file_path: String::new(),
+            data: (0..n)
+                .map(|i| (0..n).map(|j| (i + 2 * j + id) % 5).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn tiled_product_matches_reference() {
+        // 5 阶矩阵、块边长 2，最后一行块和列块不完整
+        for sequence in [Sequence::Sijk, Sequence::Skji] {
+            let mut spm =
+                ScratchpadCalculator::with_tile(matrix(0, 5), matrix(1, 5), 48, 2).unwrap();
+            spm.calculate(sequence).unwrap();
+            assert!(spm.verify());
+            assert!(spm.scratchpad.peak_usage <= 48);
+        }
+    }
+
+    #[test]
+    fn rejects_tiles_that_do_not_fit() {
+        let err = ScratchpadCalculator::with_tile(matrix(0, 5), matrix(1, 5), 47, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn keeps_the_slot_when_placement_fails() {
+        let a = matrix(0, 4);
+        let mut scratchpad = Scratchpad::<u32>::new(32, 2);
+        scratchpad.alloc(0, 0, 0, 2, 2).unwrap();
+        scratchpad.slots[0].as_mut().unwrap().dirty = true;
+        // 容量不足：第 1 个缓冲区放不下 3×3 的块
+        assert!(scratchpad.dma_in(1, &a, 0, 0, 3, 3).is_err());
+        assert!(scratchpad.slots[1].is_none());
+        // 修改过的块不能被直接覆盖
+        assert!(scratchpad.dma_in(0, &a, 2, 2, 2, 2).is_err());
+        assert!(scratchpad.holds(0, 0, 0));
+        assert_eq!(scratchpad.dma_ops, 0);
+
+        let mut c = matrix(2, 4);
+        scratchpad.dma_out(0, &mut c);
+        scratchpad.dma_in(0, &a, 2, 2, 2, 2).unwrap();
+        assert!(scratchpad.holds(0, 2, 2));
+        assert_eq!(c.data[0][..2], [0, 0]);
+    }
+}
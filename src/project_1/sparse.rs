@@ -0,0 +1,718 @@
+#![allow(unused)]
+use super::format::invalid_data;
+use super::{BufferPool, Cache, Element, MarketData, Matrix, read_market, write_market_coordinate};
+use fs::*;
+use io::*;
+use rand::Rng;
+use rand::seq::index::sample;
+use std::*;
+
+/// 三元组（COO）格式：每个非零元素存为 (行, 列, 值)
+#[derive(Clone, Debug)]
+pub struct CooMatrix<T: Element = u32> {
+    pub rows: u32,
+    pub cols: u32,
+    pub row_idx: Vec<u32>,
+    pub col_idx: Vec<u32>,
+    pub values: Vec<T>,
+}
+
+/// 压缩行（CSR）格式
+#[derive(Clone, Debug)]
+pub struct CsrMatrix<T: Element = u32> {
+    pub rows: u32,
+    pub cols: u32,
+    pub row_ptr: Vec<u32>,
+    pub col_idx: Vec<u32>,
+    pub values: Vec<T>,
+}
+
+/// 压缩列（CSC）格式
+#[derive(Clone, Debug)]
+pub struct CscMatrix<T: Element = u32> {
+    pub rows: u32,
+    pub cols: u32,
+    pub col_ptr: Vec<u32>,
+    pub row_idx: Vec<u32>,
+    pub values: Vec<T>,
+}
+
+/// 稀疏矩阵的非零元分布
+#[derive(Clone, Debug)]
+pub enum SparsePattern {
+    /// 每个元素以 density 的概率非零
+    Random { density: f64 },
+    /// 只有 |i - j| <= bandwidth 的元素非零
+    Banded { bandwidth: u32 },
+    /// 第 r 行的非零元个数正比于 (r + 1)^(-exponent)，平均每行 avg_nnz_per_row 个
+    PowerLaw { avg_nnz_per_row: f64, exponent: f64 },
+}
+
+impl SparsePattern {
+    pub fn label(&self) -> String {
+        match self {
+            SparsePattern::Random { density } => format!("random_{}", density),
+            SparsePattern::Banded { bandwidth } => format!("banded_{}", bandwidth),
+            SparsePattern::PowerLaw {
+                avg_nnz_per_row,
+                exponent,
+            } => format!("powerlaw_{}_{}", avg_nnz_per_row, exponent),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SparseKernel {
+    SpmvCsr,
+    SpmvCsc,
+    SpmvCoo,
+    SpgemmCsr,
+}
+
+impl SparseKernel {
+    pub fn to_string(&self) -> &str {
+        match self {
+            SparseKernel::SpmvCsr => "SpmvCsr",
+            SparseKernel::SpmvCsc => "SpmvCsc",
+            SparseKernel::SpmvCoo => "SpmvCoo",
+            SparseKernel::SpgemmCsr => "SpgemmCsr",
+        }
+    }
+}
+
+impl<T: Element> CooMatrix<T> {
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// 按给定分布生成随机稀疏矩阵，非零元取值为 `Element::random` 的结果加 1，保证非零
+    pub fn generate(rows: u32, cols: u32, pattern: &SparsePattern) -> CooMatrix<T> {
+        let mut rng = rand::rng();
+        let mut coo = CooMatrix {
+            rows,
+            cols,
+            row_idx: Vec::new(),
+            col_idx: Vec::new(),
+            values: Vec::new(),
+        };
+        match pattern {
+            SparsePattern::Random { density } => {
+                for i in 0..rows {
+                    for j in 0..cols {
+                        if rng.random_bool(density.clamp(0.0, 1.0)) {
+                            coo.push(i, j, T::random(&mut rng) + T::ONE);
+                        }
+                    }
+                }
+            }
+            SparsePattern::Banded { bandwidth } => {
+                for i in 0..rows {
+                    let start = i.saturating_sub(*bandwidth);
+                    let end = u32::min(i.saturating_add(*bandwidth), cols.saturating_sub(1));
+                    for j in start..=end {
+                        if j < cols {
+                            coo.push(i, j, T::random(&mut rng) + T::ONE);
+                        }
+                    }
+                }
+            }
+            SparsePattern::PowerLaw {
+                avg_nnz_per_row,
+                exponent,
+            } => {
+                let weights: Vec<f64> = (0..rows)
+                    .map(|r| ((r + 1) as f64).powf(-exponent))
+                    .collect();
+                let total_weight: f64 = weights.iter().sum();
+                let total_nnz = avg_nnz_per_row * rows as f64;
+                for i in 0..rows {
+                    let degree = (total_nnz * weights[i as usize] / total_weight).round() as usize;
+                    let degree = usize::min(degree, cols as usize);
+                    let mut picked = sample(&mut rng, cols as usize, degree).into_vec();
+                    picked.sort_unstable();
+                    for j in picked {
+                        coo.push(i, j as u32, T::random(&mut rng) + T::ONE);
+                    }
+                }
+            }
+        }
+        coo
+    }
+
+    pub fn from_dense(matrix: &Matrix<T>) -> CooMatrix<T> {
+        let mut coo = CooMatrix {
+            rows: matrix.dimension,
+            cols: matrix.dimension,
+            row_idx: Vec::new(),
+            col_idx: Vec::new(),
+            values: Vec::new(),
+        };
+        for (i, row) in matrix.data.iter().enumerate() {
+            for (j, &v) in row.iter().enumerate() {
+                if !v.is_zero() {
+                    coo.push(i as u32, j as u32, v);
+                }
+            }
+        }
+        coo
+    }
+
+    fn from_market(market: MarketData<T>) -> CooMatrix<T> {
+        match market {
+            MarketData::Coordinate {
+                rows,
+                cols,
+                entries,
+            } => {
+                let mut coo = CooMatrix {
+                    rows,
+                    cols,
+                    row_idx: Vec::with_capacity(entries.len()),
+                    col_idx: Vec::with_capacity(entries.len()),
+                    values: Vec::with_capacity(entries.len()),
+                };
+                for (i, j, v) in entries {
+                    coo.push(i, j, v);
+                }
+                coo
+            }
+            MarketData::Dense { rows, cols, data } => {
+                let mut coo = CooMatrix {
+                    rows,
+                    cols,
+                    row_idx: Vec::new(),
+                    col_idx: Vec::new(),
+                    values: Vec::new(),
+                };
+                for (i, row) in data.iter().enumerate() {
+                    for (j, &v) in row.iter().enumerate() {
+                        if !v.is_zero() {
+                            coo.push(i as u32, j as u32, v);
+                        }
+                    }
+                }
+                coo
+            }
+        }
+    }
+
+    fn push(&mut self, i: u32, j: u32, v: T) {
+        self.row_idx.push(i);
+        self.col_idx.push(j);
+        self.values.push(v);
+    }
+
+    /// 读取稀疏矩阵文件：第一行为 "rows cols nnz"，之后每行一个 "row col value"（下标从 0 开始）。
+    /// `.mtx` 文件按 Matrix Market 读取，array 格式会去掉其中的 0。
+    pub fn from_file(file_path: &str) -> io::Result<CooMatrix<T>> {
+        let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let file_path = format!("{}/data/project_1/{}", cargo_manifest_dir, file_path);
+        if file_path.ends_with(".mtx") {
+            return Ok(Self::from_market(read_market(&file_path)?));
+        }
+        Self::read_text(&file_path)
+    }
+
+    // 读取 "rows cols nnz" 开头的文本稀疏矩阵文件，file_path 为完整路径
+    fn read_text(file_path: &str) -> io::Result<CooMatrix<T>> {
+        let file = File::open(file_path)?;
+        let file_len = file.metadata()?.len();
+        let reader = BufReader::new(file);
+        let mut lines = reader.lines();
+
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid_data(format!("稀疏矩阵文件为空: {}", file_path)))??;
+        let header: Vec<u64> = header
+            .split_whitespace()
+            .map(|v| v.parse::<u64>())
+            .collect::<result::Result<_, _>>()
+            .map_err(|e| invalid_data(format!("无法解析文件头: {}", e)))?;
+        if header.len() != 3 {
+            return Err(invalid_data(format!(
+                "文件头应为 \"rows cols nnz\"，实际为 {:?}",
+                header
+            )));
+        }
+        let dimension = |value: u64| {
+            u32::try_from(value)
+                .map_err(|_| invalid_data(format!("矩阵尺寸 {} 超出 u32 的范围", value)))
+        };
+        let (rows, cols) = (dimension(header[0])?, dimension(header[1])?);
+        // 每个非零元至少占 "i j v\n" 中的 4 个字节，先检查再按 nnz 分配
+        if header[2] > file_len / 4 {
+            return Err(invalid_data(format!(
+                "声明了 {} 个非零元，文件只有 {} 字节",
+                header[2], file_len
+            )));
+        }
+        let nnz = header[2] as usize;
+
+        let mut coo = CooMatrix {
+            rows,
+            cols,
+            row_idx: Vec::with_capacity(nnz),
+            col_idx: Vec::with_capacity(nnz),
+            values: Vec::with_capacity(nnz),
+        };
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let entry = match fields[..] {
+                [i, j, v] => i
+                    .parse::<u32>()
+                    .ok()
+                    .zip(j.parse::<u32>().ok())
+                    .zip(v.parse::<T>().ok())
+                    .filter(|((i, j), _)| *i < rows && *j < cols),
+                _ => None,
+            };
+            let ((i, j), v) =
+                entry.ok_or_else(|| invalid_data(format!("非法的非零元: \"{}\"", line)))?;
+            coo.push(i, j, v);
+        }
+        if coo.nnz() != nnz {
+            return Err(invalid_data(format!(
+                "文件头声明 {} 个非零元，实际读到 {} 个",
+                nnz,
+                coo.nnz()
+            )));
+        }
+        Ok(coo)
+    }
+
+    /// 写出稀疏矩阵文件，`.mtx` 文件按 Matrix Market 坐标格式写出
+    pub fn to_file(&self, file_path: &str) -> io::Result<()> {
+        let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let file_path = format!("{}/data/project_1/{}", cargo_manifest_dir, file_path);
+        if file_path.ends_with(".mtx") {
+            let entries: Vec<(u32, u32, T)> = (0..self.nnz())
+                .map(|p| (self.row_idx[p], self.col_idx[p], self.values[p]))
+                .collect();
+            write_market_coordinate(&file_path, self.rows, self.cols, &entries)?;
+            println!("> 稀疏矩阵已保存到文件: {}", file_path);
+            return Ok(());
+        }
+        let mut writer = BufWriter::new(File::create(&file_path)?);
+        writeln!(writer, "{} {} {}", self.rows, self.cols, self.nnz())?;
+        for p in 0..self.nnz() {
+            writeln!(
+                writer,
+                "{} {} {}",
+                self.row_idx[p], self.col_idx[p], self.values[p]
+            )?;
+        }
+        writer.flush()?;
+        println!("> 稀疏矩阵已保存到文件: {}", file_path);
+        Ok(())
+    }
+}
+
+impl<T: Element> CsrMatrix<T> {
+    pub fn from_coo(coo: &CooMatrix<T>) -> CsrMatrix<T> {
+        let (row_ptr, col_idx, values) =
+            compress(coo.rows, &coo.row_idx, &coo.col_idx, &coo.values);
+        CsrMatrix {
+            rows: coo.rows,
+            cols: coo.cols,
+            row_ptr,
+            col_idx,
+            values,
+        }
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn to_dense(&self) -> Vec<Vec<T>> {
+        let mut data = vec![vec![T::default(); self.cols as usize]; self.rows as usize];
+        for (i, row) in data.iter_mut().enumerate() {
+            for p in self.row_ptr[i] as usize..self.row_ptr[i + 1] as usize {
+                row[self.col_idx[p] as usize] += self.values[p];
+            }
+        }
+        data
+    }
+}
+
+impl<T: Element> CscMatrix<T> {
+    pub fn from_coo(coo: &CooMatrix<T>) -> CscMatrix<T> {
+        let (col_ptr, row_idx, values) =
+            compress(coo.cols, &coo.col_idx, &coo.row_idx, &coo.values);
+        CscMatrix {
+            rows: coo.rows,
+            cols: coo.cols,
+            col_ptr,
+            row_idx,
+            values,
+        }
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+}
+
+// 按 major 下标做计数排序，得到 (ptr, minor 下标, 值)；同一 major 内按 minor 升序
+fn compress<T: Element>(
+    major_len: u32,
+    major: &[u32],
+    minor: &[u32],
+    values: &[T],
+) -> (Vec<u32>, Vec<u32>, Vec<T>) {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by_key(|&p| (major[p], minor[p]));
+
+    let mut ptr = vec![0u32; major_len as usize + 1];
+    for &m in major {
+        ptr[m as usize + 1] += 1;
+    }
+    for m in 0..major_len as usize {
+        ptr[m + 1] += ptr[m];
+    }
+    let minor_sorted = order.iter().map(|&p| minor[p]).collect();
+    let values_sorted = order.iter().map(|&p| values[p]).collect();
+    (ptr, minor_sorted, values_sorted)
+}
+
+// Cache::touch 中各数组所在的存储区
+const REGION_A_PTR: u32 = 0;
+const REGION_A_IDX: u32 = 1;
+const REGION_A_VAL: u32 = 2;
+const REGION_B_PTR: u32 = 3;
+const REGION_B_IDX: u32 = 4;
+const REGION_B_VAL: u32 = 5;
+const REGION_X: u32 = 6;
+const REGION_Y: u32 = 7;
+const REGION_ACC: u32 = 8;
+const REGION_C_PTR: u32 = 9;
+const REGION_C_IDX: u32 = 10;
+const REGION_C_VAL: u32 = 11;
+
+/// 稀疏矩阵乘法的访存模拟：下标数组、数值数组、向量的每次读写都经过 Cache。
+/// 下标数组按 u32 计 4 字节，数值按 T 的大小计算地址。
+pub struct SparseCalculator<T: Element = u32> {
+    pub cache: Cache<T>,
+    pub cache_miss: u32,
+    pub cache_access: u32,
+    /// 可选的磁盘页缓冲池，Cache 未命中时经过它读取整行
+    pub buffer_pool: Option<BufferPool>,
+}
+
+impl<T: Element> SparseCalculator<T> {
+    pub fn new(cache: Cache<T>) -> SparseCalculator<T> {
+        SparseCalculator {
+            cache,
+            cache_miss: 0,
+            cache_access: 0,
+            buffer_pool: None,
+        }
+    }
+
+    fn access(&mut self, region: u32, address: usize, len: usize) {
+        self.cache_access += 1;
+        if !self.cache.touch_bytes(region, address, len) {
+            self.cache_miss += 1;
+            if let Some(pool) = &mut self.buffer_pool {
+                let sector_size = self.cache.sector_size as usize;
+                pool.access_range(region, address - address % sector_size, sector_size);
+            }
+        }
+    }
+
+    fn load<E: Element>(&mut self, region: u32, array: &[E], idx: usize) -> E {
+        self.access(region, idx * E::SIZE, E::SIZE);
+        array[idx]
+    }
+
+    fn store<E: Element>(&mut self, region: u32, array: &mut [E], idx: usize, value: E) {
+        self.access(region, idx * E::SIZE, E::SIZE);
+        array[idx] = value;
+    }
+
+    /// y = A·x，A 为 CSR：按行遍历，x 的访问是不规则的 gather
+    pub fn spmv_csr(&mut self, a: &CsrMatrix<T>, x: &[T]) -> Vec<T> {
+        let mut y = vec![T::default(); a.rows as usize];
+        for i in 0..a.rows as usize {
+            let start = self.load(REGION_A_PTR, &a.row_ptr, i) as usize;
+            let end = self.load(REGION_A_PTR, &a.row_ptr, i + 1) as usize;
+            let mut sum = T::default();
+            for p in start..end {
+                let j = self.load(REGION_A_IDX, &a.col_idx, p) as usize;
+                let v = self.load(REGION_A_VAL, &a.values, p);
+                sum += v * self.load(REGION_X, x, j);
+            }
+            self.store(REGION_Y, &mut y, i, sum);
+        }
+        y
+    }
+
+    /// y = A·x，A 为 CSC：按列遍历，y 的访问是不规则的 scatter
+    pub fn spmv_csc(&mut self, a: &CscMatrix<T>, x: &[T]) -> Vec<T> {
+        let mut y = vec![T::default(); a.rows as usize];
+        for j in 0..a.cols as usize {
+            let start = self.load(REGION_A_PTR, &a.col_ptr, j) as usize;
+            let end = self.load(REGION_A_PTR, &a.col_ptr, j + 1) as usize;
+            let xj = self.load(REGION_X, x, j);
+            for p in start..end {
+                let i = self.load(REGION_A_IDX, &a.row_idx, p) as usize;
+                let v = self.load(REGION_A_VAL, &a.values, p);
+                let yi = self.load(REGION_Y, &y, i);
+                self.store(REGION_Y, &mut y, i, yi + v * xj);
+            }
+        }
+        y
+    }
+
+    /// y = A·x，A 为 COO：行下标与列下标存放在两个数组中
+    pub fn spmv_coo(&mut self, a: &CooMatrix<T>, x: &[T]) -> Vec<T> {
+        let mut y = vec![T::default(); a.rows as usize];
+        for p in 0..a.nnz() {
+            let i = self.load(REGION_A_PTR, &a.row_idx, p) as usize;
+            let j = self.load(REGION_A_IDX, &a.col_idx, p) as usize;
+            let v = self.load(REGION_A_VAL, &a.values, p);
+            let xj = self.load(REGION_X, x, j);
+            let yi = self.load(REGION_Y, &y, i);
+            self.store(REGION_Y, &mut y, i, yi + v * xj);
+        }
+        y
+    }
+
+    /// C = A·B，A、B、C 均为 CSR，使用 Gustavson 算法和长度为 B.cols 的稠密累加器
+    pub fn spgemm_csr(&mut self, a: &CsrMatrix<T>, b: &CsrMatrix<T>) -> CsrMatrix<T> {
+        if a.cols != b.rows {
+            panic!("矩阵维度不匹配，无法相乘");
+        }
+        let mut acc = vec![T::default(); b.cols as usize];
+        let mut occupied = vec![false; b.cols as usize];
+        let mut c = CsrMatrix {
+            rows: a.rows,
+            cols: b.cols,
+            row_ptr: vec![0; a.rows as usize + 1],
+            col_idx: Vec::new(),
+            values: Vec::new(),
+        };
+
+        for i in 0..a.rows as usize {
+            let mut touched: Vec<usize> = Vec::new();
+            let a_start = self.load(REGION_A_PTR, &a.row_ptr, i) as usize;
+            let a_end = self.load(REGION_A_PTR, &a.row_ptr, i + 1) as usize;
+            for p in a_start..a_end {
+                let k = self.load(REGION_A_IDX, &a.col_idx, p) as usize;
+                let av = self.load(REGION_A_VAL, &a.values, p);
+                let b_start = self.load(REGION_B_PTR, &b.row_ptr, k) as usize;
+                let b_end = self.load(REGION_B_PTR, &b.row_ptr, k + 1) as usize;
+                for q in b_start..b_end {
+                    let j = self.load(REGION_B_IDX, &b.col_idx, q) as usize;
+                    let bv = self.load(REGION_B_VAL, &b.values, q);
+                    let old = self.load(REGION_ACC, &acc, j);
+                    self.store(REGION_ACC, &mut acc, j, old + av * bv);
+                    if !occupied[j] {
+                        occupied[j] = true;
+                        touched.push(j);
+                    }
+                }
+            }
+
+            // 把累加器中的本行结果按列号顺序写出，并清零累加器
+            touched.sort_unstable();
+            for j in touched {
+                let v = self.load(REGION_ACC, &acc, j);
+                self.store(REGION_ACC, &mut acc, j, T::default());
+                occupied[j] = false;
+                c.col_idx.push(0);
+                c.values.push(T::default());
+                let p = c.values.len() - 1;
+                self.store(REGION_C_IDX, &mut c.col_idx, p, j as u32);
+                self.store(REGION_C_VAL, &mut c.values, p, v);
+            }
+            let nnz = c.values.len() as u32;
+            self.store(REGION_C_PTR, &mut c.row_ptr, i + 1, nnz);
+        }
+        c
+    }
+}
+
+pub struct SparseEvaluator;
+
+impl SparseEvaluator {
+    /// 对每种 kernel 输出一个 CSV，每行对应一组 (维度, 分布, cache 参数)。
+    /// SpMV 的向量 x 与 SpGEMM 的 B 与 A 同分布随机生成；cache_line_sizes 以字节为单位。
+    pub fn evaluate<T: Element>(
+        dimensions: Vec<u32>,
+        patterns: Vec<SparsePattern>,
+        cache_line_sizes: Vec<u32>,
+        cache_line_numbers: Vec<u32>,
+        kernels: Vec<SparseKernel>,
+    ) -> io::Result<()> {
+        let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let output_dir = format!("{}/data/project_1/origin_data", cargo_manifest_dir);
+        fs::create_dir_all(&output_dir)?;
+        let mut rng = rand::rng();
+
+        for kernel in kernels {
+            let suffix = if T::NAME != u32::NAME {
+                format!("_{}", T::NAME)
+            } else {
+                String::new()
+            };
+            let file_path = format!("{}/sparse_{}{}.csv", output_dir, kernel.to_string(), suffix);
+            let mut writer = BufWriter::new(File::create(&file_path)?);
+            writeln!(
+                writer,
+                "dimension,pattern,nnz,cache_line_size,cache_line_number,cache_miss,cache_access"
+            )?;
+            for &dimension in &dimensions {
+                for pattern in &patterns {
+                    let coo_a = CooMatrix::<T>::generate(dimension, dimension, pattern);
+                    let coo_b = CooMatrix::<T>::generate(dimension, dimension, pattern);
+                    let x: Vec<T> = (0..dimension).map(|_| T::random(&mut rng)).collect();
+                    let csr_a = CsrMatrix::from_coo(&coo_a);
+                    let csc_a = CscMatrix::from_coo(&coo_a);
+                    let csr_b = CsrMatrix::from_coo(&coo_b);
+                    for &cache_line_size in &cache_line_sizes {
+                        for &cache_line_number in &cache_line_numbers {
+                            let cache = Cache::<T>::new(cache_line_number, cache_line_size);
+                            let mut calculator = SparseCalculator::new(cache);
+                            match kernel {
+                                SparseKernel::SpmvCsr => {
+                                    calculator.spmv_csr(&csr_a, &x);
+                                }
+                                SparseKernel::SpmvCsc => {
+                                    calculator.spmv_csc(&csc_a, &x);
+                                }
+                                SparseKernel::SpmvCoo => {
+                                    calculator.spmv_coo(&coo_a, &x);
+                                }
+                                SparseKernel::SpgemmCsr => {
+                                    calculator.spgemm_csr(&csr_a, &csr_b);
+                                }
+                            }
+                            writeln!(
+                                writer,
+                                "{},{},{},{},{},{},{}",
+                                dimension,
+                                pattern.label(),
+                                coo_a.nnz(),
+                                cache_line_size,
+                                cache_line_number,
+                                calculator.cache_miss,
+                                calculator.cache_access
+                            )?;
+                        }
+                    }
+                }
+            }
+            writer.flush()?;
+            println!("> 稀疏评测结果已保存到文件: {}", file_path);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 3×4，非零元故意不按行列顺序给出
+    fn sample_coo() -> CooMatrix<u32> {
+        let mut coo = CooMatrix {
+            rows: 3,
+            cols: 4,
+            row_idx: Vec::new(),
+            col_idx: Vec::new(),
+            values: Vec::new(),
+        };
+        for (i, j, v) in [(2, 3, 7), (0, 1, 2), (2, 0, 5), (0, 3, 4), (1, 2, 3)] {
+            coo.push(i, j, v);
+        }
+        coo
+    }
+
+    fn dense() -> Vec<Vec<u32>> {
+        vec![vec![0, 2, 0, 4], vec![0, 0, 3, 0], vec![5, 0, 0, 7]]
+    }
+
+    fn dense_mul(a: &[Vec<u32>], x: &[u32]) -> Vec<u32> {
+        a.iter()
+            .map(|row| row.iter().zip(x).map(|(a, x)| a * x).sum())
+            .collect()
+    }
+
+    #[test]
+    fn rejects_oversized_text_headers() {
+        let path = env::temp_dir().join(format!("project_1_sparse_{}.txt", std::process::id()));
+        for header in ["3 4 1000000000000", "4294967296 4 0", "3 4294967297 0"] {
+            fs::write(&path, format!("{}\n0 1 2\n", header)).unwrap();
+            let err = CooMatrix::<u32>::read_text(path.to_str().unwrap()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", header);
+        }
+        fs::write(&path, "3 4 1\n0 1 2\n").unwrap();
+        let coo = CooMatrix::<u32>::read_text(path.to_str().unwrap()).unwrap();
+        assert_eq!((coo.rows, coo.cols, coo.nnz()), (3, 4, 1));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn converts_between_formats() {
+        let coo = sample_coo();
+        let csr = CsrMatrix::from_coo(&coo);
+        assert_eq!(csr.row_ptr, [0, 2, 3, 5]);
+        assert_eq!(csr.col_idx, [1, 3, 2, 0, 3]);
+        assert_eq!(csr.values, [2, 4, 3, 5, 7]);
+        assert_eq!(csr.to_dense(), dense());
+
+        let csc = CscMatrix::from_coo(&coo);
+        assert_eq!(csc.col_ptr, [0, 1, 2, 3, 5]);
+        assert_eq!(csc.row_idx, [2, 0, 1, 0, 2]);
+        assert_eq!(csc.values, [5, 2, 3, 4, 7]);
+
+        let square = Matrix::<u32> {
+            id: 0,
+            dimension: 3,
+            file_path: String::new(),
+            data: vec![vec![0, 1, 0], vec![2, 0, 0], vec![0, 0, 3]],
+        };
+        let from_dense = CsrMatrix::from_coo(&CooMatrix::from_dense(&square));
+        assert_eq!(from_dense.nnz(), 3);
+        assert_eq!(from_dense.to_dense(), square.data);
+    }
+
+    #[test]
+    fn spmv_matches_dense_product() {
+        let coo = sample_coo();
+        let x = [1, 2, 3, 4];
+        let expected = dense_mul(&dense(), &x);
+        let mut calculator = SparseCalculator::new(Cache::<u32>::new(4, 16));
+        assert_eq!(
+            calculator.spmv_csr(&CsrMatrix::from_coo(&coo), &x),
+            expected
+        );
+        assert_eq!(
+            calculator.spmv_csc(&CscMatrix::from_coo(&coo), &x),
+            expected
+        );
+        assert_eq!(calculator.spmv_coo(&coo, &x), expected);
+        assert!(calculator.cache_miss > 0 && calculator.cache_miss <= calculator.cache_access);
+    }
+
+    #[test]
+    fn spgemm_matches_dense_product() {
+        let a = CsrMatrix::from_coo(&sample_coo());
+        // B = Aᵀ，C = A·Aᵀ 是 3×3
+        let mut coo_t = sample_coo();
+        mem::swap(&mut coo_t.row_idx, &mut coo_t.col_idx);
+        mem::swap(&mut coo_t.rows, &mut coo_t.cols);
+        let b = CsrMatrix::from_coo(&coo_t);
+        let mut calculator = SparseCalculator::new(Cache::<u32>::new(4, 16));
+        let c = calculator.spgemm_csr(&a, &b);
+        let a = dense();
+        let expected: Vec<Vec<u32>> = (0..3).map(|i| dense_mul(&a, &a[i])).collect::<Vec<_>>();
+        // A·Aᵀ 对称，第 i 行等于 A 与 A 第 i 行的乘积
+        assert_eq!(c.to_dense(), expected);
+    }
+}
@@ -0,0 +1,365 @@
+#![allow(unused)]
+use super::*;
+use fs::*;
+use io::*;
+use std::*;
+
+/// 一次经过 Cache 的访问，以及访问完成后被访问行的内容。
+/// 其余行在这次访问中不变，渲染时从全部无效的 Cache 开始依次回放即可得到每一步的完整状态。
+#[derive(Clone, Debug)]
+pub struct TraceEvent<T: Element = u32> {
+    /// 矩阵编号：0 为 A，1 为 B，2 为 C
+    pub matrix: u32,
+    pub i: usize,
+    pub j: usize,
+    pub address: Address,
+    /// 命中或被填入的行
+    pub line: usize,
+    pub hit: bool,
+    pub write: bool,
+    /// 未命中时被替换掉的有效行的标签
+    pub evicted: Option<u32>,
+    pub line_state: CacheLine<T>,
+}
+
+/// 访问轨迹的输出格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// 纯文本表格，适合写入文件
+    Plain,
+    /// 带 ANSI 颜色的表格：命中为绿色，未命中为红色，本次访问的行反色显示
+    Ansi,
+    /// HTML 时间线：每列是一次访问，每行是一个 Cache 行
+    Html,
+}
+
+const RESET: &str = "\x1b[0m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const INVERSE: &str = "\x1b[7m";
+
+fn matrix_name(id: u32) -> &'static str {
+    match id {
+        0 => "A",
+        1 => "B",
+        2 => "C",
+        _ => "?",
+    }
+}
+
+// 与 cache 同样大小、全部无效的行，回放的起点
+fn empty_lines<T: Element>(cache: &Cache<T>) -> Vec<CacheLine<T>> {
+    vec![CacheLine::new(cache.lines[0].cache_line_size); cache.line_number as usize]
+}
+
+pub struct CacheTracer;
+
+impl CacheTracer {
+    /// 在 dimension 阶的随机矩阵上按 sequence 计算一次，把每次访问的轨迹写到 output，
+    /// output 为 None 时打印到终端。轨迹会逐条打印整个 Cache，只适合 3、6 这样的小维度。
+    pub fn trace<T: Element>(
+        dimension: u32,
+        cache: Cache<T>,
+        sequence: Sequence,
+        register_model: RegisterModel,
+        format: TraceFormat,
+        output: Option<&str>,
+    ) -> io::Result<()> {
+        let mut rng = rand::rng();
+        let mut random_matrix = |id: u32| Matrix::<T> {
+            id,
+            dimension,
+            file_path: String::new(),
+            data: (0..dimension)
+                .map(|_| (0..dimension).map(|_| T::random(&mut rng)).collect())
+                .collect(),
+        };
+        let (matrix_a, matrix_b) = (random_matrix(0), random_matrix(1));
+        let mut calculator = Calculator::new(
+            matrix_a,
+            matrix_b,
+            cache,
+            &format!("origin_data/trace_matrix_c_{}.txt", dimension),
+        );
+        calculator.register_model = register_model.clone();
+        calculator.trace = Some(Vec::new());
+        calculator.calculate(sequence.clone());
+
+        let events = calculator.trace.take().unwrap();
+        let title = format!(
+            "{} {} 阶，{} 行 × {} 字节，{} 路组相联（{}），寄存器模型 {}",
+            sequence.to_string(),
+            dimension,
+            calculator.cache.line_number,
+            calculator.cache.lines[0].cache_line_size,
+            calculator.cache.ways,
+            calculator.cache.partition.label(),
+            register_model.label()
+        );
+        let content = Self::render(&events, &calculator.cache, dimension, &title, format);
+        match output {
+            Some(path) => {
+                fs::write(path, content)?;
+                println!("> 访问轨迹已保存到文件: {}", path);
+            }
+            None => print!("{}", content),
+        }
+        Ok(())
+    }
+
+    pub fn render<T: Element>(
+        events: &[TraceEvent<T>],
+        cache: &Cache<T>,
+        dimension: u32,
+        title: &str,
+        format: TraceFormat,
+    ) -> String {
+        match format {
+            TraceFormat::Html => Self::render_html(events, cache, dimension, title),
+            _ => Self::render_table(events, cache, dimension, title, format == TraceFormat::Ansi),
+        }
+    }
+
+    // 第 line 行中标签为 tag 的块是哪个矩阵的哪一段元素，例如 B[1][0]~B[1][3]
+    fn line_label<T: Element>(cache: &Cache<T>, dimension: u32, line: usize, tag: u32) -> String {
+        let cache_line_size = cache.lines[0].cache_line_size as usize;
+        let address = cache.line_address(line, tag);
+        let name = matrix_name((address / REGION_SPAN) as u32);
+        let n = dimension as usize;
+        let first = address % REGION_SPAN / T::SIZE;
+        let last = usize::min(first + cache_line_size / T::SIZE, n * n) - 1;
+        if first >= n * n {
+            return format!("{}[越界]", name);
+        }
+        if first == last {
+            format!("{}[{}][{}]", name, first / n, first % n)
+        } else {
+            format!(
+                "{}[{}][{}]~{}[{}][{}]",
+                name,
+                first / n,
+                first % n,
+                name,
+                last / n,
+                last % n
+            )
+        }
+    }
+
+    fn render_table<T: Element>(
+        events: &[TraceEvent<T>],
+        cache: &Cache<T>,
+        dimension: u32,
+        title: &str,
+        color: bool,
+    ) -> String {
+        let paint = |code: &str, text: String| {
+            if color {
+                format!("{}{}{}", code, text, RESET)
+            } else {
+                text
+            }
+        };
+        let mut out = format!("> 访问轨迹：{}\n", title);
+        let mut hits = 0;
+        let mut lines = empty_lines(cache);
+        for (step, event) in events.iter().enumerate() {
+            lines[event.line] = event.line_state.clone();
+            if event.hit {
+                hits += 1;
+            }
+            let result = if event.hit {
+                paint(GREEN, "命中".to_string())
+            } else {
+                let evicted = match event.evicted {
+                    Some(tag) => format!(
+                        "，替换 tag={}（{}）",
+                        tag,
+                        Self::line_label(cache, dimension, event.line, tag)
+                    ),
+                    None => String::new(),
+                };
+                paint(RED, format!("未命中{}", evicted))
+            };
+            out.push_str(&format!(
+                "\n#{:<4} {} {}[{}][{}]  tag={} index={} offset={}  {}\n",
+                step + 1,
+                if event.write { "写" } else { "读" },
+                matrix_name(event.matrix),
+                event.i,
+                event.j,
+                event.address.tag,
+                event.address.index,
+                event.address.offset,
+                result
+            ));
+            // 中文表头按两个字符宽度对齐
+            out.push_str("    行 | 有效 |      tag | 数据来源                 | 内容\n");
+            for (index, line) in lines.iter().enumerate() {
+                let (tag, label, data) = if line.valid {
+                    let data: Vec<String> = line.data.iter().map(|v| v.to_string()).collect();
+                    (
+                        line.tag.to_string(),
+                        Self::line_label(cache, dimension, index, line.tag),
+                        data.join(" "),
+                    )
+                } else {
+                    ("-".to_string(), "-".to_string(), String::new())
+                };
+                let row = format!(
+                    "  {:>4} | {:^4} | {:>8} | {:<24} | {}",
+                    index,
+                    if line.valid { 1 } else { 0 },
+                    tag,
+                    label,
+                    data
+                );
+                if index == event.line {
+                    if color {
+                        out.push_str(&format!("{}{}{}\n", INVERSE, row, RESET));
+                    } else {
+                        out.push_str(&format!("{} <\n", row));
+                    }
+                } else {
+                    out.push_str(&format!("{}\n", row));
+                }
+            }
+        }
+        out.push_str(&format!(
+            "\n> 共 {} 次访问，命中 {} 次，未命中 {} 次\n",
+            events.len(),
+            hits,
+            events.len() - hits
+        ));
+        out
+    }
+
+    fn render_html<T: Element>(
+        events: &[TraceEvent<T>],
+        cache: &Cache<T>,
+        dimension: u32,
+        title: &str,
+    ) -> String {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str(&format!("<title>{}</title>\n", title));
+        out.push_str(
+            "<style>\n\
+             body { font-family: monospace; }\n\
+             table { border-collapse: collapse; }\n\
+             th, td { border: 1px solid #ccc; padding: 2px 6px; white-space: nowrap; text-align: center; }\n\
+             th.row { position: sticky; left: 0; background: #f5f5f5; }\n\
+             td.hit { background: #c8f7c5; }\n\
+             td.miss { background: #f7c5c5; }\n\
+             td.empty { color: #bbb; }\n\
+             </style>\n</head>\n<body>\n",
+        );
+        let hits = events.iter().filter(|e| e.hit).count();
+        out.push_str(&format!(
+            "<h3>{}</h3>\n<p>共 {} 次访问，命中 {} 次，未命中 {} 次。绿色为命中，红色为未命中，悬停查看地址与行内数据。</p>\n",
+            title,
+            events.len(),
+            hits,
+            events.len() - hits
+        ));
+        out.push_str("<table>\n<tr><th class=\"row\">行</th>");
+        for (step, event) in events.iter().enumerate() {
+            out.push_str(&format!(
+                "<th title=\"tag={} index={} offset={}\">#{}<br>{}{}[{}][{}]</th>",
+                event.address.tag,
+                event.address.index,
+                event.address.offset,
+                step + 1,
+                if event.write { "写 " } else { "" },
+                matrix_name(event.matrix),
+                event.i,
+                event.j
+            ));
+        }
+        out.push_str("</tr>\n");
+        // 按时间回放一遍，同时向每个 Cache 行对应的表格行追加一格
+        let mut lines = empty_lines(cache);
+        let mut rows: Vec<String> = (0..lines.len())
+            .map(|index| format!("<tr><th class=\"row\">{}</th>", index))
+            .collect();
+        for event in events {
+            lines[event.line] = event.line_state.clone();
+            for (index, (line, row)) in lines.iter().zip(rows.iter_mut()).enumerate() {
+                let accessed = index == event.line;
+                let class = match (accessed, event.hit) {
+                    (true, true) => " class=\"hit\"",
+                    (true, false) => " class=\"miss\"",
+                    _ if !line.valid => " class=\"empty\"",
+                    _ => "",
+                };
+                if line.valid {
+                    let data: Vec<String> = line.data.iter().map(|v| v.to_string()).collect();
+                    let evicted = match (accessed, event.evicted) {
+                        (true, Some(tag)) => {
+                            format!("\n替换 {}", Self::line_label(cache, dimension, index, tag))
+                        }
+                        _ => String::new(),
+                    };
+                    row.push_str(&format!(
+                        "<td{} title=\"tag={} 数据: {}{}\">{}</td>",
+                        class,
+                        line.tag,
+                        data.join(" "),
+                        evicted,
+                        Self::line_label(cache, dimension, index, line.tag)
+                    ));
+                } else {
+                    row.push_str(&format!("<td{}>-</td>", class));
+                }
+            }
+        }
+        for row in rows {
+            out.push_str(&row);
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_reaches_the_final_cache() {
+        let n = 3;
+        let matrix = |id: u32| Matrix::<u32> {
+            id,
+            dimension: n,
+            file_path: String::new(),
+            data: (0..n)
+                .map(|i| (0..n).map(|j| i * n + j + id).collect())
+                .collect(),
+        };
+        let mut calculator = Calculator::new(matrix(0), matrix(1), Cache::new(4, 8), "");
+        calculator.matrix_c.file_path = env::temp_dir()
+            .join(format!("project_1_trace_{}.txt", process::id()))
+            .to_string_lossy()
+            .into_owned();
+        calculator.trace = Some(Vec::new());
+        calculator.calculate(Sequence::Sijk);
+        let events = calculator.trace.take().unwrap();
+        assert_eq!(events.len() as u32, calculator.cache_access);
+
+        let mut lines = empty_lines(&calculator.cache);
+        for event in &events {
+            lines[event.line] = event.line_state.clone();
+        }
+        for (replayed, line) in lines.iter().zip(&calculator.cache.lines) {
+            assert_eq!(replayed.valid, line.valid);
+            if line.valid {
+                assert_eq!(replayed.tag, line.tag);
+                assert_eq!(replayed.data, line.data);
+            }
+        }
+        let html = CacheTracer::render(&events, &calculator.cache, n, "", TraceFormat::Html);
+        assert_eq!(html.matches("<td").count(), events.len() * lines.len());
+        fs::remove_file(&calculator.matrix_c.file_path).unwrap();
+    }
+}
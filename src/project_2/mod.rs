@@ -1,7 +1,13 @@
 #![allow(unused)]
+use crate::storage::{
+    BlockDevice, Codecs, DEFAULT_BLOCK_SIZE, DeviceModel, FileDevice, IoCounters, IoPhases,
+    IoStats, RecordCodec, TextCodec, convert, parse_run_id, run_path,
+};
 use fs::*;
 use io::*;
 use rand::Rng;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use std::*;
 
@@ -10,6 +16,10 @@ pub struct SourceFileGenerator {
     pub min: i32,
     pub max: i32,
     pub output_file_path: String,
+    /// 输入文件写到哪个设备上，默认为文件系统
+    pub device: Arc<dyn BlockDevice>,
+    /// 输入文件的编码，默认为文本
+    pub codec: Arc<dyn RecordCodec>,
 }
 
 impl SourceFileGenerator {
@@ -22,20 +32,26 @@ impl SourceFileGenerator {
             min,
             max,
             output_file_path,
+            device: Arc::new(FileDevice),
+            codec: Arc::new(TextCodec),
         }
     }
-    pub fn generate_file(&self) {
+    pub fn generate_file(&self) -> io::Result<()> {
         println!("> 开始生成随机数字序列文件...");
-        let file = File::create(&self.output_file_path).expect("Unable to create file");
-        let mut writer = BufWriter::with_capacity(1024, file);
+        let file = self.device.create(Path::new(&self.output_file_path))?;
+        let mut writer = self
+            .codec
+            .encoder(Box::new(BufWriter::with_capacity(1024, file)));
         for _ in 0..self.n {
             let num = rand::rng().random_range(self.min..=self.max);
-            write!(writer, "{} ", num).expect("Unable to write data");
+            writer.write_record(num)?;
         }
+        writer.finish()?;
         println!(
             "> 随机数字序列文件生成完毕，文件路径：{}",
             self.output_file_path
         );
+        Ok(())
     }
 }
 
@@ -43,6 +59,10 @@ pub struct RunGenerator {
     pub run_length: u32,
     pub input_file_path: String,
     pub output_file_path: String,
+    /// 读输入、写顺串的块 I/O 计数
+    pub io: IoStats,
+    /// 按 codecs.input 读输入，按 codecs.runs 写顺串
+    pub codecs: Codecs,
 }
 
 impl RunGenerator {
@@ -55,52 +75,61 @@ impl RunGenerator {
             run_length,
             input_file_path,
             output_file_path,
+            io: IoStats::default(),
+            codecs: Codecs::default(),
         }
     }
 
-    pub fn generate_run_file(&self) {
+    /// 生成全部顺串。出错时删除顺串目录，不留下写了一半的顺串
+    pub fn generate_run_file(&self) -> io::Result<()> {
         println!("> 开始生成顺串文件...");
-        let input_file = File::open(&self.input_file_path).expect("Unable to open input file");
+        let output_dir = Path::new(&self.output_file_path);
+        self.io.device().create_dir_all(output_dir)?;
+        let result = self.write_runs();
+        if result.is_err() {
+            let _ = self.io.device().remove_dir_all(output_dir);
+        }
+        result?;
+        println!("> 顺串文件生成完毕，文件路径：{}", self.output_file_path);
+        Ok(())
+    }
 
-        let reader = BufReader::new(input_file);
+    fn write_runs(&self) -> io::Result<()> {
+        let mut reader = self
+            .io
+            .open_records(&self.input_file_path, self.codecs.input.as_ref())?;
 
         let mut run_count = 0;
 
         let mut numbers: Vec<i32> = Vec::with_capacity(self.run_length as usize);
-        let mut current_number = String::new();
-
-        // 逐字节迭代处理
-        for byte_result in reader.bytes() {
-            let byte = byte_result.expect("Unable to read byte");
-            let ch = byte as char;
-
-            if ch.is_digit(10) || (ch == '-' && current_number.is_empty()) {
-                current_number.push(ch);
-            } else if ch.is_whitespace() && !current_number.is_empty() {
-                // 遇到空格或换行，解析当前数字
-                if let Ok(num) = current_number.parse::<i32>() {
-                    numbers.push(num);
-                }
-                current_number.clear();
-
-                if numbers.len() >= self.run_length as usize {
-                    numbers.sort_unstable();
-                    let output_file_path =
-                        format!("{}/run_{}.txt", self.output_file_path, run_count);
-                    let output_file = File::create(&output_file_path)
-                        .expect("Unable to open or create output file");
-                    let mut writer = BufWriter::new(output_file);
-                    for num in &numbers {
-                        writer
-                            .write_all(format!("{} ", num).as_bytes())
-                            .expect("Unable to write data");
-                    }
-                    run_count += 1;
-                    numbers.clear();
-                }
+
+        while let Some(num) = reader.next_record()? {
+            numbers.push(num);
+            if numbers.len() >= self.run_length as usize {
+                self.write_run(run_count, &mut numbers)?;
+                run_count += 1;
             }
         }
-        println!("> 顺串文件生成完毕，文件路径：{}", self.output_file_path);
+        // 文件末尾不足 run_length 个的数字组成最后一个顺串
+        if !numbers.is_empty() {
+            self.write_run(run_count, &mut numbers)?;
+        }
+        Ok(())
+    }
+
+    // 排序后写出第 run_id 个顺串，并清空 numbers
+    fn write_run(&self, run_id: u64, numbers: &mut Vec<i32>) -> io::Result<()> {
+        numbers.sort_unstable();
+        let output_file_path = run_path(&self.output_file_path, run_id, self.codecs.runs.as_ref());
+        let mut writer = self
+            .io
+            .create_records(&output_file_path, self.codecs.runs.as_ref())?;
+        for &num in numbers.iter() {
+            writer.write_record(num)?;
+        }
+        writer.finish()?;
+        numbers.clear();
+        Ok(())
     }
 }
 
@@ -108,6 +137,12 @@ pub struct Merger {
     pub input_file_path: String,
     pub output_file_path: String,
     pub merge_pass_count: u32,
+    /// 归并时的块 I/O 计数
+    pub io: IoStats,
+    /// 每一趟归并的 I/O，第 i 项对应 merge_pass_{i + 1}
+    pub pass_io: Vec<IoCounters>,
+    /// 顺串按 codecs.runs 编码，最后一趟的结果按 codecs.output 编码
+    pub codecs: Codecs,
 }
 
 impl Merger {
@@ -121,60 +156,38 @@ impl Merger {
             input_file_path,
             output_file_path,
             merge_pass_count,
+            io: IoStats::default(),
+            pass_io: Vec::new(),
+            codecs: Codecs::default(),
         }
     }
 
-    pub fn merge(&mut self) {
+    /// 逐趟两两归并。某一趟出错时删除这一趟的目录，之前各趟的结果保持完整
+    pub fn merge(&mut self) -> io::Result<()> {
         println!("> 开始进行归并排序...");
         loop {
-            let dir = fs::read_dir(self.input_file_path.clone()).expect(
-                format!("Unable to read input directory: {}", self.input_file_path).as_str(),
-            );
-            let runs_count = dir.count() as u32;
-            let merged_runs_count = (runs_count + 1) / 2;
-            for idx in 0..merged_runs_count {
-                let run_1_index = idx * 2;
-                let run_2_index = idx * 2 + 1;
-                if run_2_index >= runs_count {
-                    let last_run_path = format!("{}/run_{}.txt", self.input_file_path, run_1_index);
-                    let output_run_path = format!(
-                        "{}/merge_pass_{}/run_{}.txt",
-                        self.output_file_path,
-                        self.merge_pass_count,
-                        run_1_index / 2
-                    );
-                    fs::create_dir_all(format!(
-                        "{}/merge_pass_{}",
-                        self.output_file_path, self.merge_pass_count
-                    ))
-                    .expect("Unable to create output directory");
-                    fs::copy(&last_run_path, &output_run_path)
-                        .expect("Unable to copy last run file");
-                    println!(
-                        "> 复制未归并的最后一个顺串文件：{} 到 {}",
-                        last_run_path, output_run_path
-                    );
-                    break;
-                }
-                println!("> 归并第 {} 和 第 {} 个顺串...", run_1_index, run_2_index);
-                let run_1_path = format!("{}/run_{}.txt", self.input_file_path, run_1_index);
-                let run_2_path = format!("{}/run_{}.txt", self.input_file_path, run_2_index);
-                println!("> 归并文件路径：{} 和 {}", run_1_path, run_2_path);
-                let output_run_path = format!(
-                    "{}/merge_pass_{}/run_{}.txt",
-                    self.output_file_path, self.merge_pass_count, idx
-                );
-                fs::create_dir_all(format!(
-                    "{}/merge_pass_{}",
-                    self.output_file_path, self.merge_pass_count
-                ))
-                .expect("Unable to create output directory");
-                self.merge_two_runs(&run_1_path, &run_2_path, &output_run_path);
-            }
-            self.input_file_path = format!(
+            // 只数按 codecs.runs 编码的顺串，目录中以其他编码留下的文件不参与归并
+            let runs_count = self
+                .io
+                .device()
+                .list_files(Path::new(&self.input_file_path))?
+                .iter()
+                .filter_map(|path| path.file_name())
+                .filter(|name| {
+                    parse_run_id(&name.to_string_lossy(), self.codecs.runs.as_ref()).is_some()
+                })
+                .count() as u32;
+            let pass_dir = format!(
                 "{}/merge_pass_{}",
                 self.output_file_path, self.merge_pass_count
             );
+            let io_before = self.io.snapshot();
+            if let Err(e) = self.merge_pass(runs_count, &pass_dir) {
+                let _ = self.io.device().remove_dir_all(Path::new(&pass_dir));
+                return Err(e);
+            }
+            self.pass_io.push(self.io.snapshot().since(&io_before));
+            self.input_file_path = pass_dir;
             println!(
                 "> 归并第 {} 次完成，下一次归并目录：{}",
                 self.merge_pass_count, self.input_file_path
@@ -184,59 +197,115 @@ impl Merger {
                 break;
             }
         }
+        Ok(())
     }
-    pub fn merge_two_runs(&self, run_1_path: &str, run_2_path: &str, output_run_path: &str) {
-        let file1 = File::open(run_1_path).expect("Unable to open run 1 file");
-        let file2 = File::open(run_2_path).expect("Unable to open run 2 file");
-        let reader1 = BufReader::new(file1);
-        let reader2 = BufReader::new(file2);
-
-        let mut iter1 = reader1
-            .split(b' ')
-            .filter_map(|res| res.ok())
-            .filter_map(|bytes| String::from_utf8(bytes).ok())
-            .filter_map(|s| s.trim().parse::<i32>().ok());
-        let mut iter2 = reader2
-            .split(b' ')
-            .filter_map(|res| res.ok())
-            .filter_map(|bytes| String::from_utf8(bytes).ok())
-            .filter_map(|s| s.trim().parse::<i32>().ok());
-
-        let mut output_file =
-            File::create(output_run_path).expect("Unable to create output run file");
-        let mut writer = BufWriter::new(&mut output_file);
-
-        let mut val1 = iter1.next();
-        let mut val2 = iter2.next();
+
+    // 把 input_file_path 中的 runs_count 个顺串两两归并到 pass_dir；最后一趟的结果按输出编码写出
+    fn merge_pass(&self, runs_count: u32, pass_dir: &str) -> io::Result<()> {
+        self.io.device().create_dir_all(Path::new(pass_dir))?;
+        let output_codec = if runs_count <= 2 {
+            self.codecs.output.as_ref()
+        } else {
+            self.codecs.runs.as_ref()
+        };
+        let merged_runs_count = runs_count.div_ceil(2);
+        for idx in 0..merged_runs_count {
+            let run_1_index = idx * 2;
+            let run_2_index = idx * 2 + 1;
+            if run_2_index >= runs_count {
+                let last_run_path = run_path(
+                    &self.input_file_path,
+                    run_1_index as u64,
+                    self.codecs.runs.as_ref(),
+                );
+                let output_run_path = run_path(pass_dir, idx as u64, output_codec);
+                // 经计数的读写复制，复制同样产生 I/O；编码不同时逐条转换
+                if output_codec.name() == self.codecs.runs.name() {
+                    let mut reader = self.io.open(&last_run_path)?;
+                    let mut writer = self.io.create(&output_run_path)?;
+                    io::copy(&mut reader, &mut writer)?;
+                    writer.flush()?;
+                } else {
+                    convert(
+                        &self.io,
+                        &last_run_path,
+                        self.codecs.runs.as_ref(),
+                        &output_run_path,
+                        output_codec,
+                    )?;
+                }
+                println!(
+                    "> 复制未归并的最后一个顺串文件：{} 到 {}",
+                    last_run_path.display(),
+                    output_run_path.display()
+                );
+                break;
+            }
+            println!("> 归并第 {} 和 第 {} 个顺串...", run_1_index, run_2_index);
+            let runs_codec = self.codecs.runs.as_ref();
+            let run_1_path = run_path(&self.input_file_path, run_1_index as u64, runs_codec);
+            let run_2_path = run_path(&self.input_file_path, run_2_index as u64, runs_codec);
+            println!(
+                "> 归并文件路径：{} 和 {}",
+                run_1_path.display(),
+                run_2_path.display()
+            );
+            let output_run_path = run_path(pass_dir, idx as u64, output_codec);
+            self.merge_two_runs(&run_1_path, &run_2_path, &output_run_path, output_codec)?;
+        }
+        Ok(())
+    }
+
+    pub fn merge_two_runs(
+        &self,
+        run_1_path: &Path,
+        run_2_path: &Path,
+        output_run_path: &Path,
+        output_codec: &dyn RecordCodec,
+    ) -> io::Result<()> {
+        let mut reader1 = self
+            .io
+            .open_records(run_1_path, self.codecs.runs.as_ref())?;
+        let mut reader2 = self
+            .io
+            .open_records(run_2_path, self.codecs.runs.as_ref())?;
+
+        let mut writer = self.io.create_records(output_run_path, output_codec)?;
+
+        let mut val1 = reader1.next_record()?;
+        let mut val2 = reader2.next_record()?;
 
         while val1.is_some() || val2.is_some() {
             match (val1, val2) {
                 (Some(v1), Some(v2)) => {
                     if v1 <= v2 {
-                        write!(writer, "{} ", v1).expect("Unable to write data");
-                        val1 = iter1.next();
+                        writer.write_record(v1)?;
+                        val1 = reader1.next_record()?;
                     } else {
-                        write!(writer, "{} ", v2).expect("Unable to write data");
-                        val2 = iter2.next();
+                        writer.write_record(v2)?;
+                        val2 = reader2.next_record()?;
                     }
                 }
                 (Some(v1), None) => {
-                    write!(writer, "{} ", v1).expect("Unable to write data");
-                    val1 = iter1.next();
+                    writer.write_record(v1)?;
+                    val1 = reader1.next_record()?;
                 }
                 (None, Some(v2)) => {
-                    write!(writer, "{} ", v2).expect("Unable to write data");
-                    val2 = iter2.next();
+                    writer.write_record(v2)?;
+                    val2 = reader2.next_record()?;
                 }
                 (None, None) => break,
             }
         }
-        println!("> 归并完成，输出文件路径：{}", output_run_path);
+        writer.finish()?;
+        println!("> 归并完成，输出文件路径：{}", output_run_path.display());
+        Ok(())
     }
 }
 
-pub fn run(run_length: u32) -> Duration {
-    let run_generator = RunGenerator::new(
+/// 按 codecs 指定的编码排序，返回排序耗时，以及顺串生成和每一趟归并的块 I/O
+pub fn run(run_length: u32, codecs: &Codecs) -> io::Result<(Duration, Vec<(String, IoCounters)>)> {
+    let mut run_generator = RunGenerator::new(
         run_length,
         "nums.txt".to_string(),
         "merge_passes/merge_pass_0".to_string(),
@@ -246,34 +315,263 @@ pub fn run(run_length: u32) -> Duration {
         "merge_passes/merge_pass_0".to_string(),
         "merge_passes".to_string(),
     );
+    // 数据通常都在页缓存中，另用机械硬盘模型估计真实的 I/O 耗时
+    let io_stats = IoStats::simulated(DEFAULT_BLOCK_SIZE, DeviceModel::hdd());
+    run_generator.io = io_stats.clone();
+    run_generator.codecs = codecs.clone();
+    merger.io = io_stats.clone();
+    merger.codecs = codecs.clone();
+    let mut io_phases = IoPhases::new(&io_stats);
 
     let start_time = Instant::now();
 
-    run_generator.generate_run_file();
-    merger.merge();
+    run_generator.generate_run_file()?;
+    io_phases.mark("run_generation");
+    merger.merge()?;
 
     let end_time = Instant::now();
+    io_phases.extend(
+        merger
+            .pass_io
+            .iter()
+            .enumerate()
+            .map(|(i, counters)| (format!("merge_pass_{}", i + 1), *counters)),
+    );
 
     let elapsed_time = end_time.duration_since(start_time);
 
     println!("> 排序耗时 {} 毫秒。", elapsed_time.as_millis());
+    let total_io = io_phases.total();
+    println!(
+        "> 共 {} 次块读、{} 次块写（块大小 {} 字节）。",
+        total_io.block_reads,
+        total_io.block_writes,
+        io_stats.block_size()
+    );
+    println!("> 模拟磁盘 I/O 耗时 {:.1} 毫秒。", total_io.modelled_ms());
 
-    elapsed_time
+    Ok((elapsed_time, io_phases.phases))
 }
 
-pub fn evaluate(min_run_length: u32, max_run_length: u32, step: u32, n: u64) {
+pub fn evaluate(
+    min_run_length: u32,
+    max_run_length: u32,
+    step: u32,
+    n: u64,
+    codecs: &Codecs,
+) -> io::Result<()> {
     //在data/project_2/origin_data.csv中记录不同run_length下的排序时间
     let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let output_file_path = format!("{}/data/project_2/origin_data.csv", cargo_manifest_dir);
-    let file = File::create(&output_file_path).expect("Unable to create file");
+    let file = File::create(&output_file_path)?;
     let mut writer = BufWriter::with_capacity(1024, file);
-    writeln!(writer, "run_length,elapsed_time_ms").expect("Unable to write data");
-    let source_generator = SourceFileGenerator::new(n, -1000, 1000, "nums.txt".to_string());
-    source_generator.generate_file();
+    writeln!(
+        writer,
+        "run_length,elapsed_time_ms,modelled_io_ms,run_block_reads,run_block_writes,merge_block_reads,merge_block_writes,bytes_read,bytes_written,file_opens"
+    )?;
+    // 每个阶段的 I/O 明细记录在 data/project_2/io_phases.csv
+    let io_phases_file_path = format!("{}/data/project_2/io_phases.csv", cargo_manifest_dir);
+    let io_phases_file = File::create(&io_phases_file_path)?;
+    let mut io_phases_writer = BufWriter::new(io_phases_file);
+    writeln!(
+        io_phases_writer,
+        "run_length,phase,{}",
+        IoCounters::CSV_HEADER
+    )?;
+    let mut source_generator = SourceFileGenerator::new(n, -1000, 1000, "nums.txt".to_string());
+    source_generator.codec = codecs.input.clone();
+    source_generator.generate_file()?;
+    println!("> 编码：{}", codecs.describe());
     for run_length in (min_run_length..=max_run_length).step_by(step as usize) {
-        let elapsed_time = run(run_length);
-        writeln!(writer, "{},{}", run_length, elapsed_time.as_millis())
-            .expect("Unable to write data");
+        let (elapsed_time, phases) = run(run_length, codecs)?;
+        let mut run_io = IoCounters::default();
+        let mut merge_io = IoCounters::default();
+        for (phase, counters) in &phases {
+            writeln!(
+                io_phases_writer,
+                "{},{},{}",
+                run_length,
+                phase,
+                counters.to_csv()
+            )?;
+            if phase == "run_generation" {
+                run_io += *counters;
+            } else {
+                merge_io += *counters;
+            }
+        }
+        let total_io = run_io + merge_io;
+        writeln!(
+            writer,
+            "{},{},{:.3},{},{},{},{},{},{},{}",
+            run_length,
+            elapsed_time.as_millis(),
+            total_io.modelled_ms(),
+            run_io.block_reads,
+            run_io.block_writes,
+            merge_io.block_reads,
+            merge_io.block_writes,
+            total_io.bytes_read,
+            total_io.bytes_written,
+            total_io.file_opens
+        )?;
     }
+    writer.flush()?;
+    io_phases_writer.flush()?;
     println!("> 评估数据已保存到 {}", output_file_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project_3::RunValidator;
+    use crate::storage::{FaultPlan, FaultyDevice, MemoryDevice};
+
+    const INPUT: &str = "/mem/project_2/nums.txt";
+    const RUNS_DIR: &str = "/mem/project_2/merge_passes/merge_pass_0";
+
+    // 内存设备上 1050 个数字的输入文件，按 codecs.input 编码
+    fn memory_input(codecs: &Codecs) -> MemoryDevice {
+        let device = MemoryDevice::new();
+        let source_generator = SourceFileGenerator {
+            n: 1050,
+            min: -1000,
+            max: 1000,
+            output_file_path: INPUT.into(),
+            device: Arc::new(device.clone()),
+            codec: codecs.input.clone(),
+        };
+        source_generator.generate_file().unwrap();
+        device
+    }
+
+    fn memory_io(device: &MemoryDevice) -> IoStats {
+        IoStats::with_device(DEFAULT_BLOCK_SIZE, Arc::new(device.clone()))
+    }
+
+    fn faulty_io(device: &MemoryDevice, plan: FaultPlan) -> IoStats {
+        IoStats::with_device(
+            DEFAULT_BLOCK_SIZE,
+            Arc::new(FaultyDevice::new(Arc::new(device.clone()), plan)),
+        )
+    }
+
+    // 每个顺串 100 个数字；1050 不是 100 的倍数，最后一个顺串只有 50 个数字
+    fn run_generator(io: IoStats, codecs: &Codecs) -> RunGenerator {
+        RunGenerator {
+            run_length: 100,
+            input_file_path: INPUT.into(),
+            output_file_path: RUNS_DIR.into(),
+            io,
+            codecs: codecs.clone(),
+        }
+    }
+
+    fn merger(io: IoStats, codecs: &Codecs) -> Merger {
+        Merger {
+            input_file_path: RUNS_DIR.into(),
+            output_file_path: "/mem/project_2/merge_passes".into(),
+            merge_pass_count: 1,
+            io,
+            pass_io: Vec::new(),
+            codecs: codecs.clone(),
+        }
+    }
+
+    // 校验 merger 最后一趟的输出，返回元素个数
+    fn validate_output(device: &MemoryDevice, merger: &Merger) -> io::Result<u64> {
+        let output = run_path(&merger.input_file_path, 0, merger.codecs.output.as_ref());
+        RunValidator::validate_sorted_output(device, &merger.codecs, Path::new(INPUT), &output)
+    }
+
+    #[test]
+    fn sorts_on_memory_device() {
+        let codecs = Codecs::default();
+        let device = memory_input(&codecs);
+        let io = memory_io(&device);
+        run_generator(io.clone(), &codecs)
+            .generate_run_file()
+            .unwrap();
+        let mut merger = merger(io, &codecs);
+        merger.merge().unwrap();
+
+        assert_eq!(validate_output(&device, &merger).unwrap(), 1050);
+        assert_eq!(merger.pass_io.len(), 4);
+        assert!(device.files().iter().all(|path| path.starts_with("/mem")));
+    }
+
+    #[test]
+    fn run_generation_fails_cleanly() {
+        let codecs = Codecs::default();
+        let device = memory_input(&codecs);
+        let io = faulty_io(
+            &device,
+            FaultPlan {
+                capacity: Some(2000),
+                ..FaultPlan::default()
+            },
+        );
+        let err = run_generator(io, &codecs).generate_run_file().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageFull);
+        assert!(!device.exists(Path::new(RUNS_DIR)));
+    }
+
+    #[test]
+    fn merge_fails_cleanly() {
+        let plans = [
+            FaultPlan {
+                fail_read: Some(6),
+                ..FaultPlan::default()
+            },
+            FaultPlan {
+                fail_write: Some(8),
+                ..FaultPlan::default()
+            },
+        ];
+        let codecs = Codecs::default();
+        for plan in plans {
+            let device = memory_input(&codecs);
+            run_generator(memory_io(&device), &codecs)
+                .generate_run_file()
+                .unwrap();
+
+            let mut merger = merger(faulty_io(&device, plan.clone()), &codecs);
+            assert!(merger.merge().is_err(), "{:?}", plan);
+            // 失败的那一趟不留下目录，之前各趟仍然完整
+            let failed_pass = format!(
+                "/mem/project_2/merge_passes/merge_pass_{}",
+                merger.merge_pass_count
+            );
+            assert!(!device.exists(Path::new(&failed_pass)));
+            assert_eq!(merger.pass_io.len() as u32, merger.merge_pass_count - 1);
+        }
+    }
+
+    #[test]
+    fn truncated_merge_output_fails_validation() {
+        // 写入本身都成功，但每个文件只留下前 1000 字节
+        let codecs = Codecs::default();
+        let device = memory_input(&codecs);
+        run_generator(memory_io(&device), &codecs)
+            .generate_run_file()
+            .unwrap();
+        let io = faulty_io(
+            &device,
+            FaultPlan {
+                truncate_after: Some(1000),
+                ..FaultPlan::default()
+            },
+        );
+        let mut merger = merger(io, &codecs);
+        let err = merger
+            .merge()
+            .and_then(|()| {
+                let output = run_path(&merger.input_file_path, 0, merger.codecs.output.as_ref());
+                assert!(device.size(&output)? <= 1000);
+                validate_output(&device, &merger)
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
 }
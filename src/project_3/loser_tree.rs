@@ -0,0 +1,376 @@
+use std::cmp::Ordering;
+
+/// 败者树：在 k 个叶子中反复选出最小者。叶子 i 位于完全二叉树的位置 k + i，
+/// losers[p] 存放内部节点 p 上比赛的败者，losers[0] 存放最终胜者。
+/// 比较规则由 compare 给出，Less 表示左边胜出。
+pub struct LoserTree<T, F = fn(&T, &T) -> Ordering>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    losers: Vec<usize>,
+    leaves: Vec<T>,
+    compare: F,
+}
+
+impl<T: Ord> LoserTree<T> {
+    /// 按 T 自身的全序比较
+    pub fn from_ord(leaves: Vec<T>) -> Self {
+        Self::new(leaves, T::cmp)
+    }
+}
+
+impl<T, F> LoserTree<T, F>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    pub fn new(leaves: Vec<T>, compare: F) -> Self {
+        if leaves.is_empty() {
+            panic!("不能使用0个元素构建败者树。");
+        }
+        let mut tree = Self {
+            losers: vec![0; leaves.len()],
+            leaves,
+            compare,
+        };
+        tree.build();
+        tree
+    }
+
+    /// 叶子个数，构造时保证大于 0
+    pub fn k(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.compare)(a, b)
+    }
+
+    /// 自底向上进行一轮完整的锦标赛。直接修改叶子（leaves_mut）后需要重新调用
+    pub fn build(&mut self) {
+        let k = self.leaves.len();
+        // winners[p] 记录以 p 为根的子树的胜者
+        let mut winners = vec![0; 2 * k];
+        for i in 0..k {
+            winners[k + i] = i;
+        }
+        for p in (1..k).rev() {
+            let (left, right) = (winners[2 * p], winners[2 * p + 1]);
+            if self.loses(left, right) {
+                winners[p] = right;
+                self.losers[p] = left;
+            } else {
+                winners[p] = left;
+                self.losers[p] = right;
+            }
+        }
+        self.losers[0] = winners[1];
+    }
+
+    /// 胜者所在的叶子编号
+    pub fn winner(&self) -> usize {
+        self.losers[0]
+    }
+
+    pub fn winner_value(&self) -> &T {
+        &self.leaves[self.winner()]
+    }
+
+    pub fn leaf(&self, idx: usize) -> &T {
+        &self.leaves[idx]
+    }
+
+    pub fn leaves(&self) -> &[T] {
+        &self.leaves
+    }
+
+    pub fn leaves_mut(&mut self) -> &mut [T] {
+        &mut self.leaves
+    }
+
+    /// 用 value 替换第 idx 个叶子并重赛，返回原来的叶子
+    pub fn replace(&mut self, idx: usize, value: T) -> T {
+        let old = std::mem::replace(&mut self.leaves[idx], value);
+        self.replay(idx);
+        old
+    }
+
+    /// 第 idx 个叶子的值变化后，沿它到根的路径重赛
+    pub fn replay(&mut self, idx: usize) {
+        let mut winner = idx;
+        let mut p = (self.leaves.len() + idx) / 2;
+        while p > 0 {
+            let loser = self.losers[p];
+            if self.loses(winner, loser) {
+                self.losers[p] = winner;
+                winner = loser;
+            }
+            p /= 2;
+        }
+        self.losers[0] = winner;
+    }
+
+    // 叶子 a 是否输给叶子 b；相等时保持 a 胜出
+    fn loses(&self, a: usize, b: usize) -> bool {
+        (self.compare)(&self.leaves[a], &self.leaves[b]) == Ordering::Greater
+    }
+}
+
+/// 败者树中一个位置的状态。派生的全序即比较规则：所有 Active 小于所有 Frozen，
+/// Frozen 先按顺串编号再按值比较，Exhausted 最大。状态与值分开存放，
+/// 值的全部取值（包括 i32::MAX 这样的极值）都能正常排序。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ElementState<T = i32> {
+    /// 属于当前顺串（或当前归并）的元素
+    Active(T),
+    /// 比当前顺串的最后输出小，只能进入编号为 run 的后续顺串
+    Frozen { run: u64, value: T },
+    /// 输入已读完，该位置不再有元素
+    Exhausted,
+}
+
+impl<T> ElementState<T> {
+    /// 与派生的 Ord 相同的规则，但值之间用 compare 比较
+    pub fn cmp_by<F: Fn(&T, &T) -> Ordering>(&self, other: &Self, compare: &F) -> Ordering {
+        use ElementState::*;
+        match (self, other) {
+            (Active(a), Active(b)) => compare(a, b),
+            (
+                Frozen {
+                    run: run_a,
+                    value: a,
+                },
+                Frozen {
+                    run: run_b,
+                    value: b,
+                },
+            ) => run_a.cmp(run_b).then_with(|| compare(a, b)),
+            (Exhausted, Exhausted) => Ordering::Equal,
+            (Active(_), _) | (Frozen { .. }, Exhausted) => Ordering::Less,
+            _ => Ordering::Greater,
+        }
+    }
+
+    pub fn from_option(value: Option<T>) -> Self {
+        match value {
+            Some(value) => ElementState::Active(value),
+            None => ElementState::Exhausted,
+        }
+    }
+}
+
+/// 工作区状态之间的比较，包装置换选择的值比较
+type StateCompare<T> = Box<dyn Fn(&ElementState<T>, &ElementState<T>) -> Ordering>;
+
+/// 置换选择：工作区保存 k 个元素，每次输出最小的、仍能接在当前顺串后面的元素。
+/// 比 compare 意义下刚输出的值小的新元素被冻结到下一个顺串，
+/// 随机输入下顺串的平均长度约为 2k。
+pub struct ReplacementSelection<T, F = fn(&T, &T) -> Ordering>
+where
+    F: Fn(&T, &T) -> Ordering + Clone,
+{
+    tree: LoserTree<ElementState<T>, StateCompare<T>>,
+    compare: F,
+    run: u64,
+}
+
+impl<T: Ord + 'static> ReplacementSelection<T> {
+    pub fn from_ord(k: usize, initial_elements: Vec<T>) -> Self {
+        Self::new(k, initial_elements, T::cmp)
+    }
+}
+
+impl<T: 'static, F> ReplacementSelection<T, F>
+where
+    F: Fn(&T, &T) -> Ordering + Clone + 'static,
+{
+    /// initial_elements 是最先读入的至多 k 个元素，不足 k 个时其余位置视为已读完
+    pub fn new(k: usize, initial_elements: Vec<T>, compare: F) -> Self {
+        if initial_elements.len() > k {
+            panic!("初始元素多于工作区大小 k。");
+        }
+        let mut leaves: Vec<ElementState<T>> = initial_elements
+            .into_iter()
+            .map(ElementState::Active)
+            .collect();
+        leaves.resize_with(k, || ElementState::Exhausted);
+        let tree_compare = compare.clone();
+        Self {
+            tree: LoserTree::new(leaves, Box::new(move |a, b| a.cmp_by(b, &tree_compare))),
+            compare,
+            run: 0,
+        }
+    }
+
+    /// 取出下一个输出元素及其顺串编号（从 0 开始），并把 next 放入它空出的位置。
+    /// next 是输入中的下一个元素，输入读完后传 None；所有元素输出完毕时返回 None。
+    /// 输出完毕后不能再传入元素，否则它无处存放，debug 构建中会 panic。
+    pub fn pop(&mut self, next: Option<T>) -> Option<(u64, T)> {
+        if let ElementState::Frozen { run, .. } = self.tree.winner_value() {
+            // 当前顺串中已没有元素：冻结的元素进入新顺串
+            self.run = *run;
+            for state in self.tree.leaves_mut() {
+                *state = match std::mem::replace(state, ElementState::Exhausted) {
+                    ElementState::Frozen { value, .. } => ElementState::Active(value),
+                    other => other,
+                };
+            }
+            self.tree.build();
+        }
+
+        let winner = self.tree.winner();
+        let replacement = match next {
+            Some(value) => {
+                // 胜者已读完说明输入早已结束
+                debug_assert!(
+                    matches!(self.tree.leaf(winner), ElementState::Active(_)),
+                    "所有元素已输出完毕，又传入了新元素"
+                );
+                let ElementState::Active(last) = self.tree.leaf(winner) else {
+                    return None;
+                };
+                if (self.compare)(&value, last) == Ordering::Less {
+                    ElementState::Frozen {
+                        run: self.run + 1,
+                        value,
+                    }
+                } else {
+                    ElementState::Active(value)
+                }
+            }
+            None => ElementState::Exhausted,
+        };
+        match self.tree.replace(winner, replacement) {
+            ElementState::Active(value) => Some((self.run, value)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 依次传入 input 中的元素，读完后传 None，按顺串编号收集输出
+    fn runs<T: 'static, F>(mut selection: ReplacementSelection<T, F>, input: Vec<T>) -> Vec<Vec<T>>
+    where
+        F: Fn(&T, &T) -> Ordering + Clone + 'static,
+    {
+        let mut runs: Vec<Vec<T>> = Vec::new();
+        let mut input = input.into_iter();
+        while let Some((run, value)) = selection.pop(input.next()) {
+            if runs.len() as u64 == run {
+                runs.push(Vec::new());
+            }
+            assert_eq!(runs.len() as u64, run + 1, "顺串编号必须连续");
+            runs.last_mut().unwrap().push(value);
+        }
+        assert!(selection.pop(None).is_none());
+        runs
+    }
+
+    #[test]
+    fn frozen_elements_start_the_next_run() {
+        // 1 和 2 比刚输出的 5、8 小，被冻结到顺串 1；工作区全部冻结时才开始新顺串
+        let selection = ReplacementSelection::from_ord(2, vec![5, 3]);
+        assert_eq!(
+            runs(selection, vec![8, 1, 2, 9]),
+            [vec![3, 5, 8], vec![1, 2, 9]]
+        );
+    }
+
+    #[test]
+    fn extreme_values_are_ordinary_elements() {
+        let selection = ReplacementSelection::from_ord(2, vec![i32::MAX, 0]);
+        assert_eq!(
+            runs(selection, vec![i32::MIN, i32::MAX, i32::MIN]),
+            [vec![0, i32::MAX, i32::MAX], vec![i32::MIN, i32::MIN]]
+        );
+        assert!(
+            ElementState::Active(i32::MAX)
+                < ElementState::Frozen {
+                    run: 1,
+                    value: i32::MIN
+                }
+        );
+        assert!(
+            ElementState::Frozen {
+                run: 1,
+                value: i32::MAX
+            } < ElementState::Exhausted
+        );
+    }
+
+    #[test]
+    fn short_input_is_exhausted() {
+        // 初始元素不足 k 个：其余位置从一开始就是 Exhausted
+        let selection = ReplacementSelection::from_ord(4, vec![2, 1]);
+        assert_eq!(runs(selection, Vec::new()), [vec![1, 2]]);
+
+        let mut tree = LoserTree::from_ord(vec![
+            ElementState::Active(4),
+            ElementState::Exhausted,
+            ElementState::Active(1),
+        ]);
+        assert_eq!(tree.winner(), 2);
+        tree.replace(2, ElementState::Exhausted);
+        assert_eq!(tree.winner(), 0);
+        tree.replace(0, ElementState::Exhausted);
+        assert_eq!(*tree.winner_value(), ElementState::Exhausted);
+    }
+
+    #[test]
+    fn merges_non_integer_keys() {
+        // 字节串键的 3 路归并，叶子用 ElementState 标记读完的输入
+        let inputs = [
+            vec!["apple", "fig"],
+            vec!["banana"],
+            vec!["cherry", "date", "elder"],
+        ];
+        let mut cursors = vec![0; inputs.len()];
+        let mut tree = LoserTree::from_ord(
+            inputs
+                .iter()
+                .map(|input| ElementState::from_option(input.first().map(|s| s.as_bytes())))
+                .collect(),
+        );
+        let mut merged = Vec::new();
+        while let ElementState::Active(key) = *tree.winner_value() {
+            let winner = tree.winner();
+            merged.push(key);
+            cursors[winner] += 1;
+            let next = inputs[winner].get(cursors[winner]).map(|s| s.as_bytes());
+            tree.replace(winner, ElementState::from_option(next));
+        }
+        let expected: Vec<&[u8]> = ["apple", "banana", "cherry", "date", "elder", "fig"]
+            .iter()
+            .map(|s| s.as_bytes())
+            .collect();
+        assert_eq!(merged, expected);
+        assert_eq!(tree.k(), 3);
+    }
+
+    #[test]
+    fn custom_comparator_reverses_runs() {
+        // 降序比较：比刚输出的值大的元素被冻结
+        let selection = ReplacementSelection::new(2, vec![5_i64, 3], |a: &i64, b: &i64| b.cmp(a));
+        assert_eq!(
+            runs(selection, vec![8, 1, 2, 9]),
+            [vec![5, 3, 1], vec![8, 2], vec![9]]
+        );
+
+        // 只比较时间戳，时间戳相同时先到的叶子胜出
+        let events = vec![(20_i64, "b"), (10, "a"), (20, "c"), (10, "d")];
+        let tree = LoserTree::new(events, |x: &(i64, &str), y: &(i64, &str)| x.0.cmp(&y.0));
+        assert_eq!(tree.winner(), 1);
+        assert_eq!(tree.compare(tree.leaf(0), tree.leaf(2)), Ordering::Equal);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "又传入了新元素")]
+    fn rejects_input_after_exhaustion() {
+        let mut selection = ReplacementSelection::from_ord(2, vec![1]);
+        assert_eq!(selection.pop(None), Some((0, 1)));
+        selection.pop(Some(2));
+    }
+}
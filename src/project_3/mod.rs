@@ -1,13 +1,21 @@
 #![allow(unused)]
+use crate::storage::{
+    BlockDevice, Codecs, DEFAULT_BLOCK_SIZE, DeviceModel, FileDevice, IoCounters, IoPhases,
+    IoStats, RecordCodec, RecordDecoder, RecordEncoder, TextCodec, convert, parse_run_id, run_path,
+};
 use fs::*;
 use io::*;
 use rand::Rng;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use std::*;
 
+mod loser_tree;
+pub use loser_tree::*;
+
 pub fn run() {
     let config = ExperimentConfig::default();
     if let Err(err) = ExperimentRunner::evaluate(config) {
@@ -29,6 +37,10 @@ pub struct SourceFileGenerator {
     pub min: i32,
     pub max: i32,
     pub output_file_path: PathBuf,
+    /// 输入文件写到哪个设备上，默认为文件系统
+    pub device: Arc<dyn BlockDevice>,
+    /// 输入文件的编码，默认为文本
+    pub codec: Arc<dyn RecordCodec>,
 }
 
 impl SourceFileGenerator {
@@ -52,199 +64,68 @@ impl SourceFileGenerator {
     ) -> io::Result<Self> {
         let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         let output_dir = resolve_manifest_relative_path(&cargo_manifest_dir, output_dir);
-        fs::create_dir_all(&output_dir)?;
         let output_file_path = output_dir.join(file_name);
         Ok(Self {
             n,
             min,
             max,
             output_file_path,
+            device: Arc::new(FileDevice),
+            codec: Arc::new(TextCodec),
         })
     }
 
     pub fn generate_file(&self) -> io::Result<()> {
         println!("> 开始生成随机数字序列文件...");
         if let Some(parent) = self.output_file_path.parent() {
-            fs::create_dir_all(parent)?;
+            self.device.create_dir_all(parent)?;
         }
-        let file = File::create(&self.output_file_path)?;
-        let mut writer = BufWriter::with_capacity(1024, file);
+        let file = self.device.create(&self.output_file_path)?;
+        let mut writer = self
+            .codec
+            .encoder(Box::new(BufWriter::with_capacity(1024, file)));
         for _ in 0..self.n {
             let num = rand::rng().random_range(self.min..=self.max);
-            write!(writer, "{} ", num)?;
+            writer.write_record(num)?;
         }
         println!(
             "> 随机数字序列文件生成完毕，文件路径：{}",
             self.output_file_path.display()
         );
-        writer.flush()?;
+        writer.finish()?;
         Ok(())
     }
 }
 
-pub struct LoserTree {
-    pub losers: Vec<usize>,
-    pub work_area: Vec<(i32, bool)>, // (value, is_frozen)
-    pub k: usize,
+/// 按某种编码逐个读取元素，不把整个文件读入内存
+pub struct InputElementReader {
+    decoder: Box<dyn RecordDecoder>,
 }
 
-impl LoserTree {
-    pub fn new(initial_elements: Vec<i32>) -> Self {
-        let k = initial_elements.len();
-
-        if k == 0 {
-            panic!("不能使用0个元素构建败者树。");
-        }
-
-        let mut tree = Self {
-            // losers[0] 存最终胜者, losers[1..k] 存内部节点败者
-            losers: vec![0; k], // 全部初始化为指向第一个元素
-            work_area: initial_elements.into_iter().map(|v| (v, false)).collect(),
-            k,
-        };
-
-        tree.build();
-
-        tree
-    }
-
-    pub fn get_winner_idx(&self) -> usize {
-        self.losers[0]
-    }
-
-    pub fn unfreeze_all_elements(&mut self) {
-        for i in 0..self.k {
-            if self.work_area[i].1 == true {
-                self.work_area[i].1 = false;
-            }
-        }
-    }
-
-    pub fn build(&mut self) {
-        // 假设所有内部节点都指向一个已知的“超级胜者”（例如索引0）
-        let initial_winner_idx = 0;
-        for i in 0..self.k {
-            self.losers[i] = initial_winner_idx;
-        }
-
-        // 依次将所有 k 个 (现在已解冻的) 元素重新调整进树中
-        for i in 0..self.k {
-            self.replay_match(i);
-        }
-    }
-
-    fn get_key(&self, idx: usize) -> i32 {
-        let (value, is_frozen) = self.work_area[idx];
-
-        if is_frozen { i32::MAX } else { value }
+impl InputElementReader {
+    /// 创建一个新的文本读取器，指定一个大的内部缓冲区
+    pub fn new(file: File) -> io::Result<Self> {
+        Ok(Self::from_reader(BufReader::with_capacity(
+            8 * 1024 * 1024,
+            file,
+        )))
     }
 
-    pub fn replace_and_replay(&mut self, leaf_idx: usize, new_element: (i32, bool)) {
-        self.work_area[leaf_idx] = new_element;
-
-        self.replay_match(leaf_idx);
+    /// 从已有的缓冲输入读取文本，例如 IoStats::open 得到的按块计数的输入
+    pub fn from_reader(reader: impl BufRead + Send + 'static) -> Self {
+        Self::with_codec(&TextCodec, reader)
     }
 
-    /// 从指定的叶子节点开始，向上进行比赛
-    fn replay_match(&mut self, leaf_idx: usize) {
-        let mut winner_idx = leaf_idx;
-
-        // p 是当前比赛发生的“内部节点”的索引
-        // (k + i) / 2 将叶子节点(0..k-1)映射到树的下半部分
-        let mut p = (self.k + leaf_idx) / 2;
-
-        // 循环直到树根 (p == 0)
-        while p > 0 {
-            // 'loser_idx' 是存储在节点 p 的“旧败者”的索引
-            let loser_idx = self.losers[p];
-
-            if self.get_key(winner_idx) > self.get_key(loser_idx) {
-                // 如果当前胜者 'winner_idx' 输了 (因为它值更大)
-
-                // 将它(新的败者 'winner_idx') 存入 losers[p]
-                self.losers[p] = winner_idx;
-
-                // “旧的败者” 'loser_idx' 晋级，成为新的 'winner_idx'，
-                winner_idx = loser_idx;
-            }
-            // 移动到父节点
-            p /= 2;
+    /// 按 codec 解码已有的缓冲输入
+    pub fn with_codec(codec: &dyn RecordCodec, reader: impl BufRead + Send + 'static) -> Self {
+        Self {
+            decoder: codec.decoder(Box::new(reader)),
         }
-
-        self.losers[0] = winner_idx;
-    }
-}
-
-pub struct InputElementReader {
-    bytes: std::io::Bytes<BufReader<File>>,
-}
-
-impl InputElementReader {
-    /// 创建一个新的读取器，指定一个大的内部缓冲区
-    pub fn new(file: File) -> io::Result<Self> {
-        let reader = BufReader::with_capacity(8 * 1024 * 1024, file);
-        Ok(Self {
-            bytes: reader.bytes(),
-        })
     }
 
     /// 返回 Option<i32>，模拟迭代器
     pub fn next_element(&mut self) -> io::Result<Option<i32>> {
-        let mut num_buf = Vec::new();
-        let mut found_digit_or_sign = false;
-
-        // 1. 跳过所有开头的空白字符
-        let mut first_byte = None;
-        loop {
-            match self.bytes.next() {
-                Some(Ok(b)) => {
-                    if !b.is_ascii_whitespace() {
-                        // 找到了数字（或负号）的第一个字节
-                        first_byte = Some(b);
-                        break;
-                    }
-                    // else: 是空白，继续跳过
-                }
-                Some(Err(e)) => return Err(e), // I/O 错误
-                None => return Ok(None),       // 正常的文件末尾 (EOF)
-            }
-        }
-
-        // 2. 收集数字的剩余字节
-        if let Some(b) = first_byte {
-            num_buf.push(b);
-            found_digit_or_sign = true;
-
-            loop {
-                match self.bytes.next() {
-                    Some(Ok(b)) => {
-                        if b.is_ascii_whitespace() {
-                            // 数字结束
-                            break;
-                        }
-                        // else: 是数字的一部分
-                        num_buf.push(b);
-                    }
-                    Some(Err(e)) => return Err(e), // I/O 错误
-                    None => break,                 // 正常 EOF，这是最后一个数字
-                }
-            }
-        }
-
-        // 3. 如果我们找到了字节，就解析它们
-        if found_digit_or_sign {
-            // 将字节 [50, 51, 52] 转换为字符串 "123"
-            let s = std::str::from_utf8(&num_buf)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-
-            // 将字符串 "123" 解析为 i32
-            s.parse::<i32>()
-                .map(Some)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-        } else {
-            // 只找到了空白
-            Ok(None)
-        }
+        self.decoder.next_record()
     }
 }
 
@@ -253,10 +134,14 @@ pub struct RunGenerator {
     pub runs_dir: PathBuf,
     pub output_file_path: PathBuf,
     pub buffer_r: InputElementReader,
-    pub buffer_w1: BufWriter<File>,
-    pub buffer_w2: BufWriter<File>,
+    /// 当前正在写入的顺串，只在 generate_run_file 期间打开
+    pub buffer_w: Option<Box<dyn RecordEncoder>>,
     pub run_count: u64,
-    pub loser_tree: LoserTree,
+    pub selection: ReplacementSelection<i32>,
+    /// 读输入、写顺串的块 I/O 计数
+    pub io: IoStats,
+    /// 输入与顺串的编码
+    pub codecs: Codecs,
 }
 
 impl RunGenerator {
@@ -273,45 +158,75 @@ impl RunGenerator {
         input_file_path: impl AsRef<Path>,
         runs_dir: impl AsRef<Path>,
         k: usize,
+    ) -> io::Result<Self> {
+        Self::with_io(input_file_path, runs_dir, k, IoStats::default())
+    }
+
+    /// 与 with_dirs 相同，I/O 计入给定的 io（构造时读入的前 k 个元素也计入）
+    pub fn with_io(
+        input_file_path: impl AsRef<Path>,
+        runs_dir: impl AsRef<Path>,
+        k: usize,
+        io: IoStats,
+    ) -> io::Result<Self> {
+        Self::with_codecs(input_file_path, runs_dir, k, io, Codecs::default())
+    }
+
+    /// 与 with_io 相同，按 codecs.input 读输入、按 codecs.runs 写顺串
+    pub fn with_codecs(
+        input_file_path: impl AsRef<Path>,
+        runs_dir: impl AsRef<Path>,
+        k: usize,
+        io: IoStats,
+        codecs: Codecs,
     ) -> io::Result<Self> {
         let cargo_manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         let input_file_path = resolve_manifest_relative_path(&cargo_manifest_dir, input_file_path);
         let runs_dir = resolve_manifest_relative_path(&cargo_manifest_dir, runs_dir);
-        fs::create_dir_all(&runs_dir)?;
+        io.device().create_dir_all(&runs_dir)?;
 
-        let mut buffer_r = InputElementReader::new(File::open(&input_file_path)?)?;
+        let mut buffer_r =
+            InputElementReader::with_codec(codecs.input.as_ref(), io.open(&input_file_path)?);
 
         let mut initial_elements = Vec::with_capacity(k);
-        for _ in 0..k {
-            initial_elements.push(buffer_r.next_element()?.unwrap_or(i32::MAX));
+        while initial_elements.len() < k {
+            match buffer_r.next_element()? {
+                Some(value) => initial_elements.push(value),
+                None => break,
+            }
         }
 
-        let output_file_path = runs_dir.join("run_0.txt");
-        let buffer_w1 = BufWriter::with_capacity(1024, File::create(&output_file_path)?);
-        let buffer_w2 = BufWriter::with_capacity(1024, File::create(&output_file_path)?);
-        let loser_tree = LoserTree::new(initial_elements);
+        let output_file_path = run_path(&runs_dir, 0, codecs.runs.as_ref());
+        let selection = ReplacementSelection::from_ord(k, initial_elements);
         Ok(Self {
             input_file_path,
             runs_dir,
             output_file_path,
             buffer_r,
-            buffer_w1,
-            buffer_w2,
+            buffer_w: None,
             run_count: 0,
-            loser_tree,
+            selection,
+            io,
+            codecs,
         })
     }
 
-    fn update_output_file_path(&mut self, w: usize) -> io::Result<()> {
-        self.output_file_path = self.runs_dir.join(format!("run_{}.txt", self.run_count));
-        if w == 1 {
-            self.buffer_w1.flush()?;
-            self.buffer_w1 = BufWriter::with_capacity(1024, File::create(&self.output_file_path)?);
-        } else {
-            self.buffer_w2.flush()?;
-            self.buffer_w2 = BufWriter::with_capacity(1024, File::create(&self.output_file_path)?);
-        };
-        self.run_count += 1;
+    // 结束当前顺串（若有），并为第 run_count 个顺串创建输出文件
+    fn update_output_file_path(&mut self) -> io::Result<()> {
+        self.finish_run()?;
+        self.output_file_path = run_path(&self.runs_dir, self.run_count, self.codecs.runs.as_ref());
+        self.buffer_w = Some(
+            self.io
+                .create_records(&self.output_file_path, self.codecs.runs.as_ref())?,
+        );
+        Ok(())
+    }
+
+    fn finish_run(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.buffer_w.take() {
+            writer.finish()?;
+            self.run_count += 1;
+        }
         Ok(())
     }
 
@@ -348,55 +263,46 @@ impl RunGenerator {
         Ok(())
     }
 
+    /// 清空 device 上的目录，保留目录本身；目录不存在时什么也不做
+    pub fn clean_directory(device: &dyn BlockDevice, dir_path: &Path) -> io::Result<()> {
+        if !device.exists(dir_path) {
+            return Ok(());
+        }
+        device.remove_dir_all(dir_path)?;
+        device.create_dir_all(dir_path)
+    }
+
+    /// 生成全部顺串。出错时删除已写出的顺串，不在 runs_dir 中留下不完整的结果
     pub fn generate_run_file(&mut self) -> io::Result<()> {
-        RunGenerator::clean_directory_contents(&self.runs_dir)?;
-
-        let mut pre_winner_value = i32::MIN;
-        let mut all_elements_processed = false;
-
-        while !all_elements_processed {
-            let winner_idx = self.loser_tree.get_winner_idx();
-            let winner_key = self.loser_tree.get_key(winner_idx); // 使用 get_key() 获取“有效”键值
-
-            if winner_key == i32::MAX {
-                self.update_output_file_path(if self.run_count % 2 == 0 { 1 } else { 2 })?;
-                self.loser_tree.unfreeze_all_elements();
-                self.loser_tree.build();
-                pre_winner_value = i32::MIN;
-                let new_winner_key = self.loser_tree.get_key(self.loser_tree.get_winner_idx());
-                if new_winner_key == i32::MAX {
-                    all_elements_processed = true; // 退出主循环
-                }
-                continue;
-            }
+        // 先清空目录再创建第一个顺串，避免写入已被删除的文件
+        RunGenerator::clean_directory(self.io.device().as_ref(), &self.runs_dir)?;
+        self.run_count = 0;
+
+        let result = self.write_runs();
+        if result.is_err() {
+            // 先关闭当前顺串，它析构时的写出不会再落到清空后的目录里
+            self.buffer_w = None;
+            let _ = RunGenerator::clean_directory(self.io.device().as_ref(), &self.runs_dir);
+            self.run_count = 0;
+        }
+        result
+    }
 
-            if self.run_count % 2 == 0 {
-                write!(self.buffer_w1, "{} ", winner_key)?;
-            } else {
-                write!(self.buffer_w2, "{} ", winner_key)?;
+    fn write_runs(&mut self) -> io::Result<()> {
+        loop {
+            let next_element = self.buffer_r.next_element()?;
+            let Some((run, value)) = self.selection.pop(next_element) else {
+                break;
+            };
+            // 置换选择给出的顺串编号变化时，结束当前顺串并创建下一个
+            if self.buffer_w.is_none() || run != self.run_count {
+                self.update_output_file_path()?;
             }
-            pre_winner_value = winner_key;
-
-            let next_element = self
-                .buffer_r
-                .next_element()? // 文件读完，用 MAX 填充
-                .unwrap_or(i32::MAX);
-
-            let replacement: (i32, bool);
-            if next_element < pre_winner_value {
-                replacement = (next_element, true);
-            } else {
-                replacement = (next_element, false);
+            if let Some(writer) = self.buffer_w.as_mut() {
+                writer.write_record(value)?;
             }
-
-            self.loser_tree.replace_and_replay(winner_idx, replacement);
-        }
-
-        if self.run_count % 2 == 0 {
-            self.buffer_w1.flush()?;
-        } else {
-            self.buffer_w2.flush()?;
         }
+        self.finish_run()?;
 
         Ok(())
     }
@@ -426,6 +332,12 @@ pub struct Merger {
     pub input_file_path: String,
     pub output_file_path: String,
     pub merge_plan: Option<Box<MergeNode>>,
+    /// 构建合并计划与合并时的块 I/O 计数
+    pub io: IoStats,
+    /// 上一次 merge_loop 中每次双路合并的 I/O，按执行顺序
+    pub merge_io: Vec<(String, IoCounters)>,
+    /// 顺串与中间结果按 codecs.runs 编码，最终输出按 codecs.output 编码
+    pub codecs: Codecs,
 }
 
 impl Merger {
@@ -434,11 +346,22 @@ impl Merger {
         let input_file_path = format!("{}/data/project_3/{}", cargo_manifest_dir, input_file_path);
         let output_file_path =
             format!("{}/data/project_3/{}", cargo_manifest_dir, output_file_path);
-        let merge_pass_count = 1;
+        Self::with_paths(input_file_path, output_file_path, IoStats::default())
+    }
+
+    /// 在 io 的设备上把 runs_dir 中的顺串合并为 output_file，临时文件放在 runs_dir 旁的 temp 目录
+    pub fn with_paths(
+        runs_dir: impl AsRef<Path>,
+        output_file: impl AsRef<Path>,
+        io: IoStats,
+    ) -> Self {
         Self {
-            input_file_path,
-            output_file_path,
+            input_file_path: runs_dir.as_ref().to_string_lossy().to_string(),
+            output_file_path: output_file.as_ref().to_string_lossy().to_string(),
             merge_plan: None,
+            io,
+            merge_io: Vec::new(),
+            codecs: Codecs::default(),
         }
     }
     pub fn build_merge_plan(&mut self) -> io::Result<()> {
@@ -446,17 +369,21 @@ impl Merger {
 
         let mut heap: BinaryHeap<Box<MergeNode>> = BinaryHeap::new();
 
-        for entry in fs::read_dir(&self.input_file_path)? {
-            let entry = entry?;
-            let file_name = entry.file_name().to_string_lossy().to_string();
-
-            if let Some(run_id) = parse_run_id(&file_name) {
-                let file_path = format!("{}/{}", self.input_file_path, file_name);
-
-                let file = File::open(file_path)?;
-                let mut reader = InputElementReader::new(file)?;
+        for file_path in self
+            .io
+            .device()
+            .list_files(Path::new(&self.input_file_path))?
+        {
+            let file_name = file_path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+
+            if let Some(run_id) = parse_run_id(&file_name, self.codecs.runs.as_ref()) {
+                let mut reader = self
+                    .io
+                    .open_records(&file_path, self.codecs.runs.as_ref())?;
                 let mut count: u64 = 0;
-                while reader.next_element()?.is_some() {
+                while reader.next_record()?.is_some() {
                     count += 1;
                 }
 
@@ -507,7 +434,7 @@ impl Merger {
         Ok(())
     }
 
-    pub fn merge_loop(&self) -> io::Result<()> {
+    pub fn merge_loop(&mut self) -> io::Result<()> {
         let root_node = match &self.merge_plan {
             Some(plan) => plan,
             None => {
@@ -516,59 +443,110 @@ impl Merger {
             }
         };
 
-        // 准备临时目录 ".../data/project_3/temp"
-        let base_path = self.input_file_path.strip_suffix("/runs").ok_or_else(|| {
+        // 准备临时目录：顺串目录旁的 temp，例如 ".../data/project_3/temp"
+        let base_path = Path::new(&self.input_file_path).parent().ok_or_else(|| {
             io::Error::new(io::ErrorKind::InvalidInput, "无效的 input_file_path 格式")
         })?;
 
-        let temp_dir_path = format!("{}/temp", base_path);
+        let temp_dir_path = format!("{}/temp", base_path.display());
+        let device = self.io.device().clone();
 
         // 清理并(重新)创建临时目录
-        let _ = fs::remove_dir_all(&temp_dir_path); // 忽略清理失败 (可能目录不存在)
-        fs::create_dir_all(&temp_dir_path)?;
+        let _ = device.remove_dir_all(Path::new(&temp_dir_path)); // 忽略清理失败 (可能目录不存在)
+        device.create_dir_all(Path::new(&temp_dir_path))?;
 
         println!("开始合并... 临时目录: {}", temp_dir_path);
 
-        // 启动递归合并
+        // 启动递归合并；中间结果都在临时目录中，出错时连同目录一起删除，输出文件不会被写到一半
         let mut temp_file_counter: u32 = 1;
-        let final_file_path =
-            self.execute_merge_node(root_node, &temp_dir_path, &mut temp_file_counter)?;
+        let mut phases = IoPhases::new(&self.io);
+        let final_file_path = match self.execute_merge_node(
+            root_node,
+            &temp_dir_path,
+            &mut temp_file_counter,
+            &mut phases,
+            self.codecs.output.as_ref(),
+        ) {
+            Ok(path) => path,
+            Err(e) => {
+                let _ = device.remove_dir_all(Path::new(&temp_dir_path));
+                return Err(e);
+            }
+        };
+        self.merge_io = phases.phases;
 
         println!("合并完成。最终文件: {}", final_file_path);
 
-        // 将最终合并的文件移动到目标输出位置
-        fs::rename(final_file_path, &self.output_file_path)?;
+        // 将最终合并的文件移动到目标输出位置。只有一个顺串时它就是结果，编码不同则需要转换
+        let moved = if root_node.leaf_id.is_some()
+            && self.codecs.runs.name() != self.codecs.output.name()
+        {
+            let temp_output = format!("{}/output", temp_dir_path);
+            convert(
+                &self.io,
+                Path::new(&final_file_path),
+                self.codecs.runs.as_ref(),
+                Path::new(&temp_output),
+                self.codecs.output.as_ref(),
+            )
+            .and_then(|_| device.rename(Path::new(&temp_output), Path::new(&self.output_file_path)))
+        } else {
+            device.rename(
+                Path::new(&final_file_path),
+                Path::new(&self.output_file_path),
+            )
+        };
+        if let Err(e) = moved {
+            let _ = device.remove_dir_all(Path::new(&temp_dir_path));
+            return Err(e);
+        }
         println!("已将最终文件移动到: {}", self.output_file_path);
 
         // 清理临时目录
-        fs::remove_dir_all(temp_dir_path)?;
+        device.remove_dir_all(Path::new(&temp_dir_path))?;
         println!("已清理临时目录。");
 
         Ok(())
     }
 
+    // output_codec 是本节点合并结果的编码：根节点为最终输出的编码，其余为顺串的编码
     fn execute_merge_node(
         &self,
-        node: &Box<MergeNode>,
+        node: &MergeNode,
         temp_dir: &str,
         next_temp_id: &mut u32,
+        phases: &mut IoPhases,
+        output_codec: &dyn RecordCodec,
     ) -> io::Result<String> {
         // 如果是叶子节点，它代表一个原始的 run 文件。
         if let Some(run_id) = node.leaf_id {
-            let file_path = format!("{}/run_{}.txt", self.input_file_path, run_id);
-            return Ok(file_path);
+            let file_path = run_path(
+                &self.input_file_path,
+                run_id as u64,
+                self.codecs.runs.as_ref(),
+            );
+            return Ok(file_path.to_string_lossy().to_string());
         }
 
         // 否则，它是一个内部节点，需要合并其子节点
         if let (Some(left), Some(right)) = (&node.left, &node.right) {
             // 递归处理左子树 (获取左侧输入文件路径)
-            let left_file_path = self.execute_merge_node(left, temp_dir, next_temp_id)?;
+            let run_codec = self.codecs.runs.as_ref();
+            let left_file_path =
+                self.execute_merge_node(left, temp_dir, next_temp_id, phases, run_codec)?;
 
             // 递归处理右子树 (获取右侧输入文件路径)
-            let right_file_path = self.execute_merge_node(right, temp_dir, next_temp_id)?;
+            let right_file_path =
+                self.execute_merge_node(right, temp_dir, next_temp_id, phases, run_codec)?;
 
             // 定义本次合并的输出文件路径
-            let output_path = format!("{}/temp_{}.txt", temp_dir, *next_temp_id);
+            let merge_id = *next_temp_id;
+            let output_path = format!(
+                "{}/temp_{}.{}",
+                temp_dir,
+                merge_id,
+                output_codec.extension()
+            );
             *next_temp_id += 1; // 增加计数器
 
             println!(
@@ -579,14 +557,20 @@ impl Merger {
             );
 
             // 执行双路合并
-            self.perform_2_way_merge(&left_file_path, &right_file_path, &output_path)?;
+            self.perform_2_way_merge(
+                &left_file_path,
+                &right_file_path,
+                &output_path,
+                output_codec,
+            )?;
+            phases.mark(format!("merge_{}", merge_id));
 
             // 清理临时的输入文件
             if left_file_path.starts_with(temp_dir) {
-                fs::remove_file(left_file_path)?;
+                self.io.device().remove_file(Path::new(&left_file_path))?;
             }
             if right_file_path.starts_with(temp_dir) {
-                fs::remove_file(right_file_path)?;
+                self.io.device().remove_file(Path::new(&right_file_path))?;
             }
 
             // 返回新创建的临时文件的路径
@@ -602,18 +586,14 @@ impl Merger {
         in_path_1: &str,
         in_path_2: &str,
         out_path: &str,
+        output_codec: &dyn RecordCodec,
     ) -> io::Result<()> {
-        let file1 = File::open(in_path_1)?;
-        let file2 = File::open(in_path_2)?;
-        let mut reader1 = InputElementReader::new(file1)?;
-        let mut reader2 = InputElementReader::new(file2)?;
-
-        let out_file = File::create(out_path)?;
-        // 为输出也使用一个大的缓冲区
-        let mut writer = BufWriter::with_capacity(8 * 1024 * 1024, out_file);
+        let mut reader1 = self.io.open_records(in_path_1, self.codecs.runs.as_ref())?;
+        let mut reader2 = self.io.open_records(in_path_2, self.codecs.runs.as_ref())?;
+        let mut writer = self.io.create_records(out_path, output_codec)?;
 
-        let mut elem1 = reader1.next_element()?;
-        let mut elem2 = reader2.next_element()?;
+        let mut elem1 = reader1.next_record()?;
+        let mut elem2 = reader2.next_record()?;
 
         // 这是经典的合并排序 (merge) 逻辑
         loop {
@@ -622,23 +602,23 @@ impl Merger {
                 (Some(val1), Some(val2)) => {
                     if val1 <= val2 {
                         // 写入 val1, 并从 reader1 读取下一个
-                        write!(writer, "{} ", val1)?;
-                        elem1 = reader1.next_element()?;
+                        writer.write_record(val1)?;
+                        elem1 = reader1.next_record()?;
                     } else {
                         // 写入 val2, 并从 reader2 读取下一个
-                        write!(writer, "{} ", val2)?;
-                        elem2 = reader2.next_element()?;
+                        writer.write_record(val2)?;
+                        elem2 = reader2.next_record()?;
                     }
                 }
                 // 情况 2: 只有 reader1 还有元素
                 (Some(val1), None) => {
-                    write!(writer, "{} ", val1)?;
-                    elem1 = reader1.next_element()?;
+                    writer.write_record(val1)?;
+                    elem1 = reader1.next_record()?;
                 }
                 // 情况 3: 只有 reader2 还有元素
                 (None, Some(val2)) => {
-                    write!(writer, "{} ", val2)?;
-                    elem2 = reader2.next_element()?;
+                    writer.write_record(val2)?;
+                    elem2 = reader2.next_record()?;
                 }
                 // 情况 4: 两个文件都已耗尽
                 (None, None) => {
@@ -647,7 +627,7 @@ impl Merger {
             }
         }
 
-        writer.flush()?;
+        writer.finish()?;
         Ok(())
     }
 
@@ -676,7 +656,7 @@ pub struct RunStatistics {
 }
 
 impl RunStatistics {
-    pub fn from_directory(dir: &Path) -> io::Result<Self> {
+    pub fn from_directory(dir: &Path, codec: &dyn RecordCodec) -> io::Result<Self> {
         let mut entries = Vec::new();
         if !dir.exists() {
             return Ok(Self { entries });
@@ -686,8 +666,8 @@ impl RunStatistics {
             let entry = entry?;
             let file_name = entry.file_name();
             let file_name = file_name.to_string_lossy();
-            if let Some(run_id) = parse_run_id(&file_name) {
-                let length = count_elements_in_file(&entry.path())?;
+            if let Some(run_id) = parse_run_id(&file_name, codec) {
+                let length = count_elements_in_file(&entry.path(), codec)?;
                 entries.push(RunLengthEntry { run_id, length });
             }
         }
@@ -730,9 +710,9 @@ impl RunStatistics {
     }
 }
 
-fn count_elements_in_file(path: &Path) -> io::Result<u64> {
+fn count_elements_in_file(path: &Path, codec: &dyn RecordCodec) -> io::Result<u64> {
     let file = File::open(path)?;
-    let mut reader = InputElementReader::new(file)?;
+    let mut reader = InputElementReader::with_codec(codec, BufReader::new(file));
     let mut count = 0_u64;
     while reader.next_element()?.is_some() {
         count += 1;
@@ -740,6 +720,146 @@ fn count_elements_in_file(path: &Path) -> io::Result<u64> {
     Ok(count)
 }
 
+fn read_elements_from_file(
+    device: &dyn BlockDevice,
+    codec: &dyn RecordCodec,
+    path: &Path,
+) -> io::Result<Vec<i32>> {
+    let mut reader = InputElementReader::with_codec(codec, BufReader::new(device.open(path)?));
+    let mut elements = Vec::new();
+    while let Some(value) = reader.next_element()? {
+        elements.push(value);
+    }
+    Ok(elements)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Debug, Clone)]
+pub struct RunValidationReport {
+    pub run_count: usize,
+    pub total_length: u64,
+}
+
+/// 外排序结果的正确性检查：顺串文件与最终输出都必须是输入的一个有序排列。
+/// 任一检查失败时返回 `InvalidData` 错误，错误信息指出第一个出问题的文件。
+/// 文件都从 device 上读取，按 codecs 中对应的编码解码，读取不计入排序的 I/O 次数。
+pub struct RunValidator;
+
+impl RunValidator {
+    /// 检查 runs_dir 中的顺串：编号从 0 连续、每个文件非空且非递减，
+    /// 长度之和等于输入长度，所有元素合起来与输入是同一个多重集
+    pub fn validate_runs(
+        device: &dyn BlockDevice,
+        codecs: &Codecs,
+        input_file: &Path,
+        runs_dir: &Path,
+    ) -> io::Result<RunValidationReport> {
+        let run_file = |run_id: u32| run_path(runs_dir, run_id as u64, codecs.runs.as_ref());
+        let run_name = |run_id: u32| {
+            let path = run_file(run_id);
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        };
+        let mut run_ids = Vec::new();
+        for path in device.list_files(runs_dir)? {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            if let Some(run_id) = parse_run_id(&file_name, codecs.runs.as_ref()) {
+                run_ids.push(run_id);
+            }
+        }
+        run_ids.sort_unstable();
+        if let Some((expected, &run_id)) = run_ids
+            .iter()
+            .enumerate()
+            .find(|&(i, &id)| id as usize != i)
+        {
+            return Err(invalid_data(format!(
+                "顺串编号不连续：缺少 {}（下一个为 {}）",
+                run_name(expected as u32),
+                run_name(run_id)
+            )));
+        }
+
+        let mut elements = Vec::new();
+        for &run_id in &run_ids {
+            let run = read_elements_from_file(device, codecs.runs.as_ref(), &run_file(run_id))?;
+            if run.is_empty() {
+                return Err(invalid_data(format!("{} 为空", run_name(run_id))));
+            }
+            Self::check_sorted(&run, &run_name(run_id))?;
+            elements.extend(run);
+        }
+
+        let total_length = Self::check_permutation(device, codecs, input_file, elements, "顺串")?;
+        Ok(RunValidationReport {
+            run_count: run_ids.len(),
+            total_length,
+        })
+    }
+
+    /// 检查最终输出文件非递减，且与输入是同一个多重集，返回元素个数
+    pub fn validate_sorted_output(
+        device: &dyn BlockDevice,
+        codecs: &Codecs,
+        input_file: &Path,
+        output_file: &Path,
+    ) -> io::Result<u64> {
+        let output = read_elements_from_file(device, codecs.output.as_ref(), output_file)?;
+        let name = output_file
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+        Self::check_sorted(&output, &name)?;
+        Self::check_permutation(device, codecs, input_file, output, &name)
+    }
+
+    fn check_sorted(elements: &[i32], name: &str) -> io::Result<()> {
+        match elements.windows(2).position(|w| w[0] > w[1]) {
+            Some(i) => Err(invalid_data(format!(
+                "{} 不是有序的：第 {} 个元素 {} 大于第 {} 个元素 {}",
+                name,
+                i,
+                elements[i],
+                i + 1,
+                elements[i + 1]
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // 排序后逐个比较，相等即说明两者是同一个多重集
+    fn check_permutation(
+        device: &dyn BlockDevice,
+        codecs: &Codecs,
+        input_file: &Path,
+        mut elements: Vec<i32>,
+        name: &str,
+    ) -> io::Result<u64> {
+        let mut input = read_elements_from_file(device, codecs.input.as_ref(), input_file)?;
+        if input.len() != elements.len() {
+            return Err(invalid_data(format!(
+                "{} 共有 {} 个元素，而输入有 {} 个",
+                name,
+                elements.len(),
+                input.len()
+            )));
+        }
+        input.sort_unstable();
+        elements.sort_unstable();
+        if let Some(i) = (0..input.len()).find(|&i| input[i] != elements[i]) {
+            return Err(invalid_data(format!(
+                "{} 与输入的元素不一致：排序后第 {} 个元素为 {}，输入中为 {}",
+                name, i, elements[i], input[i]
+            )));
+        }
+        Ok(input.len() as u64)
+    }
+}
+
 pub struct MergePlanSummary {
     pub merge_steps: Vec<String>,
     pub leaf_count: usize,
@@ -793,7 +913,7 @@ impl MergePlanSummary {
         writeln!(file, "leaf_count: {}", self.leaf_count)?;
         writeln!(file, "max_depth: {}", self.max_depth)?;
         writeln!(file, "weighted_path_length: {}", self.weighted_path_len)?;
-        writeln!(file, "")?;
+        writeln!(file)?;
         writeln!(file, "steps:")?;
         for (idx, step) in self.merge_steps.iter().enumerate() {
             writeln!(file, "{}. {}", idx + 1, step)?;
@@ -814,6 +934,14 @@ pub struct ExperimentConfig {
     pub summary_csv: String,
     pub run_stats_dir: String,
     pub merge_plan_dir: String,
+    /// 统计 I/O 次数时的块大小（字节）
+    pub block_size: usize,
+    /// 模拟磁盘的参数。数据都在页缓存中时实测耗时体现不出 I/O，另按它报告模拟的 I/O 时间
+    pub disk: DeviceModel,
+    /// 每个阶段（顺串生成、合并计划、每次双路合并）的 I/O 明细
+    pub io_phases_csv: String,
+    /// 输入、顺串与输出文件的编码
+    pub codecs: Codecs,
 }
 
 impl Default for ExperimentConfig {
@@ -829,6 +957,10 @@ impl Default for ExperimentConfig {
             summary_csv: "origin_data.csv".into(),
             run_stats_dir: "analysis/run_stats".into(),
             merge_plan_dir: "analysis/merge_plans".into(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            disk: DeviceModel::hdd(),
+            io_phases_csv: "analysis/io_phases.csv".into(),
+            codecs: Codecs::default(),
         }
     }
 }
@@ -844,6 +976,7 @@ impl ExperimentRunner {
         let run_stats_dir_path = Path::new(&base_dir).join(&config.run_stats_dir);
         let merge_plan_dir_path = Path::new(&base_dir).join(&config.merge_plan_dir);
         let summary_csv_path = Path::new(&base_dir).join(&config.summary_csv);
+        let io_phases_csv_path = Path::new(&base_dir).join(&config.io_phases_csv);
 
         fs::create_dir_all(&runs_dir_path)?;
         fs::create_dir_all(&run_stats_dir_path)?;
@@ -852,16 +985,23 @@ impl ExperimentRunner {
         let mut summary_writer = BufWriter::new(File::create(&summary_csv_path)?);
         writeln!(
             summary_writer,
-            "k,run_count,total_numbers,min_run_length,max_run_length,avg_run_length,total_time_ms,max_tree_depth,weighted_path_length"
+            "k,run_count,total_numbers,min_run_length,max_run_length,avg_run_length,total_time_ms,modelled_io_ms,max_tree_depth,weighted_path_length,run_block_reads,run_block_writes,merge_block_reads,merge_block_writes,bytes_read,bytes_written,file_opens"
         )?;
+        if let Some(parent) = io_phases_csv_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut io_phases_writer = BufWriter::new(File::create(&io_phases_csv_path)?);
+        writeln!(io_phases_writer, "k,phase,{}", IoCounters::CSV_HEADER)?;
 
-        let source_generator = SourceFileGenerator::new(
+        let mut source_generator = SourceFileGenerator::new(
             config.total_numbers,
             config.min_value,
             config.max_value,
             config.input_file.clone(),
         );
+        source_generator.codec = config.codecs.input.clone();
         source_generator.generate_file()?;
+        println!("> 编码：{}", config.codecs.describe());
 
         for &k in &config.k_values {
             if k == 0 {
@@ -870,33 +1010,99 @@ impl ExperimentRunner {
 
             RunGenerator::clean_directory_contents(&runs_dir_path)?;
 
-            let mut run_generator = RunGenerator::new(config.input_file.clone(), k);
+            // 顺串生成与合并共用一组计数，按阶段取增量
+            let io_stats = IoStats::simulated(config.block_size, config.disk);
+            let mut io_phases = IoPhases::new(&io_stats);
+            let input_file_path = Path::new(&base_dir).join(&config.input_file);
+            let mut run_generator = RunGenerator::with_codecs(
+                &input_file_path,
+                &runs_dir_path,
+                k,
+                io_stats.clone(),
+                config.codecs.clone(),
+            )?;
             let mut merger =
                 Merger::new(config.runs_dir.clone(), config.sorted_output_file.clone());
+            merger.io = io_stats.clone();
+            merger.codecs = config.codecs.clone();
 
             let start_time = Instant::now();
             run_generator.generate_run_file()?;
+            let run_generation_time = start_time.elapsed();
+            let run_io = io_phases.mark("run_generation");
+
+            // 校验不计入耗时，也不计入 I/O 次数
+            let report = RunValidator::validate_runs(
+                &FileDevice,
+                &config.codecs,
+                &input_file_path,
+                &runs_dir_path,
+            )?;
+            println!(
+                "> k = {}：{} 个顺串共 {} 个元素，校验通过",
+                k, report.run_count, report.total_length
+            );
+            let start_time = Instant::now();
 
-            let run_stats = RunStatistics::from_directory(&runs_dir_path)?;
+            let run_stats =
+                RunStatistics::from_directory(&runs_dir_path, config.codecs.runs.as_ref())?;
             let run_stats_file = run_stats_dir_path.join(format!("k_{}.csv", k));
             run_stats.write_report(run_stats_file)?;
 
             merger.build_merge_plan()?;
+            io_phases.mark("merge_plan");
             let plan_summary = merger
                 .merge_plan
                 .as_ref()
                 .map(|node| MergePlanSummary::from_root(node))
-                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "merge plan is empty"))?;
+                .ok_or_else(|| io::Error::other("merge plan is empty"))?;
             let plan_report = merge_plan_dir_path.join(format!("k_{}.txt", k));
             plan_summary.write_report(plan_report)?;
 
             merger.merge_loop()?;
-            let elapsed_ms = start_time.elapsed().as_millis();
+            let elapsed_ms = (run_generation_time + start_time.elapsed()).as_millis();
+            io_phases.extend(merger.merge_io.iter().cloned());
+            let total_io = io_phases.total();
+            let merge_io = total_io.since(&run_io);
+            println!(
+                "> k = {}：顺串生成 {} 次块读、{} 次块写，合并 {} 次块读、{} 次块写（块大小 {} 字节）",
+                k,
+                run_io.block_reads,
+                run_io.block_writes,
+                merge_io.block_reads,
+                merge_io.block_writes,
+                config.block_size
+            );
+            println!(
+                "> k = {}：实测耗时 {} 毫秒，模拟磁盘 I/O 耗时 {:.1} 毫秒",
+                k,
+                elapsed_ms,
+                total_io.modelled_ms()
+            );
+            for (phase, counters) in &io_phases.phases {
+                writeln!(io_phases_writer, "{},{},{}", k, phase, counters.to_csv())?;
+            }
+            let io_columns = format!(
+                "{},{},{},{},{},{},{}",
+                run_io.block_reads,
+                run_io.block_writes,
+                merge_io.block_reads,
+                merge_io.block_writes,
+                total_io.bytes_read,
+                total_io.bytes_written,
+                total_io.file_opens
+            );
+            RunValidator::validate_sorted_output(
+                &FileDevice,
+                &config.codecs,
+                &input_file_path,
+                &Path::new(&base_dir).join(&config.sorted_output_file),
+            )?;
 
             if let Some(summary) = run_stats.summary() {
                 writeln!(
                     summary_writer,
-                    "{},{},{},{},{},{:.2},{},{},{}",
+                    "{},{},{},{},{},{:.2},{},{:.3},{},{},{}",
                     k,
                     summary.run_count,
                     summary.total_length,
@@ -904,13 +1110,15 @@ impl ExperimentRunner {
                     summary.max_length,
                     summary.avg_length,
                     elapsed_ms,
+                    total_io.modelled_ms(),
                     plan_summary.max_depth,
-                    plan_summary.weighted_path_len
+                    plan_summary.weighted_path_len,
+                    io_columns
                 )?;
             } else {
                 writeln!(
                     summary_writer,
-                    "{},{},{},{},{},{:.2},{},{},{}",
+                    "{},{},{},{},{},{:.2},{},{:.3},{},{},{}",
                     k,
                     0,
                     0,
@@ -918,21 +1126,373 @@ impl ExperimentRunner {
                     0,
                     0.0,
                     elapsed_ms,
+                    total_io.modelled_ms(),
                     plan_summary.max_depth,
-                    plan_summary.weighted_path_len
+                    plan_summary.weighted_path_len,
+                    io_columns
                 )?;
             }
         }
 
         summary_writer.flush()?;
+        io_phases_writer.flush()?;
         Ok(())
     }
 }
 
-pub fn parse_run_id(file_name: &str) -> Option<u32> {
-    file_name
-        .strip_prefix("run_")?
-        .strip_suffix(".txt")?
-        .parse::<u32>()
-        .ok()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{
+        BinaryCodec, DeltaVarintCodec, FaultPlan, FaultyDevice, MemoryDevice, VarintCodec,
+    };
+
+    const INPUT: &str = "/mem/project_3/nums.txt";
+    const RUNS_DIR: &str = "/mem/project_3/runs";
+    const OUTPUT: &str = "/mem/project_3/sorted_nums.txt";
+    const TEMP_DIR: &str = "/mem/project_3/temp";
+
+    // 内存设备上 5000 个取值在 [min, max] 内的数字，按 codecs.input 编码
+    fn memory_input_in(min: i32, max: i32, codecs: &Codecs) -> MemoryDevice {
+        let device = MemoryDevice::new();
+        let mut source_generator =
+            SourceFileGenerator::with_output_dir(5000, min, max, "/mem/project_3", "nums.txt")
+                .unwrap();
+        source_generator.device = Arc::new(device.clone());
+        source_generator.codec = codecs.input.clone();
+        source_generator.generate_file().unwrap();
+        device
+    }
+
+    // 内存设备上 5000 个数字的输入文件
+    fn memory_input(codecs: &Codecs) -> MemoryDevice {
+        memory_input_in(-100, 100, codecs)
+    }
+
+    fn memory_io(device: &MemoryDevice) -> IoStats {
+        IoStats::with_device(256, Arc::new(device.clone()))
+    }
+
+    fn faulty_io(device: &MemoryDevice, plan: FaultPlan) -> IoStats {
+        IoStats::with_device(
+            256,
+            Arc::new(FaultyDevice::new(Arc::new(device.clone()), plan)),
+        )
+    }
+
+    // 从 INPUT 生成 RUNS_DIR 中的顺串
+    fn generate_runs(io: &IoStats, codecs: &Codecs, k: usize) -> io::Result<RunGenerator> {
+        let mut run_generator =
+            RunGenerator::with_codecs(INPUT, RUNS_DIR, k, io.clone(), codecs.clone())?;
+        run_generator.generate_run_file()?;
+        Ok(run_generator)
+    }
+
+    // 已为 RUNS_DIR 中的顺串建好合并计划的合并器
+    fn planned_merger(io: &IoStats, codecs: &Codecs) -> Merger {
+        let mut merger = Merger::with_paths(RUNS_DIR, OUTPUT, io.clone());
+        merger.codecs = codecs.clone();
+        merger.build_merge_plan().unwrap();
+        merger
+    }
+
+    // 生成顺串并合并到 OUTPUT，返回校验通过的输出长度
+    fn sort(device: &MemoryDevice, io: &IoStats, codecs: &Codecs, k: usize) -> io::Result<u64> {
+        generate_runs(io, codecs, k)?;
+        planned_merger(io, codecs).merge_loop()?;
+        validate_output(device, codecs)
+    }
+
+    fn validate_runs(device: &MemoryDevice, codecs: &Codecs) -> io::Result<RunValidationReport> {
+        RunValidator::validate_runs(device, codecs, Path::new(INPUT), Path::new(RUNS_DIR))
+    }
+
+    fn validate_output(device: &MemoryDevice, codecs: &Codecs) -> io::Result<u64> {
+        RunValidator::validate_sorted_output(device, codecs, Path::new(INPUT), Path::new(OUTPUT))
+    }
+
+    #[test]
+    fn sorts_on_memory_device() {
+        let codecs = Codecs::default();
+        let device = memory_input(&codecs);
+        let io = memory_io(&device);
+        let run_generator = generate_runs(&io, &codecs, 8).unwrap();
+        let report = validate_runs(&device, &codecs).unwrap();
+        assert_eq!(report.run_count as u64, run_generator.run_count);
+        assert_eq!(report.total_length, 5000);
+
+        let mut merger = planned_merger(&io, &codecs);
+        merger.merge_loop().unwrap();
+        assert_eq!(validate_output(&device, &codecs).unwrap(), 5000);
+        assert_eq!(merger.merge_io.len(), report.run_count - 1);
+        assert!(io.snapshot().block_writes > 0);
+        assert!(!device.exists(Path::new(TEMP_DIR)));
+        assert!(device.files().iter().all(|path| path.starts_with("/mem")));
+    }
+
+    // 能让顺串生成或合并中途失败的几种故障
+    fn failing_plans() -> Vec<(FaultPlan, ErrorKind)> {
+        vec![
+            (
+                FaultPlan {
+                    fail_read: Some(10),
+                    ..FaultPlan::default()
+                },
+                ErrorKind::Other,
+            ),
+            (
+                FaultPlan {
+                    fail_write: Some(3),
+                    ..FaultPlan::default()
+                },
+                ErrorKind::Other,
+            ),
+            (
+                FaultPlan {
+                    capacity: Some(2000),
+                    ..FaultPlan::default()
+                },
+                ErrorKind::StorageFull,
+            ),
+        ]
+    }
+
+    #[test]
+    fn run_generation_fails_cleanly() {
+        let codecs = Codecs::default();
+        for (plan, kind) in failing_plans() {
+            let device = memory_input(&codecs);
+            let io = faulty_io(&device, plan.clone());
+            let mut run_generator = RunGenerator::with_io(INPUT, RUNS_DIR, 8, io).unwrap();
+            let err = run_generator.generate_run_file().unwrap_err();
+            assert_eq!(err.kind(), kind, "{:?}", plan);
+            assert!(device.list_files(Path::new(RUNS_DIR)).unwrap().is_empty());
+            assert_eq!(run_generator.run_count, 0);
+        }
+    }
+
+    #[test]
+    fn merge_loop_fails_cleanly() {
+        let codecs = Codecs::default();
+        for (plan, kind) in failing_plans() {
+            let device = memory_input(&codecs);
+            let io = memory_io(&device);
+            generate_runs(&io, &codecs, 8).unwrap();
+            let runs = device.list_files(Path::new(RUNS_DIR)).unwrap();
+
+            let mut merger = planned_merger(&io, &codecs);
+            merger.io = faulty_io(&device, plan.clone());
+            let err = merger.merge_loop().unwrap_err();
+            assert_eq!(err.kind(), kind, "{:?}", plan);
+            assert!(!device.exists(Path::new(TEMP_DIR)));
+            assert!(!device.exists(Path::new(OUTPUT)));
+            assert_eq!(device.list_files(Path::new(RUNS_DIR)).unwrap(), runs);
+        }
+    }
+
+    fn truncating_io(device: &MemoryDevice) -> IoStats {
+        faulty_io(
+            device,
+            FaultPlan {
+                truncate_after: Some(50),
+                ..FaultPlan::default()
+            },
+        )
+    }
+
+    #[test]
+    fn truncated_runs_fail_validation() {
+        // 顺串生成本身成功，但每个顺串文件只留下前 50 字节
+        let codecs = Codecs::default();
+        let device = memory_input(&codecs);
+        let run_generator = generate_runs(&truncating_io(&device), &codecs, 8).unwrap();
+        let runs = device.list_files(Path::new(RUNS_DIR)).unwrap();
+        assert_eq!(runs.len() as u64, run_generator.run_count);
+        assert!(runs.iter().all(|run| device.size(run).unwrap() <= 50));
+        let err = validate_runs(&device, &codecs).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_merge_output_fails_validation() {
+        let codecs = Codecs::default();
+        let device = memory_input(&codecs);
+        generate_runs(&memory_io(&device), &codecs, 8).unwrap();
+
+        let mut merger = planned_merger(&memory_io(&device), &codecs);
+        merger.io = truncating_io(&device);
+        // 被截断的中间文件可能以半个数字结尾，合并时就发现了；否则由校验发现
+        let err = merger
+            .merge_loop()
+            .and_then(|()| validate_output(&device, &codecs))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn sorts_with_short_reads() {
+        let codecs = Codecs::default();
+        let device = memory_input(&codecs);
+        let io = faulty_io(
+            &device,
+            FaultPlan {
+                short_reads: Some(7),
+                ..FaultPlan::default()
+            },
+        );
+        assert_eq!(sort(&device, &io, &codecs, 8).unwrap(), 5000);
+    }
+
+    #[test]
+    fn sorts_with_mixed_codecs() {
+        let codecs = Codecs {
+            input: Arc::new(BinaryCodec),
+            runs: Arc::new(VarintCodec),
+            output: Arc::new(TextCodec),
+        };
+        let device = memory_input(&codecs);
+        assert_eq!(device.read(INPUT).unwrap().len(), 4 * 5000);
+        let io = memory_io(&device);
+        generate_runs(&io, &codecs, 8).unwrap();
+        validate_runs(&device, &codecs).unwrap();
+        planned_merger(&io, &codecs).merge_loop().unwrap();
+        assert_eq!(validate_output(&device, &codecs).unwrap(), 5000);
+    }
+
+    #[test]
+    fn merges_delta_runs() {
+        // 只有 8 种不同的值，差值几乎都是 0
+        let device = memory_input_in(1000, 1007, &Codecs::default());
+        let run_bytes = |codecs: &Codecs| {
+            assert_eq!(
+                sort(&device, &memory_io(&device), codecs, 256).unwrap(),
+                5000
+            );
+            let runs = device.list_files(Path::new(RUNS_DIR)).unwrap();
+            runs.iter()
+                .map(|run| device.size(run).unwrap())
+                .sum::<u64>()
+        };
+        let text = run_bytes(&Codecs::default());
+        let delta = run_bytes(&Codecs {
+            runs: Arc::new(DeltaVarintCodec { block_records: 128 }),
+            ..Codecs::default()
+        });
+        assert!(delta * 3 <= text, "{} {}", delta, text);
+    }
+
+    // 在内存设备上写入输入文件与若干个文本顺串
+    fn handmade_runs(runs: &[&str]) -> MemoryDevice {
+        let device = MemoryDevice::new();
+        device.write(INPUT, "5 3 9 1 3\n");
+        for (id, run) in runs.iter().enumerate() {
+            device.write(run_path(RUNS_DIR, id as u64, &TextCodec), *run);
+        }
+        device
+    }
+
+    fn validate_handmade(runs: &[&str]) -> io::Result<RunValidationReport> {
+        validate_runs(&handmade_runs(runs), &Codecs::default())
+    }
+
+    #[test]
+    fn validator_accepts_sorted_runs() {
+        let report = validate_handmade(&["3 5 9", "1 3"]).unwrap();
+        assert_eq!((report.run_count, report.total_length), (2, 5));
+    }
+
+    #[test]
+    fn validator_rejects_bad_runs() {
+        let cases = [
+            (["5 3 9", "1 3"], "不是有序的"),
+            // 丢失一个 3
+            (["3 5 9", "1"], "共有 4 个元素"),
+            // 3 被替换成了重复的 1
+            (["3 5 9", "1 1"], "不一致"),
+            // 多出一个重复的 9
+            (["3 5 9 9", "1 3"], "共有 6 个元素"),
+        ];
+        for (runs, message) in cases {
+            let err = validate_handmade(&runs).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{:?}", runs);
+            assert!(err.to_string().contains(message), "{:?}: {}", runs, err);
+        }
+
+        let device = handmade_runs(&[]);
+        device.write(OUTPUT, "1 3 3 5 5");
+        let err = validate_output(&device, &Codecs::default()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn extreme_values_survive_run_generation() {
+        let values = [
+            i32::MAX,
+            i32::MIN,
+            0,
+            -1,
+            i32::MAX,
+            1,
+            i32::MIN,
+            i32::MIN + 1,
+            i32::MAX - 1,
+            7,
+        ];
+        let input: Vec<String> = values.iter().cycle().take(40).map(i32::to_string).collect();
+        let run_codecs: [Arc<dyn RecordCodec>; 4] = [
+            Arc::new(TextCodec),
+            Arc::new(BinaryCodec),
+            Arc::new(VarintCodec),
+            Arc::new(DeltaVarintCodec { block_records: 4 }),
+        ];
+        for runs in run_codecs {
+            let codecs = Codecs {
+                runs,
+                ..Codecs::default()
+            };
+            let device = MemoryDevice::new();
+            device.write(INPUT, input.join(" "));
+            let io = memory_io(&device);
+            generate_runs(&io, &codecs, 3).unwrap();
+            assert_eq!(validate_runs(&device, &codecs).unwrap().total_length, 40);
+
+            planned_merger(&io, &codecs).merge_loop().unwrap();
+            assert_eq!(validate_output(&device, &codecs).unwrap(), 40);
+            let output = String::from_utf8(device.read(OUTPUT).unwrap()).unwrap();
+            let output: Vec<&str> = output.split_whitespace().collect();
+            assert_eq!(output[0], i32::MIN.to_string());
+            assert_eq!(output[39], i32::MAX.to_string());
+        }
+    }
+
+    #[test]
+    fn run_files_carry_the_codec_extension() {
+        let device = MemoryDevice::new();
+        device.write(INPUT, "5 3 9 1 3 8 2 7\n");
+        let codecs = Codecs {
+            runs: Arc::new(BinaryCodec),
+            ..Codecs::default()
+        };
+        generate_runs(&memory_io(&device), &codecs, 2).unwrap();
+
+        let mut names: Vec<String> = device
+            .list_files(Path::new(RUNS_DIR))
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert!(!names.is_empty());
+        for (id, name) in names.iter().enumerate() {
+            assert_eq!(*name, format!("run_{}.bin", id));
+            assert_eq!(parse_run_id(name, &BinaryCodec), Some(id as u32));
+            assert_eq!(parse_run_id(name, &TextCodec), None);
+        }
+
+        // 以文本编码留下的文件不算作二进制顺串
+        device.write(run_path(RUNS_DIR, names.len() as u64, &TextCodec), "1 2\n");
+        let report = validate_runs(&device, &codecs).unwrap();
+        assert_eq!(report.run_count, names.len());
+        assert_eq!(report.total_length, 8);
+    }
 }
@@ -1,11 +1,23 @@
 #![allow(unused)]
 
-use crate::project_3::{RunGenerator, SourceFileGenerator};
-use std::cmp::Ordering;
+use crate::project_3::{ElementState, LoserTree, RunGenerator, RunValidator, SourceFileGenerator};
+use crate::storage::{
+    BinaryCodec, BlockDevice, Codecs, DEFAULT_BLOCK_SIZE, DeltaVarintCodec, DeviceModel,
+    FileDevice, IoCounters, IoStats, RecordCodec, RecordDecoder, RecordEncoder, SimulatedDisk,
+    TextCodec, VarintCodec, parse_run_id,
+};
 use std::env;
 use std::fs::{self, File};
-use std::io::{self, BufWriter, Read, Write};
+use std::io::{self, BufRead, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+mod plan;
+pub use plan::*;
 
 /// The default size (in number of elements) of each in-memory buffer.
 const DEFAULT_BUFFER_CAPACITY: usize = 1024;
@@ -13,7 +25,8 @@ const DEFAULT_BUFFER_CAPACITY: usize = 1024;
 /// Configuration for the improved external merge sort experiment.
 #[derive(Debug, Clone)]
 pub struct Project4Config {
-    /// Directory that stores all initial runs (`run_*.txt`).
+    /// Directory that stores all initial runs (`run_<id>.<ext>`, the extension
+    /// given by the runs codec).
     pub runs_dir: String,
     /// Path of the final sorted output file.
     pub output_file: String,
@@ -33,6 +46,79 @@ pub struct Project4Config {
     pub max_value: i32,
     /// Number of elements kept in memory when generating runs.
     pub initial_k: usize,
+    /// Directory for intermediate runs written by all but the last merge pass.
+    pub temp_dir: String,
+    /// How spare input buffers are handed out to runs.
+    pub extra_buffer_policy: ExtraBufferPolicy,
+    /// Output buffers shared with the write-behind thread; at least one.
+    pub output_buffers: usize,
+    /// Background threads that read runs during a merge; all runs of a merge share them.
+    pub reader_threads: usize,
+    /// Total bytes for merge buffers. When set, `max_k`, `buffer_capacity` and
+    /// `extra_input_buffers` are derived from it with `disk` and ignored.
+    pub memory_budget: Option<usize>,
+    /// Block size in bytes at which run reads and output writes are counted.
+    pub block_size: usize,
+    /// Simulated disk that times every block read and write; reported next to wall-clock
+    /// time, and used to choose a plan for `memory_budget`.
+    pub disk: DeviceModel,
+    /// Storage holding the source file, runs, temp files and output: the file system by
+    /// default, or e.g. a `MemoryDevice` in tests. All paths above refer to it.
+    pub backing: Arc<dyn BlockDevice>,
+    /// Encodings of the source file, of runs and intermediate runs, and of the output.
+    pub codecs: Codecs,
+}
+
+/// Rule for choosing which run receives a free extra input buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExtraBufferPolicy {
+    /// Knuth's forecasting: the run whose last buffered key is smallest will run
+    /// dry first, so it gets the next buffer.
+    #[default]
+    Forecasting,
+    /// The run that has been refilled most often so far.
+    MostRefills,
+}
+
+impl ExtraBufferPolicy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExtraBufferPolicy::Forecasting => "forecasting",
+            ExtraBufferPolicy::MostRefills => "most_refills",
+        }
+    }
+}
+
+/// Buffer activity accumulated over all merges of a `KWayLoserTreeMerger`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MergeStats {
+    /// Buffers of run data handed to the merge, whether read synchronously or prefetched.
+    pub refills: u64,
+    /// Times a prefetched secondary buffer was swapped in as the primary.
+    pub buffer_swaps: u64,
+    /// Times a run ran out of data and the merge had to wait for its next buffer,
+    /// either because none was requested or because the prefetch had not finished.
+    pub stalls: u64,
+    /// Time the background reader threads spent filling buffers.
+    pub read_time: Duration,
+    /// Time the write-behind thread spent writing output buffers.
+    pub write_time: Duration,
+    /// Time the merge loop waited for input buffers.
+    pub read_stall_time: Duration,
+    /// Time the merge loop waited for a free output buffer.
+    pub write_stall_time: Duration,
+}
+
+impl MergeStats {
+    /// Fraction of background I/O time that was hidden behind the merge loop.
+    pub fn overlap(&self) -> f64 {
+        let io_time = (self.read_time + self.write_time).as_secs_f64();
+        if io_time == 0.0 {
+            return 0.0;
+        }
+        let stall_time = (self.read_stall_time + self.write_stall_time).as_secs_f64();
+        (1.0 - stall_time / io_time).clamp(0.0, 1.0)
+    }
 }
 
 impl Default for Project4Config {
@@ -49,76 +135,445 @@ impl Default for Project4Config {
             min_value: -10_000,
             max_value: 10_000,
             initial_k: 8,
+            temp_dir: format!("{}/data/project_4/temp", cargo_manifest_dir),
+            extra_buffer_policy: ExtraBufferPolicy::default(),
+            output_buffers: 2,
+            reader_threads: 4,
+            memory_budget: None,
+            block_size: DEFAULT_BLOCK_SIZE,
+            disk: DeviceModel::hdd(),
+            backing: Arc::new(FileDevice),
+            codecs: Codecs::default(),
         }
     }
 }
 
-/// Entry point used by `main` to demonstrate the project.
+impl Project4Config {
+    /// Fresh block counters on a simulated `disk` over `backing`.
+    pub fn io_stats(&self) -> IoStats {
+        IoStats::with_device(
+            self.block_size,
+            Arc::new(SimulatedDisk::new(self.backing.clone(), self.disk)),
+        )
+    }
+}
+
+/// Entry point used by `main`: runs the default parameter sweep.
 pub fn run() {
-    let config = Project4Config::default();
-    if let Err(err) = prepare_runs(&config)
-        .and_then(|_| KWayLoserTreeMerger::new(config).and_then(|mut merger| merger.merge()))
-    {
-        eprintln!("[project_4] Merge failed: {}", err);
-    }
-}
-
-fn prepare_runs(config: &Project4Config) -> io::Result<()> {
-    let runs_dir = Path::new(&config.runs_dir);
-    let has_existing_runs = runs_dir
-        .read_dir()
-        .map(|mut entries| {
-            entries.any(|entry| match entry {
-                Ok(entry) => entry.file_type().map(|t| t.is_file()).unwrap_or(false),
-                Err(_) => false,
-            })
-        })
-        .unwrap_or(false);
+    let config = Project4ExperimentConfig::default();
+    if let Err(err) = Project4ExperimentRunner::evaluate(config) {
+        eprintln!("[project_4] Failed to complete evaluation: {}", err);
+    }
+}
+
+/// Parameter sweep for the k-way merge. Every input size is sorted once for each
+/// combination of `max_k`, `buffer_capacity` and `extra_input_buffers`.
+#[derive(Debug, Clone)]
+pub struct Project4ExperimentConfig {
+    /// Settings shared by every merge; the swept fields are overwritten per row.
+    pub base: Project4Config,
+    /// Number of generated integers for each input file.
+    pub input_sizes: Vec<u64>,
+    pub max_k_values: Vec<usize>,
+    pub buffer_capacities: Vec<usize>,
+    pub extra_input_buffer_counts: Vec<usize>,
+    /// Summary CSV, relative to `data/project_4`.
+    pub summary_csv: String,
+    /// Block I/O of run generation and of every merge pass, relative to `data/project_4`.
+    pub io_phases_csv: String,
+    /// Run encodings compared after the sweep; input and output keep `base.codecs`.
+    pub run_codecs: Vec<Arc<dyn RecordCodec>>,
+    /// Value ranges for the codec comparison, e.g. a wide one and a duplicate-heavy one.
+    pub codec_value_ranges: Vec<(i32, i32)>,
+    /// Number of generated integers for each codec comparison.
+    pub codec_total_numbers: u64,
+    /// `initial_k` for the codec comparison. Runs should span many blocks, otherwise
+    /// the per-block headers of the delta encoding dominate its size.
+    pub codec_initial_k: usize,
+    /// Run size, compression ratio, I/O and time per run encoding, relative to `data/project_4`.
+    pub codecs_csv: String,
+}
+
+impl Default for Project4ExperimentConfig {
+    fn default() -> Self {
+        Self {
+            base: Project4Config::default(),
+            input_sizes: vec![10_000, 100_000],
+            max_k_values: vec![2, 4, 8, 16, 32, 64],
+            buffer_capacities: vec![64, 1024],
+            extra_input_buffer_counts: vec![0, 2, 8],
+            summary_csv: "origin_data.csv".into(),
+            io_phases_csv: "io_phases.csv".into(),
+            run_codecs: vec![
+                Arc::new(TextCodec),
+                Arc::new(BinaryCodec),
+                Arc::new(VarintCodec),
+                Arc::new(DeltaVarintCodec::default()),
+            ],
+            codec_value_ranges: vec![(-10_000, 10_000), (0, 15)],
+            codec_total_numbers: 100_000,
+            codec_initial_k: 1024,
+            codecs_csv: "codecs.csv".into(),
+        }
+    }
+}
+
+pub struct Project4ExperimentRunner;
+
+impl Project4ExperimentRunner {
+    pub fn evaluate(config: Project4ExperimentConfig) -> io::Result<()> {
+        let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
+        let data_dir = Path::new(&manifest_dir).join("data/project_4");
+        fs::create_dir_all(&data_dir)?;
+        let summary_csv_path = data_dir.join(&config.summary_csv);
+
+        let mut summary_writer = BufWriter::new(File::create(&summary_csv_path)?);
+        writeln!(
+            summary_writer,
+            "total_numbers,run_count,max_k,buffer_capacity,extra_input_buffers,passes,elapsed_ms,modelled_io_ms,run_modelled_io_ms,merge_modelled_io_ms,refills,buffer_swaps,stalls,stall_ms,overlap,run_block_reads,run_block_writes,merge_block_reads,merge_block_writes,bytes_read,bytes_written,file_opens"
+        )?;
+        let io_phases_csv_path = data_dir.join(&config.io_phases_csv);
+        let mut io_phases_writer = BufWriter::new(File::create(&io_phases_csv_path)?);
+        writeln!(
+            io_phases_writer,
+            "total_numbers,max_k,buffer_capacity,extra_input_buffers,phase,{}",
+            IoCounters::CSV_HEADER
+        )?;
+
+        for &total_numbers in &config.input_sizes {
+            let mut source_generator = SourceFileGenerator::with_output_dir(
+                total_numbers,
+                config.base.min_value,
+                config.base.max_value,
+                &data_dir,
+                format!("input_{}.txt", total_numbers),
+            )?;
+            source_generator.device = config.base.backing.clone();
+            source_generator.codec = config.base.codecs.input.clone();
+            source_generator.generate_file()?;
+            let source_path = source_generator.output_file_path.clone();
+
+            let run_io_stats = config.base.io_stats();
+            let mut run_generator = RunGenerator::with_codecs(
+                &source_path,
+                &config.base.runs_dir,
+                config.base.initial_k,
+                run_io_stats.clone(),
+                config.base.codecs.clone(),
+            )?;
+            run_generator.generate_run_file()?;
+            let run_count = run_generator.run_count;
+            let run_io = run_io_stats.snapshot();
+
+            for &max_k in &config.max_k_values {
+                for &buffer_capacity in &config.buffer_capacities {
+                    for &extra_input_buffers in &config.extra_input_buffer_counts {
+                        let merge_config = Project4Config {
+                            max_k,
+                            buffer_capacity,
+                            extra_input_buffers,
+                            ..config.base.clone()
+                        };
+                        let mut merger = KWayLoserTreeMerger::new(merge_config)?;
+                        let start_time = Instant::now();
+                        merger.merge()?;
+                        let elapsed_ms = start_time.elapsed().as_millis();
+
+                        // Validation is not part of the measured time.
+                        RunValidator::validate_sorted_output(
+                            config.base.backing.as_ref(),
+                            &config.base.codecs,
+                            &source_path,
+                            Path::new(&config.base.output_file),
+                        )?;
+
+                        let row = format!(
+                            "{},{},{},{}",
+                            total_numbers, max_k, buffer_capacity, extra_input_buffers
+                        );
+                        writeln!(
+                            io_phases_writer,
+                            "{},run_generation,{}",
+                            row,
+                            run_io.to_csv()
+                        )?;
+                        let mut merge_io = IoCounters::default();
+                        for report in &merger.passes {
+                            writeln!(
+                                io_phases_writer,
+                                "{},pass_{},{}",
+                                row,
+                                report.pass,
+                                report.io.to_csv()
+                            )?;
+                            merge_io += report.io;
+                        }
+                        let total_io = run_io + merge_io;
+
+                        let stats = merger.stats;
+                        writeln!(
+                            summary_writer,
+                            "{},{},{},{},{},{},{},{:.3},{:.3},{:.3},{},{},{},{:.2},{:.3},{},{},{},{},{},{},{}",
+                            total_numbers,
+                            run_count,
+                            max_k,
+                            buffer_capacity,
+                            extra_input_buffers,
+                            merger.passes.len(),
+                            elapsed_ms,
+                            // The whole sort, as in project_3, then each phase on its own.
+                            total_io.modelled_ms(),
+                            run_io.modelled_ms(),
+                            merge_io.modelled_ms(),
+                            stats.refills,
+                            stats.buffer_swaps,
+                            stats.stalls,
+                            (stats.read_stall_time + stats.write_stall_time).as_secs_f64() * 1000.0,
+                            stats.overlap(),
+                            run_io.block_reads,
+                            run_io.block_writes,
+                            merge_io.block_reads,
+                            merge_io.block_writes,
+                            total_io.bytes_read,
+                            total_io.bytes_written,
+                            total_io.file_opens
+                        )?;
+                    }
+                }
+            }
+        }
 
-    if has_existing_runs {
-        return Ok(());
+        summary_writer.flush()?;
+        io_phases_writer.flush()?;
+        println!(
+            "[project_4] Summary written to {}",
+            summary_csv_path.display()
+        );
+        Self::compare_codecs(&config, &data_dir)
     }
 
-    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into());
-    let data_dir = Path::new(&manifest_dir).join("data/project_4");
+    /// Sort the same input once per run encoding and record how much smaller the
+    /// runs get and what that does to I/O volume and sort time. The compression
+    /// ratio is relative to 4-byte binary records.
+    fn compare_codecs(config: &Project4ExperimentConfig, data_dir: &Path) -> io::Result<()> {
+        let codecs_csv_path = data_dir.join(&config.codecs_csv);
+        let mut writer = BufWriter::new(File::create(&codecs_csv_path)?);
+        writeln!(
+            writer,
+            "min_value,max_value,total_numbers,run_codec,run_count,run_bytes,compression_ratio,run_generation_ms,merge_ms,modelled_io_ms,block_reads,block_writes,bytes_read,bytes_written"
+        )?;
+
+        let total_numbers = config.codec_total_numbers;
+        for &(min_value, max_value) in &config.codec_value_ranges {
+            let mut source_generator = SourceFileGenerator::with_output_dir(
+                total_numbers,
+                min_value,
+                max_value,
+                data_dir,
+                format!("input_codecs_{}_{}.txt", min_value, max_value),
+            )?;
+            source_generator.device = config.base.backing.clone();
+            source_generator.codec = config.base.codecs.input.clone();
+            source_generator.generate_file()?;
+            let source_path = source_generator.output_file_path.clone();
+
+            for run_codec in &config.run_codecs {
+                let codecs = Codecs {
+                    runs: run_codec.clone(),
+                    ..config.base.codecs.clone()
+                };
+                let merge_config = Project4Config {
+                    initial_k: config.codec_initial_k,
+                    codecs: codecs.clone(),
+                    ..config.base.clone()
+                };
+
+                let run_io_stats = merge_config.io_stats();
+                let start_time = Instant::now();
+                let mut run_generator = RunGenerator::with_codecs(
+                    &source_path,
+                    &merge_config.runs_dir,
+                    merge_config.initial_k,
+                    run_io_stats.clone(),
+                    codecs.clone(),
+                )?;
+                run_generator.generate_run_file()?;
+                let run_generation_ms = start_time.elapsed().as_millis();
+                let run_io = run_io_stats.snapshot();
+                let mut run_bytes = 0;
+                for run in merge_config
+                    .backing
+                    .list_files(Path::new(&merge_config.runs_dir))?
+                {
+                    run_bytes += merge_config.backing.size(&run)?;
+                }
+
+                let mut merger = KWayLoserTreeMerger::new(merge_config)?;
+                let start_time = Instant::now();
+                merger.merge()?;
+                let merge_ms = start_time.elapsed().as_millis();
+                RunValidator::validate_sorted_output(
+                    config.base.backing.as_ref(),
+                    &codecs,
+                    &source_path,
+                    Path::new(&config.base.output_file),
+                )?;
+                let total_io = run_io + merger.io.snapshot();
+
+                let compression_ratio = (4 * total_numbers) as f64 / run_bytes.max(1) as f64;
+                println!(
+                    "[project_4] Runs as {} for values {}..={}: {} bytes ({:.2}x vs binary), {} bytes moved, modelled I/O {:.1} ms",
+                    run_codec.name(),
+                    min_value,
+                    max_value,
+                    run_bytes,
+                    compression_ratio,
+                    total_io.bytes_read + total_io.bytes_written,
+                    total_io.modelled_ms()
+                );
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{:.3},{},{},{:.3},{},{},{},{}",
+                    min_value,
+                    max_value,
+                    total_numbers,
+                    run_codec.name(),
+                    run_generator.run_count,
+                    run_bytes,
+                    compression_ratio,
+                    run_generation_ms,
+                    merge_ms,
+                    total_io.modelled_ms(),
+                    total_io.block_reads,
+                    total_io.block_writes,
+                    total_io.bytes_read,
+                    total_io.bytes_written
+                )?;
+            }
+        }
 
-    let source_generator = SourceFileGenerator::with_output_dir(
-        config.total_numbers,
-        config.min_value,
-        config.max_value,
-        &data_dir,
-        &config.source_file,
-    )?;
-    source_generator.generate_file()?;
+        writer.flush()?;
+        println!(
+            "[project_4] Codec comparison written to {}",
+            codecs_csv_path.display()
+        );
+        Ok(())
+    }
+}
 
-    let source_path = source_generator.output_file_path.clone();
-    let mut run_generator =
-        RunGenerator::with_dirs(source_path, &config.runs_dir, config.initial_k)?;
-    run_generator.generate_run_file()?;
+/// What one merge pass did: each merge reads `fan_ins[i]` runs and writes one run.
+#[derive(Debug, Clone)]
+pub struct MergePassReport {
+    /// 1-based pass number.
+    pub pass: usize,
+    /// Number of runs before the pass.
+    pub runs_before: usize,
+    /// Fan-in of every merge performed in this pass.
+    pub fan_ins: Vec<usize>,
+    /// Number of runs after the pass; runs not merged are carried over untouched.
+    pub runs_after: usize,
+    /// Bytes written by this pass.
+    pub bytes_written: u64,
+    /// Block reads, block writes and file opens of this pass.
+    pub io: IoCounters,
+}
 
-    Ok(())
+impl MergePassReport {
+    /// Fan-ins with repeats collapsed, e.g. `15, 16x65`.
+    pub fn describe_fan_ins(&self) -> String {
+        let mut groups: Vec<(usize, usize)> = Vec::new();
+        for &fan_in in &self.fan_ins {
+            match groups.last_mut() {
+                Some((last, count)) if *last == fan_in => *count += 1,
+                _ => groups.push((fan_in, 1)),
+            }
+        }
+        groups
+            .iter()
+            .map(|&(fan_in, count)| {
+                if count == 1 {
+                    fan_in.to_string()
+                } else {
+                    format!("{}x{}", fan_in, count)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
 /// Merge driver that coordinates loser-tree based k-way merging with buffer pooling.
 pub struct KWayLoserTreeMerger {
     config: Project4Config,
     run_files: Vec<PathBuf>,
+    /// Pass structure of the last call to `merge`.
+    pub passes: Vec<MergePassReport>,
+    /// Buffer activity of the last call to `merge`.
+    pub stats: MergeStats,
+    /// Plan chosen from `memory_budget` by the last call to `merge`.
+    pub memory_plan: Option<MemoryPlan>,
+    /// Block I/O of the last call to `merge`, shared with the reader and writer threads.
+    pub io: IoStats,
 }
 
 impl KWayLoserTreeMerger {
     pub fn new(config: Project4Config) -> io::Result<Self> {
-        let mut run_files = Vec::new();
         let runs_dir = Path::new(&config.runs_dir);
-        if runs_dir.exists() {
-            for entry in fs::read_dir(runs_dir)? {
-                let entry = entry?;
-                if entry.file_type()?.is_file() {
-                    run_files.push(entry.path());
-                }
-            }
+        let run_files = if config.backing.exists(runs_dir) {
+            // Files written with another codec are left over from earlier runs.
+            let mut run_files = config.backing.list_files(runs_dir)?;
+            run_files.retain(|path| {
+                path.file_name().is_some_and(|name| {
+                    parse_run_id(&name.to_string_lossy(), config.codecs.runs.as_ref()).is_some()
+                })
+            });
+            run_files
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            run_files,
+            passes: Vec::new(),
+            stats: MergeStats::default(),
+            memory_plan: None,
+            io: config.io_stats(),
+            config,
+        })
+    }
+
+    /// Smallest number of passes that merges `runs` runs with fan-in at most `max_k`.
+    pub fn pass_count(runs: usize, max_k: usize) -> usize {
+        let mut passes = 1;
+        let mut capacity = max_k;
+        while capacity < runs {
+            capacity = capacity.saturating_mul(max_k);
+            passes += 1;
+        }
+        passes
+    }
+
+    /// Fan-ins of the merges in a pass that starts with `runs` runs and has
+    /// `passes_left` passes to go (this one included).
+    ///
+    /// Every later pass can merge at most `max_k` runs per output run, so this pass
+    /// only has to leave `max_k^(passes_left - 1)` runs behind. It merges just
+    /// enough runs to get there: full `max_k`-way merges plus one smaller merge
+    /// that absorbs the remainder. On the first pass this is the classic choice of
+    /// a reduced first fan-in, which keeps the pass count minimal while rewriting
+    /// as little data as possible.
+    pub fn plan_pass(runs: usize, max_k: usize, passes_left: usize) -> Vec<usize> {
+        if passes_left <= 1 {
+            return vec![runs];
+        }
+        let target = max_k.saturating_pow((passes_left - 1) as u32);
+        let reduction = runs.saturating_sub(target);
+        let merges = reduction.div_ceil(max_k - 1);
+        if merges == 0 {
+            return Vec::new();
         }
-        run_files.sort();
-        Ok(Self { config, run_files })
+        let mut fan_ins = vec![max_k; merges];
+        fan_ins[0] = reduction - (merges - 1) * (max_k - 1) + 1;
+        fan_ins
     }
 
     pub fn merge(&mut self) -> io::Result<()> {
@@ -127,28 +582,238 @@ impl KWayLoserTreeMerger {
             return Ok(());
         }
 
-        fs::create_dir_all(
+        let device = self.config.backing.clone();
+        let mut runs = Vec::with_capacity(self.run_files.len());
+        for path in &self.run_files {
+            runs.push((device.size(path)?, path.clone()));
+        }
+        if let Some(memory_budget) = self.config.memory_budget {
+            self.apply_memory_plan(memory_budget, &runs)?;
+        }
+        if self.config.max_k < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_k must be at least 2",
+            ));
+        }
+
+        device.create_dir_all(
             Path::new(&self.config.output_file)
                 .parent()
-                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid output path"))?,
+                .ok_or_else(|| io::Error::other("invalid output path"))?,
+        )?;
+        let temp_dir = PathBuf::from(&self.config.temp_dir);
+        let _ = device.remove_dir_all(&temp_dir);
+        device.create_dir_all(&temp_dir)?;
+
+        self.passes.clear();
+        self.stats = MergeStats::default();
+        self.io = self.config.io_stats();
+
+        // Every pass writes into the temp dir and the result is only moved into
+        // place at the end, so a failed merge leaves neither temp files nor a
+        // half-written output behind.
+        let merged = self
+            .merge_passes(runs, &temp_dir)
+            .and_then(|merged| device.rename(&merged, Path::new(&self.config.output_file)));
+        if let Err(err) = merged {
+            let _ = device.remove_dir_all(&temp_dir);
+            return Err(err);
+        }
+
+        device.remove_dir_all(&temp_dir)?;
+        println!(
+            "[project_4] Extra buffers by {}: {} refills, {} buffer swaps, {} stalls",
+            self.config.extra_buffer_policy.label(),
+            self.stats.refills,
+            self.stats.buffer_swaps,
+            self.stats.stalls
+        );
+        println!(
+            "[project_4] Background I/O: read {:.1} ms (waited {:.1} ms), write {:.1} ms (waited {:.1} ms), overlap {:.1}%",
+            self.stats.read_time.as_secs_f64() * 1000.0,
+            self.stats.read_stall_time.as_secs_f64() * 1000.0,
+            self.stats.write_time.as_secs_f64() * 1000.0,
+            self.stats.write_stall_time.as_secs_f64() * 1000.0,
+            self.stats.overlap() * 100.0
+        );
+        let io = self.io.snapshot();
+        println!(
+            "[project_4] Modelled disk: {:.1} ms for {} block reads and {} block writes",
+            io.modelled_ms(),
+            io.block_reads,
+            io.block_writes
+        );
+        Ok(())
+    }
+
+    /// Run every pass over `runs`, writing into `temp_dir`, and return the path
+    /// of the single run that is left.
+    fn merge_passes(
+        &mut self,
+        mut runs: Vec<(u64, PathBuf)>,
+        temp_dir: &Path,
+    ) -> io::Result<PathBuf> {
+        let device = self.config.backing.clone();
+        let max_k = self.config.max_k;
+        let total_passes = Self::pass_count(runs.len(), max_k);
+        for pass in 1..=total_passes {
+            let fan_ins = Self::plan_pass(runs.len(), max_k, total_passes - pass + 1);
+            let runs_before = runs.len();
+            // Merge the shortest runs first so the reduced merge touches the least data.
+            runs.sort();
+            let mut remaining = runs.into_iter();
+            let mut next_runs = Vec::with_capacity(runs_before);
+            let mut bytes_written = 0;
+            let io_before = self.io.snapshot();
+            for (idx, &fan_in) in fan_ins.iter().enumerate() {
+                let inputs: Vec<PathBuf> = remaining
+                    .by_ref()
+                    .take(fan_in)
+                    .map(|(_, path)| path)
+                    .collect();
+                let codec = if pass == total_passes {
+                    self.config.codecs.output.clone()
+                } else {
+                    self.config.codecs.runs.clone()
+                };
+                let output = temp_dir.join(format!("pass_{}_{}.{}", pass, idx, codec.extension()));
+                self.merge_runs(&inputs, &output, codec.as_ref())?;
+                for input in inputs.iter().filter(|input| input.starts_with(temp_dir)) {
+                    device.remove_file(input)?;
+                }
+                let length = device.size(&output)?;
+                bytes_written += length;
+                next_runs.push((length, output));
+            }
+            next_runs.extend(remaining);
+            runs = next_runs;
+
+            let report = MergePassReport {
+                pass,
+                runs_before,
+                fan_ins,
+                runs_after: runs.len(),
+                bytes_written,
+                io: self.io.snapshot().since(&io_before),
+            };
+            println!(
+                "[project_4] Pass {}/{}: {} runs -> {} runs, fan-ins {}, {} bytes written, {} block reads, {} block writes, {:.1} ms modelled I/O",
+                pass,
+                total_passes,
+                report.runs_before,
+                report.runs_after,
+                report.describe_fan_ins(),
+                report.bytes_written,
+                report.io.block_reads,
+                report.io.block_writes,
+                report.io.modelled_ms()
+            );
+            self.passes.push(report);
+        }
+        runs.pop()
+            .map(|(_, path)| path)
+            .ok_or_else(|| io::Error::other("merge produced no output run"))
+    }
+
+    /// Replace the merge parameters with the cheapest plan that fits `memory_budget`.
+    fn apply_memory_plan(
+        &mut self,
+        memory_budget: usize,
+        runs: &[(u64, PathBuf)],
+    ) -> io::Result<()> {
+        let run_sizes: Vec<u64> = runs.iter().map(|(size, _)| *size).collect();
+        let bytes_per_element = Self::sample_bytes_per_element(
+            self.config.backing.as_ref(),
+            self.config.codecs.runs.as_ref(),
+            runs,
+        )?;
+        let plan = MemoryPlan::choose(
+            memory_budget,
+            &run_sizes,
+            bytes_per_element,
+            self.config.output_buffers,
+            &self.config.disk,
         )?;
+        println!(
+            "[project_4] Memory plan for {} bytes: k = {}, {} elements per buffer, {} extra input buffers, {} passes",
+            plan.memory_budget,
+            plan.max_k,
+            plan.buffer_capacity,
+            plan.extra_input_buffers,
+            plan.passes
+        );
+        println!(
+            "[project_4] Predicted cost: {:.1} ms ({} accesses, {} bytes transferred)",
+            plan.predicted_ms, plan.predicted_accesses, plan.predicted_bytes
+        );
+        self.config.max_k = plan.max_k;
+        self.config.buffer_capacity = plan.buffer_capacity;
+        self.config.extra_input_buffers = plan.extra_input_buffers;
+        self.memory_plan = Some(plan);
+        Ok(())
+    }
+
+    /// Average bytes per element on disk, measured on the largest run.
+    fn sample_bytes_per_element(
+        device: &dyn BlockDevice,
+        codec: &dyn RecordCodec,
+        runs: &[(u64, PathBuf)],
+    ) -> io::Result<f64> {
+        let Some((size, path)) = runs.iter().max() else {
+            return Ok(1.0);
+        };
+        let mut reader = codec.decoder(Box::new(std::io::BufReader::new(device.open(path)?)));
+        let mut count = 0_u64;
+        while reader.next_record()?.is_some() {
+            count += 1;
+        }
+        Ok(if count == 0 {
+            1.0
+        } else {
+            *size as f64 / count as f64
+        })
+    }
 
-        // Determine how many runs to merge simultaneously.
-        let active_k = usize::min(self.config.max_k, self.run_files.len());
+    /// Merge `inputs` into a single sorted run at `output`, encoded with `codec`, in one k-way pass.
+    fn merge_runs(
+        &mut self,
+        inputs: &[PathBuf],
+        output: &Path,
+        codec: &dyn RecordCodec,
+    ) -> io::Result<()> {
+        let mut writer = WriteBehind::new(
+            self.io.create_records(output, codec)?,
+            self.config.output_buffers,
+            self.config.buffer_capacity,
+        );
 
-        // Prepare buffer pool: one primary buffer per active run plus the configured extras.
+        // Prepare buffer pool: one primary buffer per input run plus the configured extras.
         let mut buffer_pool = BufferPool::new(
-            active_k + self.config.extra_input_buffers,
+            inputs.len() + self.config.extra_input_buffers,
             self.config.buffer_capacity,
+            self.config.extra_buffer_policy,
         );
 
+        // Declared before the run buffers so it is dropped after their prefetchers.
+        let readers = ReaderPool::new(self.config.reader_threads.clamp(1, inputs.len().max(1)));
+
+        // Request the first buffer of every run before waiting on any of them,
+        // so the initial reads proceed in parallel on the reader threads.
+        let mut prefetchers = Vec::with_capacity(inputs.len());
+        for run_path in inputs {
+            let reader = self
+                .io
+                .open_records(run_path, self.config.codecs.runs.as_ref())?;
+            let mut prefetcher = readers.prefetcher(reader, self.config.buffer_capacity);
+            prefetcher.request(buffer_pool.acquire())?;
+            prefetchers.push(prefetcher);
+        }
+
         // Initialise run buffers.
-        let mut run_buffers = Vec::with_capacity(active_k);
-        for (idx, run_path) in self.run_files.iter().take(active_k).enumerate() {
-            let file = File::open(run_path)?;
-            let reader = InputElementReader::new(file)?;
-            let mut run_buffer =
-                RunBuffer::new(idx, reader, self.config.buffer_capacity, &mut buffer_pool)?;
+        let mut run_buffers = Vec::with_capacity(inputs.len());
+        for (idx, prefetcher) in prefetchers.into_iter().enumerate() {
+            let mut run_buffer = RunBuffer::new(idx, prefetcher, self.config.buffer_capacity)?;
             if run_buffer.is_finished() {
                 // Skip empty runs but keep buffer bookkeeping consistent.
                 buffer_pool.release(run_buffer.take_primary_buffer());
@@ -158,44 +823,46 @@ impl KWayLoserTreeMerger {
         }
 
         if run_buffers.is_empty() {
-            println!("[project_4] All runs are empty");
+            self.stats.write_time += writer.finish()?;
             return Ok(());
         }
 
-        // Build loser tree with currently active runs.
-        let mut loser_tree = LoserTree::new(run_buffers.len());
-        loser_tree.build(&run_buffers);
+        // Each leaf holds the head of one run; finished runs sort last.
+        let mut loser_tree =
+            LoserTree::from_ord(run_buffers.iter().map(RunBuffer::state).collect());
 
         buffer_pool.assign_extra_buffer(&mut run_buffers)?;
 
-        let output_file = File::create(&self.config.output_file)?;
-        let mut writer = BufWriter::new(output_file);
-
+        let mut active_runs = run_buffers.len();
         loop {
             let winner_idx = loser_tree.winner();
-            let winner_key = run_buffers[winner_idx].current_value();
-
-            if winner_key.is_none() {
+            let ElementState::Active(value) = *loser_tree.winner_value() else {
                 break;
-            }
-
-            let value = winner_key.unwrap();
-            write!(writer, "{} ", value)?;
+            };
+            writer.push(value)?;
 
             run_buffers[winner_idx].advance(&mut buffer_pool)?;
-            buffer_pool.assign_extra_buffer(&mut run_buffers)?;
-            loser_tree.replay(winner_idx, &run_buffers);
-
-            if run_buffers.iter().all(RunBuffer::is_finished) {
-                break;
+            if run_buffers[winner_idx].is_finished() {
+                active_runs -= 1;
+                if active_runs == 0 {
+                    break;
+                }
             }
+            buffer_pool.assign_extra_buffer(&mut run_buffers)?;
+            loser_tree.replace(winner_idx, run_buffers[winner_idx].state());
         }
 
         for run in run_buffers.iter_mut() {
+            self.stats.refills += run.refill_count;
+            self.stats.buffer_swaps += run.buffer_swaps;
+            self.stats.stalls += run.stalls;
+            self.stats.read_time += run.read_time;
+            self.stats.read_stall_time += run.stall_time;
             run.release_all_buffers(&mut buffer_pool);
         }
 
-        writer.flush()?;
+        self.stats.write_stall_time += writer.stall_time;
+        self.stats.write_time += writer.finish()?;
         Ok(())
     }
 }
@@ -204,15 +871,20 @@ impl KWayLoserTreeMerger {
 struct BufferPool {
     buffers: Vec<Vec<i32>>,
     capacity: usize,
+    policy: ExtraBufferPolicy,
 }
 
 impl BufferPool {
-    fn new(buffer_count: usize, capacity: usize) -> Self {
+    fn new(buffer_count: usize, capacity: usize, policy: ExtraBufferPolicy) -> Self {
         let mut buffers = Vec::with_capacity(buffer_count);
         for _ in 0..buffer_count {
             buffers.push(Vec::with_capacity(capacity));
         }
-        Self { buffers, capacity }
+        Self {
+            buffers,
+            capacity,
+            policy,
+        }
     }
 
     fn acquire(&mut self) -> Vec<i32> {
@@ -235,12 +907,19 @@ impl BufferPool {
             return Ok(());
         }
 
-        if let Some((idx, _)) = run_buffers
-            .iter_mut()
+        let candidates = run_buffers
+            .iter()
             .enumerate()
-            .filter(|(_, run)| !run.is_finished() && !run.has_secondary_buffer())
-            .max_by_key(|(_, run)| run.refill_count())
-        {
+            .filter(|(_, run)| run.can_prefetch());
+        let chosen = match self.policy {
+            ExtraBufferPolicy::Forecasting => candidates
+                .min_by_key(|(_, run)| run.last_buffered_value())
+                .map(|(idx, _)| idx),
+            ExtraBufferPolicy::MostRefills => candidates
+                .max_by_key(|(_, run)| run.refill_count())
+                .map(|(idx, _)| idx),
+        };
+        if let Some(idx) = chosen {
             let buffer = self.acquire();
             if let Some(buffer) = run_buffers[idx].try_prefetch(buffer)? {
                 self.release(buffer);
@@ -251,37 +930,183 @@ impl BufferPool {
     }
 }
 
+/// Result of one background fill: the buffer and the time spent reading it.
+type Filled = io::Result<(Vec<i32>, Duration)>;
+
+/// One fill request queued on the `ReaderPool`.
+struct ReadJob {
+    reader: Arc<Mutex<Box<dyn RecordDecoder>>>,
+    buffer: Vec<i32>,
+    capacity: usize,
+    /// Set when the requesting `Prefetcher` is dropped; the job is skipped.
+    cancelled: Arc<AtomicBool>,
+    /// The only sender of the request's reply channel, so a worker that dies
+    /// with the job disconnects the channel.
+    reply: Sender<Filled>,
+}
+
+/// A fixed number of reader threads shared by all runs of a merge, so the
+/// thread count does not grow with the fan-in. Dropping the pool lets the
+/// threads finish the queued jobs and joins them.
+struct ReaderPool {
+    jobs: Option<Sender<ReadJob>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ReaderPool {
+    fn new(threads: usize) -> Self {
+        let (jobs, job_rx) = mpsc::channel::<ReadJob>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                thread::spawn(move || {
+                    loop {
+                        // The lock is released before the read, so workers read in parallel.
+                        let job = job_rx.lock().unwrap().recv();
+                        let Ok(mut job) = job else {
+                            break;
+                        };
+                        if job.cancelled.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let start = Instant::now();
+                        let mut reader = job.reader.lock().unwrap();
+                        let filled =
+                            RunBuffer::fill_buffer(reader.as_mut(), &mut job.buffer, job.capacity)
+                                .map(|_| (job.buffer, start.elapsed()));
+                        // The run may have been dropped after a failed merge.
+                        let _ = job.reply.send(filled);
+                    }
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    fn prefetcher(&self, reader: Box<dyn RecordDecoder>, capacity: usize) -> Prefetcher {
+        Prefetcher {
+            reader: Arc::new(Mutex::new(reader)),
+            capacity,
+            jobs: self.jobs.clone().unwrap(),
+            response: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Drop for ReaderPool {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Reads of one run file on the shared `ReaderPool`. Empty buffers sent to it
+/// come back filled with the next elements of the run. A run has at most one
+/// request in flight; each request gets its own reply channel.
+struct Prefetcher {
+    reader: Arc<Mutex<Box<dyn RecordDecoder>>>,
+    capacity: usize,
+    jobs: Sender<ReadJob>,
+    /// Reply channel of the request in flight.
+    response: Option<Receiver<Filled>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Prefetcher {
+    fn request(&mut self, buffer: Vec<i32>) -> io::Result<()> {
+        debug_assert!(self.response.is_none(), "a run has one request in flight");
+        let (reply, response) = mpsc::channel();
+        self.jobs
+            .send(ReadJob {
+                reader: self.reader.clone(),
+                buffer,
+                capacity: self.capacity,
+                cancelled: self.cancelled.clone(),
+                reply,
+            })
+            .map_err(|_| Self::stopped())?;
+        self.response = Some(response);
+        Ok(())
+    }
+
+    fn wait(&mut self) -> Filled {
+        let response = self.response.take().ok_or_else(Self::stopped)?;
+        response.recv().map_err(|_| Self::stopped())?
+    }
+
+    /// The next filled buffer if it is already there.
+    fn ready(&mut self) -> Option<Filled> {
+        let filled = match self.response.as_ref()?.try_recv() {
+            Ok(filled) => filled,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(Self::stopped()),
+        };
+        self.response = None;
+        Some(filled)
+    }
+
+    /// The reader thread serving the request is gone, e.g. it panicked.
+    fn stopped() -> io::Error {
+        io::Error::other("run reader threads stopped")
+    }
+}
+
+impl Drop for Prefetcher {
+    // A queued read for a run that is gone would only waste a reader thread.
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
 /// Keeps track of buffered data for a single run.
 struct RunBuffer {
     id: usize,
-    reader: InputElementReader,
+    prefetcher: Prefetcher,
     primary: Vec<i32>,
     primary_pos: usize,
-    secondary: Option<Vec<i32>>,
+    /// A pooled buffer is being filled in the background (the secondary buffer).
+    prefetching: bool,
     buffer_capacity: usize,
     refill_count: u64,
+    buffer_swaps: u64,
+    stalls: u64,
+    read_time: Duration,
+    stall_time: Duration,
+    /// The reader has hit the end of the run file; no more data can be prefetched.
+    input_done: bool,
     finished: bool,
 }
 
 impl RunBuffer {
-    fn new(
-        id: usize,
-        mut reader: InputElementReader,
-        buffer_capacity: usize,
-        buffer_pool: &mut BufferPool,
-    ) -> io::Result<Self> {
-        let mut primary = buffer_pool.acquire();
-        Self::fill_buffer(&mut reader, &mut primary, buffer_capacity)?;
+    /// Wait for the first buffer, already requested on `prefetcher`. Nothing is
+    /// merged yet, so the whole wait is a stall.
+    fn new(id: usize, mut prefetcher: Prefetcher, buffer_capacity: usize) -> io::Result<Self> {
+        let start = Instant::now();
+        let (primary, read_time) = prefetcher.wait()?;
+        let stall_time = start.elapsed();
         let finished = primary.is_empty();
+        let input_done = primary.len() < buffer_capacity;
 
         Ok(Self {
             id,
-            reader,
+            prefetcher,
             primary,
             primary_pos: 0,
-            secondary: None,
+            prefetching: false,
             buffer_capacity,
             refill_count: if finished { 0 } else { 1 },
+            buffer_swaps: 0,
+            stalls: 1,
+            read_time,
+            stall_time,
+            input_done,
             finished,
         })
     }
@@ -294,6 +1119,14 @@ impl RunBuffer {
         }
     }
 
+    /// Loser tree key of this run: finished runs sort after every value.
+    fn state(&self) -> ElementState {
+        match self.current_value() {
+            Some(value) => ElementState::Active(value),
+            None => ElementState::Exhausted,
+        }
+    }
+
     fn advance(&mut self, buffer_pool: &mut BufferPool) -> io::Result<()> {
         if self.finished {
             return Ok(());
@@ -304,53 +1137,74 @@ impl RunBuffer {
             return Ok(());
         }
 
-        // Primary buffer exhausted; attempt to swap with the secondary or fetch new data.
+        // Primary buffer exhausted; swap in the prefetched buffer or read synchronously.
         self.primary_pos = 0;
-
-        if let Some(secondary) = self.secondary.take() {
-            let mut old_primary = std::mem::replace(&mut self.primary, secondary);
-            old_primary.clear();
+        let mut old_primary = std::mem::take(&mut self.primary);
+        old_primary.clear();
+
+        if !self.prefetching {
+            if self.input_done {
+                buffer_pool.release(old_primary);
+                self.finished = true;
+                return Ok(());
+            }
+            // Nothing was prefetched for this run: reuse its buffer and wait for the read.
+            self.prefetcher.request(old_primary)?;
+        } else {
             buffer_pool.release(old_primary);
-            self.refill_count += 1;
-            self.finished = self.primary.is_empty();
-            return Ok(());
         }
 
-        let mut buffer = buffer_pool.acquire();
-        Self::fill_buffer(&mut self.reader, &mut buffer, self.buffer_capacity)?;
+        let ready = if self.prefetching {
+            self.prefetcher.ready()
+        } else {
+            None
+        };
+        let (buffer, read_time) = match ready {
+            Some(filled) => filled?,
+            None => {
+                let start = Instant::now();
+                let filled = self.prefetcher.wait()?;
+                self.stall_time += start.elapsed();
+                self.stalls += 1;
+                filled
+            }
+        };
+        self.read_time += read_time;
+        self.input_done = buffer.len() < self.buffer_capacity;
+        if self.prefetching && !buffer.is_empty() {
+            self.buffer_swaps += 1;
+        }
+        self.prefetching = false;
 
         if buffer.is_empty() {
             buffer_pool.release(buffer);
             self.finished = true;
-            let mut old_primary = std::mem::take(&mut self.primary);
-            old_primary.clear();
-            buffer_pool.release(old_primary);
         } else {
-            let mut old_primary = std::mem::replace(&mut self.primary, buffer);
-            old_primary.clear();
-            buffer_pool.release(old_primary);
+            self.primary = buffer;
             self.refill_count += 1;
         }
 
         Ok(())
     }
 
-    fn try_prefetch(&mut self, mut buffer: Vec<i32>) -> io::Result<Option<Vec<i32>>> {
-        if self.finished || self.secondary.is_some() {
+    /// Start filling `buffer` in the background, or hand it back if the run cannot use it.
+    fn try_prefetch(&mut self, buffer: Vec<i32>) -> io::Result<Option<Vec<i32>>> {
+        if !self.can_prefetch() {
             return Ok(Some(buffer));
         }
+        self.prefetcher.request(buffer)?;
+        self.prefetching = true;
+        Ok(None)
+    }
 
-        Self::fill_buffer(&mut self.reader, &mut buffer, self.buffer_capacity)?;
-        if buffer.is_empty() {
-            Ok(Some(buffer))
-        } else {
-            self.secondary = Some(buffer);
-            Ok(None)
-        }
+    fn can_prefetch(&self) -> bool {
+        !self.finished && !self.input_done && !self.prefetching
     }
 
-    fn has_secondary_buffer(&self) -> bool {
-        self.secondary.is_some()
+    /// Largest key currently held in memory for this run; the run whose value is
+    /// smallest will need its next buffer first.
+    fn last_buffered_value(&self) -> Option<i32> {
+        self.primary.last().copied()
     }
 
     fn refill_count(&self) -> u64 {
@@ -366,9 +1220,7 @@ impl RunBuffer {
         if !primary.is_empty() || primary.capacity() > 0 {
             buffer_pool.release(primary);
         }
-        if let Some(buffer) = self.secondary.take() {
-            buffer_pool.release(buffer);
-        }
+        // An in-flight prefetch buffer is dropped together with the prefetcher.
     }
 
     fn take_primary_buffer(&mut self) -> Vec<i32> {
@@ -376,13 +1228,13 @@ impl RunBuffer {
     }
 
     fn fill_buffer(
-        reader: &mut InputElementReader,
+        reader: &mut dyn RecordDecoder,
         buffer: &mut Vec<i32>,
         capacity: usize,
     ) -> io::Result<()> {
         buffer.clear();
         for _ in 0..capacity {
-            match reader.next_element()? {
+            match reader.next_record()? {
                 Some(value) => buffer.push(value),
                 None => break,
             }
@@ -391,92 +1243,119 @@ impl RunBuffer {
     }
 }
 
-/// Implements a loser tree for selecting the next element among k runs.
-struct LoserTree {
-    losers: Vec<usize>,
+/// Output side of the merge: full buffers are handed to a writer thread and
+/// come back empty once written, so formatting and writing overlap with merging.
+struct WriteBehind {
+    current: Vec<i32>,
+    capacity: usize,
+    full: Option<Sender<Vec<i32>>>,
+    free: Receiver<Vec<i32>>,
+    handle: Option<JoinHandle<io::Result<Duration>>>,
+    /// Time spent waiting for the writer thread to return a buffer.
+    stall_time: Duration,
 }
 
-impl LoserTree {
-    fn new(k: usize) -> Self {
-        Self { losers: vec![0; k] }
-    }
-
-    fn build(&mut self, runs: &[RunBuffer]) {
-        for i in 0..runs.len() {
-            self.losers[i] = 0;
+impl WriteBehind {
+    fn new(mut writer: Box<dyn RecordEncoder>, buffer_count: usize, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (full, full_rx) = mpsc::channel::<Vec<i32>>();
+        let (free_tx, free) = mpsc::channel();
+        // One buffer is filled by the merge, the rest circulate through the writer.
+        for _ in 1..buffer_count.max(1) {
+            let _ = free_tx.send(Vec::with_capacity(capacity));
         }
-        for i in 0..runs.len() {
-            self.replay(i, runs);
+        let handle = thread::spawn(move || {
+            let mut busy = Duration::ZERO;
+            for mut buffer in full_rx {
+                let start = Instant::now();
+                for &value in &buffer {
+                    writer.write_record(value)?;
+                }
+                busy += start.elapsed();
+                buffer.clear();
+                // The merge may already be done and no longer want buffers back.
+                let _ = free_tx.send(buffer);
+            }
+            let start = Instant::now();
+            writer.finish()?;
+            Ok(busy + start.elapsed())
+        });
+        Self {
+            current: Vec::with_capacity(capacity),
+            capacity,
+            full: Some(full),
+            free,
+            handle: Some(handle),
+            stall_time: Duration::ZERO,
         }
     }
 
-    fn winner(&self) -> usize {
-        self.losers[0]
+    fn push(&mut self, value: i32) -> io::Result<()> {
+        self.current.push(value);
+        if self.current.len() < self.capacity {
+            return Ok(());
+        }
+        let full = std::mem::take(&mut self.current);
+        self.send(full)?;
+        // With a single buffer this waits for every write to finish.
+        let start = Instant::now();
+        self.current = match self.free.recv() {
+            Ok(buffer) => buffer,
+            Err(_) => return Err(self.writer_error()),
+        };
+        self.stall_time += start.elapsed();
+        Ok(())
     }
 
-    fn replay(&mut self, mut idx: usize, runs: &[RunBuffer]) {
-        let mut parent = (self.losers.len() + idx) / 2;
-        while parent > 0 {
-            let loser_idx = self.losers[parent];
-            if Self::compare(idx, loser_idx, runs) == Ordering::Greater {
-                self.losers[parent] = idx;
-                idx = loser_idx;
-            }
-            parent /= 2;
+    fn send(&mut self, buffer: Vec<i32>) -> io::Result<()> {
+        let sent = match &self.full {
+            Some(full) => full.send(buffer).is_ok(),
+            None => false,
+        };
+        if sent {
+            Ok(())
+        } else {
+            Err(self.writer_error())
         }
-        self.losers[0] = idx;
     }
 
-    fn compare(a: usize, b: usize, runs: &[RunBuffer]) -> Ordering {
-        let key_a = runs[a].current_value().unwrap_or(i32::MAX);
-        let key_b = runs[b].current_value().unwrap_or(i32::MAX);
-        key_b.cmp(&key_a)
+    /// Write out what is left, wait for the writer thread and return its busy time.
+    fn finish(mut self) -> io::Result<Duration> {
+        if !self.current.is_empty() {
+            let rest = std::mem::take(&mut self.current);
+            self.send(rest)?;
+        }
+        self.full = None;
+        self.join()
     }
-}
-
-/// Byte-wise reader that yields integers from a file without loading all data into memory.
-struct InputElementReader {
-    bytes: io::Bytes<std::io::BufReader<File>>,
-}
 
-impl InputElementReader {
-    fn new(file: File) -> io::Result<Self> {
-        let reader = std::io::BufReader::with_capacity(8 * 1024, file);
-        Ok(Self {
-            bytes: reader.bytes(),
-        })
+    fn join(&mut self) -> io::Result<Duration> {
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("output writer thread panicked"))),
+            None => Err(io::Error::other("output writer thread already stopped")),
+        }
     }
 
-    fn next_element(&mut self) -> io::Result<Option<i32>> {
-        let mut buf = Vec::new();
-
-        loop {
-            match self.bytes.next() {
-                Some(Ok(b)) if b.is_ascii_whitespace() => {
-                    if !buf.is_empty() {
-                        break;
-                    }
-                }
-                Some(Ok(b)) => {
-                    buf.push(b);
-                }
-                Some(Err(e)) => return Err(e),
-                None => {
-                    if buf.is_empty() {
-                        return Ok(None);
-                    } else {
-                        break;
-                    }
-                }
-            }
+    // The writer thread only stops early on an error; collect it.
+    fn writer_error(&mut self) -> io::Error {
+        self.full = None;
+        match self.join() {
+            Err(err) => err,
+            Ok(_) => io::Error::other("output writer thread stopped"),
         }
+    }
+}
 
-        let str_value =
-            std::str::from_utf8(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        let value = str_value
-            .parse::<i32>()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        Ok(Some(value))
+impl Drop for WriteBehind {
+    // A merge that fails before `finish` still waits for the writer thread, so
+    // nothing is written to the output after the caller has cleaned it up.
+    fn drop(&mut self) {
+        self.full = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
     }
 }
 
@@ -494,13 +1373,13 @@ pub struct RunStatisticsSummary {
 }
 
 impl RunStatisticsSummary {
-    pub fn from_runs(runs: &[PathBuf]) -> io::Result<Self> {
+    pub fn from_runs(runs: &[PathBuf], codec: &dyn RecordCodec) -> io::Result<Self> {
         let mut entries = Vec::with_capacity(runs.len());
         for (idx, run_path) in runs.iter().enumerate() {
             let file = File::open(run_path)?;
-            let mut reader = InputElementReader::new(file)?;
+            let mut reader = codec.decoder(Box::new(std::io::BufReader::new(file)));
             let mut count = 0_u64;
-            while reader.next_element()?.is_some() {
+            while reader.next_record()?.is_some() {
                 count += 1;
             }
             entries.push(RunStatistic {
@@ -543,3 +1422,345 @@ impl RunStatisticsSummary {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{FaultPlan, FaultyDevice, MemoryDevice, run_path};
+
+    const INPUT: &str = "/mem/project_4/input.txt";
+
+    /// Memory-backed config that merges at most 4 runs at a time with `codecs`.
+    fn memory_config(codecs: &Codecs) -> (MemoryDevice, Project4Config) {
+        let device = MemoryDevice::new();
+        let config = Project4Config {
+            runs_dir: "/mem/project_4/runs".into(),
+            output_file: "/mem/project_4/sorted_output.txt".into(),
+            temp_dir: "/mem/project_4/temp".into(),
+            max_k: 4,
+            buffer_capacity: 16,
+            backing: Arc::new(device.clone()),
+            codecs: codecs.clone(),
+            ..Project4Config::default()
+        };
+        (device, config)
+    }
+
+    /// Memory-backed config with runs already generated from 3000 numbers.
+    fn config_with_runs(codecs: &Codecs) -> (MemoryDevice, Project4Config) {
+        let (device, config) = memory_config(codecs);
+        let mut source_generator =
+            SourceFileGenerator::with_output_dir(3000, -500, 500, "/mem/project_4", "input.txt")
+                .unwrap();
+        source_generator.device = config.backing.clone();
+        source_generator.codec = codecs.input.clone();
+        source_generator.generate_file().unwrap();
+        let mut run_generator = RunGenerator::with_codecs(
+            INPUT,
+            &config.runs_dir,
+            config.initial_k,
+            config.io_stats(),
+            codecs.clone(),
+        )
+        .unwrap();
+        run_generator.generate_run_file().unwrap();
+        (device, config)
+    }
+
+    /// Number of elements in the validated output of `config`.
+    fn validate_output(device: &MemoryDevice, config: &Project4Config) -> io::Result<u64> {
+        RunValidator::validate_sorted_output(
+            device,
+            &config.codecs,
+            Path::new(INPUT),
+            Path::new(&config.output_file),
+        )
+    }
+
+    #[test]
+    fn sorts_on_memory_device() {
+        let (device, config) = config_with_runs(&Codecs::default());
+        let mut merger = KWayLoserTreeMerger::new(config.clone()).unwrap();
+        merger.merge().unwrap();
+        assert_eq!(validate_output(&device, &config).unwrap(), 3000);
+        assert!(merger.passes.len() > 1);
+        assert!(!device.exists(Path::new(&config.temp_dir)));
+        assert!(device.files().iter().all(|path| path.starts_with("/mem")));
+    }
+
+    fn with_faults(
+        config: &Project4Config,
+        device: &MemoryDevice,
+        plan: FaultPlan,
+    ) -> Project4Config {
+        Project4Config {
+            backing: Arc::new(FaultyDevice::new(Arc::new(device.clone()), plan)),
+            ..config.clone()
+        }
+    }
+
+    #[test]
+    fn merge_fails_cleanly() {
+        let plans = [
+            (
+                FaultPlan {
+                    fail_read: Some(40),
+                    ..FaultPlan::default()
+                },
+                io::ErrorKind::Other,
+            ),
+            (
+                FaultPlan {
+                    fail_write: Some(5),
+                    ..FaultPlan::default()
+                },
+                io::ErrorKind::Other,
+            ),
+            (
+                FaultPlan {
+                    capacity: Some(4000),
+                    ..FaultPlan::default()
+                },
+                io::ErrorKind::StorageFull,
+            ),
+        ];
+        for (plan, kind) in plans {
+            let (device, config) = config_with_runs(&Codecs::default());
+            let runs = device.list_files(Path::new(&config.runs_dir)).unwrap();
+            let mut merger =
+                KWayLoserTreeMerger::new(with_faults(&config, &device, plan.clone())).unwrap();
+            let err = merger.merge().unwrap_err();
+            assert_eq!(err.kind(), kind, "{:?}", plan);
+            assert!(!device.exists(Path::new(&config.temp_dir)));
+            assert!(!device.exists(Path::new(&config.output_file)));
+            assert_eq!(
+                device.list_files(Path::new(&config.runs_dir)).unwrap(),
+                runs
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_output_fails_validation() {
+        // Every write succeeds, but each file keeps only its first 200 bytes.
+        let (device, config) = config_with_runs(&Codecs::default());
+        let plan = FaultPlan {
+            truncate_after: Some(200),
+            ..FaultPlan::default()
+        };
+        let mut merger = KWayLoserTreeMerger::new(with_faults(&config, &device, plan)).unwrap();
+        // A temp run cut in the middle of a number may already fail the merge.
+        let err = merger
+            .merge()
+            .and_then(|()| {
+                assert!(device.size(Path::new(&config.output_file))? <= 200);
+                validate_output(&device, &config)
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn merges_with_short_reads() {
+        let (device, config) = config_with_runs(&Codecs::default());
+        let plan = FaultPlan {
+            short_reads: Some(5),
+            ..FaultPlan::default()
+        };
+        let mut merger = KWayLoserTreeMerger::new(with_faults(&config, &device, plan)).unwrap();
+        merger.merge().unwrap();
+        assert_eq!(validate_output(&device, &config).unwrap(), 3000);
+    }
+
+    #[test]
+    fn sorts_with_mixed_codecs() {
+        let (device, config) = config_with_runs(&Codecs {
+            input: Arc::new(TextCodec),
+            runs: Arc::new(BinaryCodec),
+            output: Arc::new(VarintCodec),
+        });
+        let config = Project4Config {
+            output_file: "/mem/project_4/sorted_output.bin".into(),
+            memory_budget: Some(4096),
+            ..config
+        };
+        let mut merger = KWayLoserTreeMerger::new(config.clone()).unwrap();
+        merger.merge().unwrap();
+        assert_eq!(validate_output(&device, &config).unwrap(), 3000);
+    }
+
+    #[test]
+    fn merges_delta_runs() {
+        let (device, config) = config_with_runs(&Codecs {
+            runs: Arc::new(DeltaVarintCodec { block_records: 10 }),
+            ..Codecs::default()
+        });
+        let mut merger = KWayLoserTreeMerger::new(config.clone()).unwrap();
+        merger.merge().unwrap();
+        assert_eq!(validate_output(&device, &config).unwrap(), 3000);
+        assert!(merger.passes.len() > 1);
+    }
+
+    #[test]
+    fn pass_count_is_minimal() {
+        let cases = [
+            (1, 4, 1),
+            (4, 4, 1),
+            (5, 4, 2),
+            (16, 4, 2),
+            (17, 4, 3),
+            (64, 4, 3),
+            (65, 4, 4),
+            (100, 2, 7),
+        ];
+        for (runs, max_k, passes) in cases {
+            assert_eq!(
+                KWayLoserTreeMerger::pass_count(runs, max_k),
+                passes,
+                "{} runs, k = {}",
+                runs,
+                max_k
+            );
+        }
+    }
+
+    #[test]
+    fn plan_pass_reduces_only_the_first_fan_in() {
+        // 17 runs, k = 4: one 2-way merge brings the count down to 16, then 4 + 1 merges
+        assert_eq!(KWayLoserTreeMerger::plan_pass(17, 4, 3), [2]);
+        assert_eq!(KWayLoserTreeMerger::plan_pass(16, 4, 2), [4, 4, 4, 4]);
+        assert_eq!(KWayLoserTreeMerger::plan_pass(4, 4, 1), [4]);
+
+        for max_k in 2..=6 {
+            for initial in 1..=80 {
+                let total = KWayLoserTreeMerger::pass_count(initial, max_k);
+                let mut runs = initial;
+                for pass in 1..=total {
+                    let fan_ins = KWayLoserTreeMerger::plan_pass(runs, max_k, total - pass + 1);
+                    assert!(fan_ins.iter().all(|&f| f <= max_k && (f >= 2 || runs == 1)));
+                    assert!(fan_ins.iter().skip(1).all(|&f| f == max_k));
+                    let merged: usize = fan_ins.iter().sum();
+                    assert!(merged <= runs);
+                    runs = runs - merged + fan_ins.len();
+                    // after this pass the remaining passes must still be enough
+                    assert!(runs <= max_k.pow((total - pass) as u32));
+                }
+                assert_eq!(runs, 1, "{} runs, k = {}", initial, max_k);
+            }
+        }
+    }
+
+    #[test]
+    fn merges_shortest_runs_first() {
+        // 20 runs of distinct lengths 1..=20; every value names its run
+        let (device, config) = memory_config(&Codecs::default());
+        let config = Project4Config {
+            buffer_capacity: 4,
+            ..config
+        };
+        let mut sizes = Vec::new();
+        let mut expected = Vec::new();
+        for run in 0..20_i32 {
+            let length = (run * 7) % 20 + 1;
+            let path = run_path(&config.runs_dir, run as u64, config.codecs.runs.as_ref());
+            let mut encoder = TextCodec.encoder(device.create(&path).unwrap());
+            for i in 0..length {
+                encoder.write_record(run * 1000 + i).unwrap();
+                expected.push(run * 1000 + i);
+            }
+            encoder.finish().unwrap();
+            sizes.push(device.size(&path).unwrap());
+        }
+
+        let mut merger = KWayLoserTreeMerger::new(config.clone()).unwrap();
+        merger.merge().unwrap();
+        let fan_ins: Vec<Vec<usize>> = merger.passes.iter().map(|p| p.fan_ins.clone()).collect();
+        assert_eq!(fan_ins, [vec![2, 4], vec![4, 4, 4, 4], vec![4]]);
+        // the first pass rewrites only the six shortest runs
+        sizes.sort_unstable();
+        assert_eq!(
+            merger.passes[0].bytes_written,
+            sizes[..6].iter().sum::<u64>()
+        );
+
+        let output = device.read(&config.output_file).unwrap();
+        let output: Vec<i32> = String::from_utf8(output)
+            .unwrap()
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn runs_share_a_few_reader_threads() {
+        // Every run of a single wide merge is read by the same two threads.
+        let (device, config) = config_with_runs(&Codecs::default());
+        let runs = device
+            .list_files(Path::new(&config.runs_dir))
+            .unwrap()
+            .len();
+        let config = Project4Config {
+            max_k: runs,
+            extra_input_buffers: 8,
+            reader_threads: 2,
+            ..config
+        };
+        let mut merger = KWayLoserTreeMerger::new(config.clone()).unwrap();
+        merger.merge().unwrap();
+        assert_eq!(merger.passes.len(), 1);
+        assert!(merger.stats.buffer_swaps > 0);
+        // The first fill of every run happens before the merge starts, so it is a stall.
+        assert!(merger.stats.stalls >= runs as u64);
+        assert_eq!(validate_output(&device, &config).unwrap(), 3000);
+    }
+
+    struct PanickingDecoder;
+
+    impl RecordDecoder for PanickingDecoder {
+        fn next_record(&mut self) -> io::Result<Option<i32>> {
+            panic!("reader thread dies");
+        }
+    }
+
+    #[test]
+    fn dead_reader_thread_is_an_error() {
+        let readers = ReaderPool::new(1);
+        let mut prefetcher = readers.prefetcher(Box::new(PanickingDecoder), 16);
+        prefetcher.request(Vec::new()).unwrap();
+        let err = prefetcher.wait().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        // The only worker is gone, so new requests fail instead of blocking.
+        assert!(
+            prefetcher
+                .request(Vec::new())
+                .and_then(|()| prefetcher.wait())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn memory_plan_uses_the_device_model() {
+        let run_sizes = vec![40_000; 100];
+        let plan =
+            |disk: &DeviceModel| MemoryPlan::choose(64 * 1024, &run_sizes, 4.0, 2, disk).unwrap();
+        let (hdd, ssd) = (plan(&DeviceModel::hdd()), plan(&DeviceModel::ssd()));
+        for plan in [&hdd, &ssd] {
+            let buffers = plan.max_k + plan.extra_input_buffers + 2;
+            assert!(buffers * plan.buffer_capacity * 4 <= 64 * 1024);
+        }
+        // Seeks dominate on the hard disk, so the same plan costs far more there.
+        let hdd_cost_of_ssd_plan = MemoryPlan::predict(
+            64 * 1024,
+            &run_sizes,
+            4.0,
+            ssd.max_k,
+            ssd.buffer_capacity,
+            ssd.extra_input_buffers,
+            &DeviceModel::hdd(),
+        );
+        assert!(hdd.predicted_ms <= hdd_cost_of_ssd_plan.predicted_ms);
+        assert!(ssd.predicted_ms < hdd.predicted_ms);
+    }
+}
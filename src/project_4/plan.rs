@@ -0,0 +1,161 @@
+use super::KWayLoserTreeMerger;
+use crate::storage::DeviceModel;
+use std::io;
+
+/// Bytes an in-memory buffer spends per element (`i32`).
+const ELEMENT_MEMORY: usize = std::mem::size_of::<i32>();
+
+/// CPU time of the merge loop per element, plus per loser tree level.
+const ELEMENT_CPU_NS: f64 = 60.0;
+const COMPARISON_NS: f64 = 4.0;
+
+/// Time to transfer `bytes` in `accesses` separate random accesses on `model`.
+fn io_ms(model: &DeviceModel, accesses: u64, bytes: u64) -> f64 {
+    accesses as f64 * model.service_ms(0, false) + bytes as f64 / (model.transfer_mb_per_s * 1000.0)
+}
+
+/// Time the merge loop spends on `elements` elements with the given fan-in.
+fn cpu_ms(elements: f64, fan_in: usize) -> f64 {
+    let levels = (fan_in.max(2) as f64).log2().ceil();
+    elements * (ELEMENT_CPU_NS + levels * COMPARISON_NS) / 1e6
+}
+
+/// Merge parameters derived from a memory budget, with the predicted cost.
+#[derive(Debug, Clone)]
+pub struct MemoryPlan {
+    pub memory_budget: usize,
+    pub max_k: usize,
+    /// Elements per buffer; input, extra and output buffers all have this size.
+    pub buffer_capacity: usize,
+    pub extra_input_buffers: usize,
+    pub passes: usize,
+    /// Random accesses (buffer fills plus output flushes) over all passes.
+    pub predicted_accesses: u64,
+    /// Bytes read plus bytes written over all passes.
+    pub predicted_bytes: u64,
+    pub predicted_ms: f64,
+}
+
+impl MemoryPlan {
+    /// Choose fan-in, buffer size and extra buffer count for merging runs of the
+    /// given sizes (in bytes on disk) within `memory_budget` bytes of buffers.
+    ///
+    /// For each candidate fan-in the buffers are made as large as the budget
+    /// allows, because fewer, larger transfers save seeks. An extra input buffer
+    /// lets the next block of the run that runs dry first be read while the merge
+    /// keeps going (forecasting), so CPU and disk time overlap; without one the
+    /// two add up. More than one extra buffer cannot help a single disk, so
+    /// only 0 and 1 are considered.
+    pub fn choose(
+        memory_budget: usize,
+        run_sizes: &[u64],
+        bytes_per_element: f64,
+        output_buffers: usize,
+        model: &DeviceModel,
+    ) -> io::Result<MemoryPlan> {
+        let output_buffers = output_buffers.max(1);
+        let largest_k = run_sizes.len().max(2);
+        let mut best: Option<MemoryPlan> = None;
+        for max_k in Self::candidate_fan_ins(largest_k) {
+            for extra_input_buffers in [0, 1] {
+                let buffers = max_k + extra_input_buffers + output_buffers;
+                let buffer_capacity = memory_budget / (buffers * ELEMENT_MEMORY);
+                if buffer_capacity == 0 {
+                    continue;
+                }
+                let plan = Self::predict(
+                    memory_budget,
+                    run_sizes,
+                    bytes_per_element,
+                    max_k,
+                    buffer_capacity,
+                    extra_input_buffers,
+                    model,
+                );
+                if best
+                    .as_ref()
+                    .is_none_or(|best| plan.predicted_ms < best.predicted_ms)
+                {
+                    best = Some(plan);
+                }
+            }
+        }
+        best.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "memory budget of {} bytes cannot hold {} buffers of one element",
+                    memory_budget,
+                    2 + output_buffers
+                ),
+            )
+        })
+    }
+
+    /// Every fan-in up to 64, then geometrically spaced ones up to `largest_k`.
+    fn candidate_fan_ins(largest_k: usize) -> Vec<usize> {
+        let mut candidates: Vec<usize> = (2..=largest_k.min(64)).collect();
+        let mut k = 64.0_f64;
+        while (k as usize) < largest_k {
+            k *= 1.25;
+            candidates.push((k as usize).min(largest_k));
+        }
+        candidates.dedup();
+        candidates
+    }
+
+    /// Predicted cost of merging with fixed parameters, following the same pass
+    /// structure as `KWayLoserTreeMerger::merge` (shortest runs merged first).
+    pub fn predict(
+        memory_budget: usize,
+        run_sizes: &[u64],
+        bytes_per_element: f64,
+        max_k: usize,
+        buffer_capacity: usize,
+        extra_input_buffers: usize,
+        model: &DeviceModel,
+    ) -> MemoryPlan {
+        let block_bytes = ((buffer_capacity as f64 * bytes_per_element) as u64).max(1);
+        let blocks = |bytes: u64| bytes.div_ceil(block_bytes).max(1);
+        let mut runs = run_sizes.to_vec();
+        let passes = KWayLoserTreeMerger::pass_count(runs.len(), max_k);
+        let (mut accesses, mut bytes, mut total_ms) = (0, 0, 0.0);
+        for pass in 1..=passes {
+            let fan_ins = KWayLoserTreeMerger::plan_pass(runs.len(), max_k, passes - pass + 1);
+            runs.sort_unstable();
+            let mut remaining = runs.into_iter();
+            let mut next_runs = Vec::new();
+            let (mut pass_accesses, mut pass_bytes, mut pass_cpu_ms) = (0, 0, 0.0);
+            for &fan_in in &fan_ins {
+                let inputs: Vec<u64> = remaining.by_ref().take(fan_in).collect();
+                let merged: u64 = inputs.iter().sum();
+                pass_accesses += inputs.iter().map(|&size| blocks(size)).sum::<u64>();
+                pass_accesses += blocks(merged);
+                pass_bytes += 2 * merged;
+                pass_cpu_ms += cpu_ms(merged as f64 / bytes_per_element, fan_in);
+                next_runs.push(merged);
+            }
+            next_runs.extend(remaining);
+            runs = next_runs;
+
+            let pass_io_ms = io_ms(model, pass_accesses, pass_bytes);
+            total_ms += if extra_input_buffers > 0 {
+                pass_io_ms.max(pass_cpu_ms)
+            } else {
+                pass_io_ms + pass_cpu_ms
+            };
+            accesses += pass_accesses;
+            bytes += pass_bytes;
+        }
+        MemoryPlan {
+            memory_budget,
+            max_k,
+            buffer_capacity,
+            extra_input_buffers,
+            passes,
+            predicted_accesses: accesses,
+            predicted_bytes: bytes,
+            predicted_ms: total_ms,
+        }
+    }
+}
@@ -0,0 +1,789 @@
+use super::IoStats;
+use std::fmt::Debug;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// 记录（i32）在文件中的编码。decoder/encoder 包装设备上打开的一个流，逐条读写记录；
+/// 每个流各有一个 decoder 或 encoder，编码可以带有流内的状态。
+pub trait RecordCodec: Send + Sync + Debug {
+    /// 报告与命令行中使用的名字
+    fn name(&self) -> &'static str;
+
+    /// 用该编码写出的顺串等中间文件的扩展名
+    fn extension(&self) -> &'static str;
+
+    fn decoder(&self, input: Box<dyn BufRead + Send>) -> Box<dyn RecordDecoder>;
+
+    fn encoder(&self, output: Box<dyn Write + Send>) -> Box<dyn RecordEncoder>;
+}
+
+pub trait RecordDecoder: Send {
+    /// 下一条记录；恰好在记录边界处到达文件末尾时返回 None，记录被截断时返回 UnexpectedEof
+    fn next_record(&mut self) -> io::Result<Option<i32>>;
+}
+
+pub trait RecordEncoder: Send {
+    fn write_record(&mut self, value: i32) -> io::Result<()>;
+
+    /// 写出缓冲中的数据并 flush 底层输出，之后不再写入
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// 以空白分隔的十进制文本，即各 project 原来的格式
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextCodec;
+
+/// 定长 4 字节小端补码
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BinaryCodec;
+
+/// zig-zag 变换后的 LEB128 变长整数，绝对值小的数只占 1~2 字节
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VarintCodec;
+
+/// 顺串专用的分块差分编码：每块以 DeltaBlockHeader 开头，之后是块内相邻记录之差的
+/// zig-zag 变长整数，其中连续 n 个为 0 的差值写成 0 和 n - 1 两个变长整数。
+/// 有序且重复多的数据一块只需几十字节。块头记录了块内的最后一个值和数据长度，
+/// 查找时可以整块跳过（见 seek）。
+/// 无序数据也能正确编码，只是压缩效果差，也不能 seek。
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaVarintCodec {
+    /// 每块最多的记录数，取值限制在 1..=DeltaBlockHeader::MAX_RECORDS
+    pub block_records: usize,
+}
+
+impl Default for DeltaVarintCodec {
+    fn default() -> Self {
+        Self {
+            block_records: 1024,
+        }
+    }
+}
+
+/// 按名字选择编码：text、binary、varint 或 delta
+pub fn codec_by_name(name: &str) -> io::Result<Arc<dyn RecordCodec>> {
+    match name {
+        "text" => Ok(Arc::new(TextCodec)),
+        "binary" => Ok(Arc::new(BinaryCodec)),
+        "varint" => Ok(Arc::new(VarintCodec)),
+        "delta" => Ok(Arc::new(DeltaVarintCodec::default())),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("未知的编码：{}（可选 text、binary、varint、delta）", name),
+        )),
+    }
+}
+
+/// 一次外排序中输入文件、顺串（含中间结果）与最终输出各自使用的编码，默认都是文本
+#[derive(Debug, Clone)]
+pub struct Codecs {
+    pub input: Arc<dyn RecordCodec>,
+    pub runs: Arc<dyn RecordCodec>,
+    pub output: Arc<dyn RecordCodec>,
+}
+
+impl Default for Codecs {
+    fn default() -> Self {
+        Self::uniform(Arc::new(TextCodec))
+    }
+}
+
+impl Codecs {
+    /// 三者使用同一种编码
+    pub fn uniform(codec: Arc<dyn RecordCodec>) -> Self {
+        Self {
+            input: codec.clone(),
+            runs: codec.clone(),
+            output: codec,
+        }
+    }
+
+    /// 例如 "input=text runs=varint output=binary"
+    pub fn describe(&self) -> String {
+        format!(
+            "input={} runs={} output={}",
+            self.input.name(),
+            self.runs.name(),
+            self.output.name()
+        )
+    }
+}
+
+impl IoStats {
+    /// 按 codec 逐条读取 path 中的记录
+    pub fn open_records(
+        &self,
+        path: impl AsRef<Path>,
+        codec: &dyn RecordCodec,
+    ) -> io::Result<Box<dyn RecordDecoder>> {
+        Ok(codec.decoder(Box::new(self.open(path)?)))
+    }
+
+    /// 创建 path，按 codec 逐条写入记录；写完后须调用 finish
+    pub fn create_records(
+        &self,
+        path: impl AsRef<Path>,
+        codec: &dyn RecordCodec,
+    ) -> io::Result<Box<dyn RecordEncoder>> {
+        Ok(codec.encoder(Box::new(self.create(path)?)))
+    }
+}
+
+/// 把 from 中按 from_codec 编码的记录按 to_codec 重新编码写入 to，返回记录条数
+pub fn convert(
+    io: &IoStats,
+    from: &Path,
+    from_codec: &dyn RecordCodec,
+    to: &Path,
+    to_codec: &dyn RecordCodec,
+) -> io::Result<u64> {
+    let mut decoder = io.open_records(from, from_codec)?;
+    let mut encoder = io.create_records(to, to_codec)?;
+    let mut count = 0_u64;
+    while let Some(value) = decoder.next_record()? {
+        encoder.write_record(value)?;
+        count += 1;
+    }
+    encoder.finish()?;
+    Ok(count)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "记录被截断")
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// 读一个字节，文件末尾返回 None
+fn read_byte(input: &mut dyn BufRead) -> io::Result<Option<u8>> {
+    let byte = match input.fill_buf()?.first() {
+        Some(&byte) => byte,
+        None => return Ok(None),
+    };
+    input.consume(1);
+    Ok(Some(byte))
+}
+
+impl RecordCodec for TextCodec {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn decoder(&self, input: Box<dyn BufRead + Send>) -> Box<dyn RecordDecoder> {
+        Box::new(TextDecoder {
+            input,
+            token: Vec::new(),
+        })
+    }
+
+    fn encoder(&self, output: Box<dyn Write + Send>) -> Box<dyn RecordEncoder> {
+        Box::new(TextEncoder { output })
+    }
+}
+
+struct TextDecoder {
+    input: Box<dyn BufRead + Send>,
+    token: Vec<u8>,
+}
+
+impl RecordDecoder for TextDecoder {
+    fn next_record(&mut self) -> io::Result<Option<i32>> {
+        self.token.clear();
+        // 跳过开头的空白，收集到下一个空白或文件末尾为止
+        loop {
+            let buf = self.input.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let mut used = 0;
+            let mut complete = false;
+            for &byte in buf {
+                used += 1;
+                if !byte.is_ascii_whitespace() {
+                    self.token.push(byte);
+                } else if !self.token.is_empty() {
+                    complete = true;
+                    break;
+                }
+            }
+            self.input.consume(used);
+            if complete {
+                break;
+            }
+        }
+        if self.token.is_empty() {
+            return Ok(None);
+        }
+        let text = std::str::from_utf8(&self.token).map_err(invalid_data)?;
+        text.parse::<i32>().map(Some).map_err(invalid_data)
+    }
+}
+
+struct TextEncoder {
+    output: Box<dyn Write + Send>,
+}
+
+impl RecordEncoder for TextEncoder {
+    fn write_record(&mut self, value: i32) -> io::Result<()> {
+        write!(self.output, "{} ", value)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl RecordCodec for BinaryCodec {
+    fn name(&self) -> &'static str {
+        "binary"
+    }
+
+    fn extension(&self) -> &'static str {
+        "bin"
+    }
+
+    fn decoder(&self, input: Box<dyn BufRead + Send>) -> Box<dyn RecordDecoder> {
+        Box::new(BinaryDecoder { input })
+    }
+
+    fn encoder(&self, output: Box<dyn Write + Send>) -> Box<dyn RecordEncoder> {
+        Box::new(BinaryEncoder { output })
+    }
+}
+
+struct BinaryDecoder {
+    input: Box<dyn BufRead + Send>,
+}
+
+impl RecordDecoder for BinaryDecoder {
+    fn next_record(&mut self) -> io::Result<Option<i32>> {
+        let mut bytes = [0_u8; 4];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            let n = self.input.read(&mut bytes[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        match filled {
+            0 => Ok(None),
+            4 => Ok(Some(i32::from_le_bytes(bytes))),
+            _ => Err(truncated()),
+        }
+    }
+}
+
+struct BinaryEncoder {
+    output: Box<dyn Write + Send>,
+}
+
+impl RecordEncoder for BinaryEncoder {
+    fn write_record(&mut self, value: i32) -> io::Result<()> {
+        self.output.write_all(&value.to_le_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl RecordCodec for VarintCodec {
+    fn name(&self) -> &'static str {
+        "varint"
+    }
+
+    fn extension(&self) -> &'static str {
+        "varint"
+    }
+
+    fn decoder(&self, input: Box<dyn BufRead + Send>) -> Box<dyn RecordDecoder> {
+        Box::new(VarintDecoder { input })
+    }
+
+    fn encoder(&self, output: Box<dyn Write + Send>) -> Box<dyn RecordEncoder> {
+        Box::new(VarintEncoder { output })
+    }
+}
+
+// zig-zag：0, -1, 1, -2, ... 依次映射为 0, 1, 2, 3, ...
+fn zigzag_encode(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn zigzag_decode(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+// LEB128：每字节低 7 位为数据，最高位表示后面还有字节
+fn write_varint(output: &mut dyn Write, mut value: u32) -> io::Result<()> {
+    let mut bytes = [0_u8; 5];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break;
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+    output.write_all(&bytes[..len])
+}
+
+fn read_varint(input: &mut dyn BufRead) -> io::Result<Option<u32>> {
+    let mut value = 0_u32;
+    for i in 0..5 {
+        let byte = match read_byte(input)? {
+            Some(byte) => byte,
+            None if i == 0 => return Ok(None),
+            None => return Err(truncated()),
+        };
+        if i == 4 && byte > 0x0f {
+            return Err(invalid_data("变长整数超出 32 位"));
+        }
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(invalid_data("变长整数超出 32 位"))
+}
+
+struct VarintDecoder {
+    input: Box<dyn BufRead + Send>,
+}
+
+impl RecordDecoder for VarintDecoder {
+    fn next_record(&mut self) -> io::Result<Option<i32>> {
+        Ok(read_varint(&mut self.input)?.map(zigzag_decode))
+    }
+}
+
+struct VarintEncoder {
+    output: Box<dyn Write + Send>,
+}
+
+impl RecordEncoder for VarintEncoder {
+    fn write_record(&mut self, value: i32) -> io::Result<()> {
+        write_varint(&mut self.output, zigzag_encode(value))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// DeltaVarintCodec 每块开头的定长块头，各字段均为 4 字节小端
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaBlockHeader {
+    /// 块内记录数，至少为 1
+    pub records: u32,
+    /// 块内第一条记录，不再出现在数据部分
+    pub first: i32,
+    /// 块内最后一条记录，有序文件中即块内最大值
+    pub last: i32,
+    /// 块头之后数据部分（records - 1 个差值，连续的 0 已合并）的字节数
+    pub payload_bytes: u32,
+}
+
+impl DeltaBlockHeader {
+    pub const LEN: usize = 16;
+    /// 一块最多的记录数。解码前按它和记录数检查块头，损坏的块头不会导致超大的分配
+    pub const MAX_RECORDS: u32 = 1 << 20;
+    /// 每个差值（或一段连续的 0）最多占的字节数：u32 的变长整数最长 5 字节
+    const MAX_BYTES_PER_DELTA: u32 = 5;
+
+    /// 读下一个块头；恰好在块边界处到达文件末尾时返回 None
+    pub fn read(input: &mut dyn BufRead) -> io::Result<Option<Self>> {
+        let mut bytes = [0_u8; Self::LEN];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            let n = input.read(&mut bytes[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+        if filled < bytes.len() {
+            return Err(truncated());
+        }
+        let field = |i: usize| bytes[4 * i..4 * i + 4].try_into().unwrap();
+        let header = Self {
+            records: u32::from_le_bytes(field(0)),
+            first: i32::from_le_bytes(field(1)),
+            last: i32::from_le_bytes(field(2)),
+            payload_bytes: u32::from_le_bytes(field(3)),
+        };
+        if header.records == 0 || header.records > Self::MAX_RECORDS {
+            return Err(invalid_data(format!(
+                "数据块的记录数 {} 不在 1..={} 内",
+                header.records,
+                Self::MAX_RECORDS
+            )));
+        }
+        if header.payload_bytes > (header.records - 1) * Self::MAX_BYTES_PER_DELTA {
+            return Err(invalid_data(format!(
+                "数据块的数据长度 {} 超过 {} 条记录可能的长度",
+                header.payload_bytes, header.records
+            )));
+        }
+        Ok(Some(header))
+    }
+
+    fn write(&self, output: &mut dyn Write) -> io::Result<()> {
+        let mut bytes = [0_u8; Self::LEN];
+        bytes[0..4].copy_from_slice(&self.records.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.first.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.last.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.payload_bytes.to_le_bytes());
+        output.write_all(&bytes)
+    }
+}
+
+impl DeltaVarintCodec {
+    /// 从有序文件中定位第一条不小于 key 的记录：块头的 last 小于 key 的块不解码，
+    /// 直接跳过数据部分。返回的 decoder 从该记录开始读。
+    pub fn seek(
+        &self,
+        input: Box<dyn BufRead + Send>,
+        key: i32,
+    ) -> io::Result<Box<dyn RecordDecoder>> {
+        let mut decoder = DeltaDecoder::new(input);
+        while let Some(header) = DeltaBlockHeader::read(&mut decoder.input)? {
+            if header.last >= key {
+                decoder.load_block(header)?;
+                decoder.pos = decoder.block.partition_point(|&value| value < key);
+                break;
+            }
+            let skipped = io::copy(
+                &mut (&mut decoder.input).take(header.payload_bytes as u64),
+                &mut io::sink(),
+            )?;
+            if skipped < header.payload_bytes as u64 {
+                return Err(truncated());
+            }
+        }
+        Ok(Box::new(decoder))
+    }
+}
+
+impl RecordCodec for DeltaVarintCodec {
+    fn name(&self) -> &'static str {
+        "delta"
+    }
+
+    fn extension(&self) -> &'static str {
+        "delta"
+    }
+
+    fn decoder(&self, input: Box<dyn BufRead + Send>) -> Box<dyn RecordDecoder> {
+        Box::new(DeltaDecoder::new(input))
+    }
+
+    fn encoder(&self, output: Box<dyn Write + Send>) -> Box<dyn RecordEncoder> {
+        Box::new(DeltaEncoder {
+            output,
+            block_records: self
+                .block_records
+                .clamp(1, DeltaBlockHeader::MAX_RECORDS as usize),
+            block: Vec::new(),
+            payload: Vec::new(),
+        })
+    }
+}
+
+struct DeltaDecoder {
+    input: Box<dyn BufRead + Send>,
+    // 当前块解码后的全部记录
+    block: Vec<i32>,
+    pos: usize,
+    payload: Vec<u8>,
+}
+
+impl DeltaDecoder {
+    fn new(input: Box<dyn BufRead + Send>) -> Self {
+        Self {
+            input,
+            block: Vec::new(),
+            pos: 0,
+            payload: Vec::new(),
+        }
+    }
+
+    // 读入 header 之后的数据部分并解码整块
+    fn load_block(&mut self, header: DeltaBlockHeader) -> io::Result<()> {
+        self.payload.resize(header.payload_bytes as usize, 0);
+        self.input
+            .read_exact(&mut self.payload)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => truncated(),
+                _ => e,
+            })?;
+        self.block.clear();
+        self.pos = 0;
+        let mut value = header.first;
+        self.block.push(value);
+        let mut payload = &self.payload[..];
+        let mut next =
+            || read_varint(&mut payload)?.ok_or_else(|| invalid_data("数据块长度与记录数不符"));
+        while self.block.len() < header.records as usize {
+            let delta = next()?;
+            if delta == 0 {
+                let repeats = next()? as usize + 1;
+                if self.block.len() + repeats > header.records as usize {
+                    return Err(invalid_data("数据块长度与记录数不符"));
+                }
+                self.block.extend(std::iter::repeat_n(value, repeats));
+            } else {
+                value = value.wrapping_add(zigzag_decode(delta));
+                self.block.push(value);
+            }
+        }
+        if !payload.is_empty() || value != header.last {
+            return Err(invalid_data("数据块与块头不符"));
+        }
+        Ok(())
+    }
+}
+
+impl RecordDecoder for DeltaDecoder {
+    fn next_record(&mut self) -> io::Result<Option<i32>> {
+        if self.pos == self.block.len() {
+            match DeltaBlockHeader::read(&mut self.input)? {
+                Some(header) => self.load_block(header)?,
+                None => return Ok(None),
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.block[self.pos - 1]))
+    }
+}
+
+struct DeltaEncoder {
+    output: Box<dyn Write + Send>,
+    block_records: usize,
+    block: Vec<i32>,
+    payload: Vec<u8>,
+}
+
+impl DeltaEncoder {
+    fn write_block(&mut self) -> io::Result<()> {
+        let (Some(&first), Some(&last)) = (self.block.first(), self.block.last()) else {
+            return Ok(());
+        };
+        self.payload.clear();
+        let mut zeros = 0_u32;
+        for pair in self.block.windows(2) {
+            let delta = zigzag_encode(pair[1].wrapping_sub(pair[0]));
+            if delta == 0 {
+                zeros += 1;
+                continue;
+            }
+            if zeros > 0 {
+                write_varint(&mut self.payload, 0)?;
+                write_varint(&mut self.payload, zeros - 1)?;
+                zeros = 0;
+            }
+            write_varint(&mut self.payload, delta)?;
+        }
+        if zeros > 0 {
+            write_varint(&mut self.payload, 0)?;
+            write_varint(&mut self.payload, zeros - 1)?;
+        }
+        DeltaBlockHeader {
+            records: self.block.len() as u32,
+            first,
+            last,
+            payload_bytes: self.payload.len() as u32,
+        }
+        .write(&mut self.output)?;
+        self.output.write_all(&self.payload)?;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl RecordEncoder for DeltaEncoder {
+    fn write_record(&mut self, value: i32) -> io::Result<()> {
+        self.block.push(value);
+        if self.block.len() == self.block_records {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryDevice;
+
+    const VALUES: [i32; 9] = [0, 1, -1, 63, -64, 300, -70_000, i32::MAX, i32::MIN];
+
+    fn round_trip(codec: &dyn RecordCodec) -> Vec<u8> {
+        let device = MemoryDevice::new();
+        let io = IoStats::with_device(16, Arc::new(device.clone()));
+        let mut encoder = io.create_records("/mem/codec", codec).unwrap();
+        for value in VALUES {
+            encoder.write_record(value).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let mut decoder = io.open_records("/mem/codec", codec).unwrap();
+        let mut decoded = Vec::new();
+        while let Some(value) = decoder.next_record().unwrap() {
+            decoded.push(value);
+        }
+        assert_eq!(decoded, VALUES, "{}", codec.name());
+        device.read("/mem/codec").unwrap()
+    }
+
+    #[test]
+    fn codecs_round_trip() {
+        assert_eq!(round_trip(&TextCodec).len(), 48);
+        assert_eq!(round_trip(&BinaryCodec).len(), 4 * VALUES.len());
+        assert_eq!(round_trip(&VarintCodec).len(), 20);
+        // 无序数据也能往返，三个块（4、4、1 条）
+        let delta = DeltaVarintCodec { block_records: 4 };
+        assert!(round_trip(&delta).len() > 3 * DeltaBlockHeader::LEN);
+    }
+
+    fn encode(codec: &dyn RecordCodec, values: impl IntoIterator<Item = i32>) -> Vec<u8> {
+        let device = MemoryDevice::new();
+        let io = IoStats::with_device(16, Arc::new(device.clone()));
+        let mut encoder = io.create_records("/mem/codec", codec).unwrap();
+        for value in values {
+            encoder.write_record(value).unwrap();
+        }
+        encoder.finish().unwrap();
+        device.read("/mem/codec").unwrap()
+    }
+
+    #[test]
+    fn delta_compresses_sorted_duplicates() {
+        // 10000 条有序记录，只有 16 种不同的值
+        let values = (0..10_000).map(|i| i / 625 - 8);
+        let binary = encode(&BinaryCodec, values.clone()).len();
+        let delta = encode(&DeltaVarintCodec::default(), values).len();
+        assert_eq!(binary, 40_000);
+        // 每块 16 个不同的值：块头加上约 30 字节的差值与重复次数
+        assert!(delta < 10 * 64, "{}", delta);
+    }
+
+    #[test]
+    fn delta_seeks_past_blocks() {
+        let codec = DeltaVarintCodec { block_records: 8 };
+        let data = encode(&codec, (0..100).map(|i| i * 3));
+        for (key, expected) in [
+            (i32::MIN, Some(0)),
+            (50, Some(51)),
+            (297, Some(297)),
+            (298, None),
+        ] {
+            let mut decoder = codec
+                .seek(Box::new(io::Cursor::new(data.clone())), key)
+                .unwrap();
+            assert_eq!(decoder.next_record().unwrap(), expected, "{}", key);
+        }
+        let mut decoder = codec
+            .seek(Box::new(io::Cursor::new(data.clone())), 100)
+            .unwrap();
+        let rest: Vec<_> = std::iter::from_fn(|| decoder.next_record().unwrap()).collect();
+        assert_eq!(rest, (34..100).map(|i| i * 3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn truncated_records_are_errors() {
+        for (codec, data) in [
+            (&BinaryCodec as &dyn RecordCodec, vec![1, 0, 0, 0, 2, 0]),
+            (&VarintCodec, vec![2, 0x80]),
+            (
+                &DeltaVarintCodec::default(),
+                [&encode(&DeltaVarintCodec::default(), [7])[..], &[1, 0]].concat(),
+            ),
+        ] {
+            let mut decoder = codec.decoder(Box::new(io::Cursor::new(data)));
+            assert!(decoder.next_record().unwrap().is_some());
+            let err = decoder.next_record().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{}", codec.name());
+        }
+    }
+
+    #[test]
+    fn delta_rejects_oversized_headers() {
+        let header = |records, payload_bytes| {
+            let mut bytes = Vec::new();
+            DeltaBlockHeader {
+                records,
+                first: 0,
+                last: 0,
+                payload_bytes,
+            }
+            .write(&mut bytes)
+            .unwrap();
+            bytes
+        };
+        for data in [
+            // 数据长度远超记录数允许的范围，不应按块头分配 4 GiB
+            header(2, u32::MAX),
+            header(4, 3 * 5 + 1),
+            header(DeltaBlockHeader::MAX_RECORDS + 1, 0),
+            header(0, 0),
+        ] {
+            let codec = DeltaVarintCodec::default();
+            let mut decoder = codec.decoder(Box::new(io::Cursor::new(data.clone())));
+            let err = decoder.next_record().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", err);
+            // 跳过块（last < key）时同样先检查块头
+            let err = codec
+                .seek(Box::new(io::Cursor::new(data)), i32::MAX)
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", err);
+        }
+    }
+
+    #[test]
+    fn converts_between_codecs() {
+        let device = MemoryDevice::new();
+        device.write("/mem/input.txt", "5 -3 12 ");
+        let io = IoStats::with_device(16, Arc::new(device.clone()));
+        let count = convert(
+            &io,
+            Path::new("/mem/input.txt"),
+            &TextCodec,
+            Path::new("/mem/input.bin"),
+            &BinaryCodec,
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(device.read("/mem/input.bin").unwrap().len(), 12);
+        convert(
+            &io,
+            Path::new("/mem/input.bin"),
+            &BinaryCodec,
+            Path::new("/mem/output.txt"),
+            &TextCodec,
+        )
+        .unwrap();
+        assert_eq!(device.read("/mem/output.txt").unwrap(), b"5 -3 12 ");
+    }
+}
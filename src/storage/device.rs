@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+/// 外排序读写文件所经过的设备。open/create 得到的流按块被读写，
+/// 每次 read/write 调用对应设备上的一个请求；其余是不计时的目录操作，语义与 std::fs 相同。
+pub trait BlockDevice: Send + Sync + Debug {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    fn size(&self, path: &Path) -> io::Result<u64>;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// 目录中直接包含的文件（不含子目录），按路径排序
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// 设备处理至今所有请求所需的模拟时间；真实设备不建模，返回 0
+    fn modelled_time(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// 直接读写文件系统
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileDevice;
+
+impl BlockDevice for FileDevice {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(File::create(path)?))
+    }
+
+    fn size(&self, path: &Path) -> io::Result<u64> {
+        Ok(fs::metadata(path)?.len())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// 磁盘的时间模型。请求若不是紧接着同一个流上一次请求的位置，需要先寻道并等待旋转，
+/// 之后按传输率传输数据；queue_depth 个请求可以同时被处理。
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceModel {
+    pub seek_ms: f64,
+    /// 平均旋转延迟（半圈）
+    pub rotational_latency_ms: f64,
+    pub transfer_mb_per_s: f64,
+    pub queue_depth: usize,
+}
+
+impl Default for DeviceModel {
+    fn default() -> Self {
+        Self::hdd()
+    }
+}
+
+impl DeviceModel {
+    /// 7200 转的机械硬盘
+    pub fn hdd() -> Self {
+        Self {
+            seek_ms: 8.0,
+            rotational_latency_ms: 4.17,
+            transfer_mb_per_s: 150.0,
+            queue_depth: 1,
+        }
+    }
+
+    /// SATA 固态硬盘：没有寻道与旋转，只有固定的访问延迟
+    pub fn ssd() -> Self {
+        Self {
+            seek_ms: 0.1,
+            rotational_latency_ms: 0.0,
+            transfer_mb_per_s: 500.0,
+            queue_depth: 32,
+        }
+    }
+
+    /// 一个 bytes 字节的请求所需的时间
+    pub fn service_ms(&self, bytes: usize, sequential: bool) -> f64 {
+        let positioning = if sequential {
+            0.0
+        } else {
+            self.seek_ms + self.rotational_latency_ms
+        };
+        positioning + bytes as f64 / (self.transfer_mb_per_s * 1000.0)
+    }
+}
+
+/// 在另一个设备之上按 DeviceModel 计时的模拟磁盘，数据仍由 backing 读写。
+///
+/// 同一线程发出的请求依次完成；不同线程的请求可以重叠，但同时最多 queue_depth 个。
+/// 请求按实际到达的顺序排入模拟时间，不会早于之前到达的请求开始。
+#[derive(Debug, Clone)]
+pub struct SimulatedDisk {
+    backing: Arc<dyn BlockDevice>,
+    model: DeviceModel,
+    state: Arc<Mutex<DiskState>>,
+}
+
+#[derive(Debug)]
+struct DiskState {
+    /// 每个队列位置空闲下来的时刻（毫秒）
+    slots: Vec<f64>,
+    /// 每个线程上一个请求完成的时刻
+    threads: HashMap<ThreadId, f64>,
+    /// 最近到达的请求的开始时刻
+    last_start: f64,
+    /// 磁头上一次服务的流
+    last_stream: Option<u64>,
+    next_stream: u64,
+    finished_at: f64,
+}
+
+impl SimulatedDisk {
+    pub fn new(backing: Arc<dyn BlockDevice>, model: DeviceModel) -> Self {
+        Self {
+            backing,
+            model,
+            state: Arc::new(Mutex::new(DiskState {
+                slots: vec![0.0; model.queue_depth.max(1)],
+                threads: HashMap::new(),
+                last_start: 0.0,
+                last_stream: None,
+                next_stream: 0,
+                finished_at: 0.0,
+            })),
+        }
+    }
+
+    /// 以文件系统为后端的模拟磁盘
+    pub fn over_files(model: DeviceModel) -> Self {
+        Self::new(Arc::new(FileDevice), model)
+    }
+
+    pub fn model(&self) -> &DeviceModel {
+        &self.model
+    }
+
+    fn stream<T>(&self, inner: T) -> SimulatedStream<T> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_stream;
+        state.next_stream += 1;
+        SimulatedStream {
+            inner,
+            id,
+            disk: self.clone(),
+        }
+    }
+
+    fn request(&self, stream: u64, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        let sequential = state.last_stream == Some(stream);
+        state.last_stream = Some(stream);
+        let service = self.model.service_ms(bytes, sequential);
+
+        let thread = thread::current().id();
+        let ready = state.threads.get(&thread).copied().unwrap_or(0.0);
+        let (slot, free_at) = state
+            .slots
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        let start = ready.max(free_at).max(state.last_start);
+        let end = start + service;
+        state.slots[slot] = end;
+        state.threads.insert(thread, end);
+        state.last_start = start;
+        state.finished_at = state.finished_at.max(end);
+    }
+}
+
+impl BlockDevice for SimulatedDisk {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(self.stream(self.backing.open(path)?)))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(self.stream(self.backing.create(path)?)))
+    }
+
+    fn size(&self, path: &Path) -> io::Result<u64> {
+        self.backing.size(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.backing.exists(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.backing.remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.backing.rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.backing.create_dir_all(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.backing.remove_dir_all(path)
+    }
+
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.backing.list_files(dir)
+    }
+
+    fn modelled_time(&self) -> Duration {
+        let finished_at = self.state.lock().unwrap().finished_at;
+        Duration::from_secs_f64(finished_at / 1000.0)
+    }
+}
+
+/// 模拟磁盘上打开的一个流，每次读写向磁盘记一个请求
+struct SimulatedStream<T> {
+    inner: T,
+    id: u64,
+    disk: SimulatedDisk,
+}
+
+impl<R: Read> Read for SimulatedStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.disk.request(self.id, n);
+        }
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for SimulatedStream<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if n > 0 {
+            self.disk.request(self.id, n);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
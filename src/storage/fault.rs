@@ -0,0 +1,195 @@
+use super::BlockDevice;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// 要注入的故障，可以同时设置多项。读写次数按设备上的请求计，从 1 开始。
+#[derive(Debug, Clone, Default)]
+pub struct FaultPlan {
+    /// 第 n 次读请求返回错误
+    pub fail_read: Option<u64>,
+    /// 第 n 次写请求返回错误
+    pub fail_write: Option<u64>,
+    /// 每次读最多返回这么多字节
+    pub short_reads: Option<usize>,
+    /// 每个文件只保存前这么多字节，之后写入的数据被悄悄丢弃，写入本身仍报告成功，
+    /// 留下一个在中途被截断的文件（如同写到一半时断电）
+    pub truncate_after: Option<u64>,
+    /// 设备容量（字节），占用超过容量的写入返回 StorageFull
+    pub capacity: Option<u64>,
+}
+
+/// 在另一个设备之上按 FaultPlan 注入读写故障，用于测试外排序的错误处理
+#[derive(Debug, Clone)]
+pub struct FaultyDevice {
+    backing: Arc<dyn BlockDevice>,
+    plan: FaultPlan,
+    state: Arc<Mutex<FaultState>>,
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    reads: u64,
+    writes: u64,
+    /// 经本设备写入、尚未删除的字节数
+    used: u64,
+}
+
+impl FaultyDevice {
+    pub fn new(backing: Arc<dyn BlockDevice>, plan: FaultPlan) -> Self {
+        Self {
+            backing,
+            plan,
+            state: Arc::new(Mutex::new(FaultState::default())),
+        }
+    }
+
+    /// 到目前为止的读请求与写请求次数
+    pub fn requests(&self) -> (u64, u64) {
+        let state = self.state.lock().unwrap();
+        (state.reads, state.writes)
+    }
+
+    fn release(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.used = state.used.saturating_sub(bytes);
+    }
+}
+
+fn injected(what: &str, n: u64) -> io::Error {
+    io::Error::other(format!("注入的故障：第 {} 次{}失败", n, what))
+}
+
+impl BlockDevice for FaultyDevice {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(FaultyReader {
+            inner: self.backing.open(path)?,
+            device: self.clone(),
+        }))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        // 覆盖已有文件会释放它原来占用的空间
+        if let Ok(size) = self.backing.size(path) {
+            self.release(size);
+        }
+        Ok(Box::new(FaultyWriter {
+            inner: self.backing.create(path)?,
+            device: self.clone(),
+            written: 0,
+        }))
+    }
+
+    fn size(&self, path: &Path) -> io::Result<u64> {
+        self.backing.size(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.backing.exists(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let size = self.backing.size(path).unwrap_or(0);
+        self.backing.remove_file(path)?;
+        self.release(size);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if let Ok(size) = self.backing.size(to) {
+            self.release(size);
+        }
+        self.backing.rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.backing.create_dir_all(path)
+    }
+
+    /// 只释放目录中直接包含的文件所占的空间
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let size: u64 = self
+            .backing
+            .list_files(path)
+            .unwrap_or_default()
+            .iter()
+            .map(|file| self.backing.size(file).unwrap_or(0))
+            .sum();
+        self.backing.remove_dir_all(path)?;
+        self.release(size);
+        Ok(())
+    }
+
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.backing.list_files(dir)
+    }
+}
+
+struct FaultyReader {
+    inner: Box<dyn Read + Send>,
+    device: FaultyDevice,
+}
+
+impl Read for FaultyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let mut state = self.device.state.lock().unwrap();
+            state.reads += 1;
+            state.reads
+        };
+        if self.device.plan.fail_read == Some(n) {
+            return Err(injected("读", n));
+        }
+        let len = match self.device.plan.short_reads {
+            Some(max) => buf.len().min(max.max(1)),
+            None => buf.len(),
+        };
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+struct FaultyWriter {
+    inner: Box<dyn Write + Send>,
+    device: FaultyDevice,
+    written: u64,
+}
+
+impl Write for FaultyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let plan = &self.device.plan;
+        // 超出 truncate_after 的部分不写入底层设备
+        let mut len = buf.len() as u64;
+        if let Some(limit) = plan.truncate_after {
+            len = len.min(limit.saturating_sub(self.written));
+        }
+        {
+            let mut state = self.device.state.lock().unwrap();
+            state.writes += 1;
+            if plan.fail_write == Some(state.writes) {
+                return Err(injected("写", state.writes));
+            }
+            if let Some(capacity) = plan.capacity
+                && state.used + len > capacity
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::StorageFull,
+                    format!("注入的故障：设备已满（容量 {} 字节）", capacity),
+                ));
+            }
+        }
+        if len < buf.len() as u64 {
+            self.inner.write_all(&buf[..len as usize])?;
+            self.written += len;
+            self.device.state.lock().unwrap().used += len;
+            return Ok(buf.len());
+        }
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        self.device.state.lock().unwrap().used += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,152 @@
+use super::BlockDevice;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// 完全在内存中的存储，供单元测试使用，不接触文件系统。
+/// 文件以路径为键保存，路径只是名字；创建文件时其所在目录隐式存在。
+/// 克隆得到的句柄共享同一组文件，测试可以在排序前后直接读写其中的数据。
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDevice {
+    inner: Arc<Mutex<MemoryFiles>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryFiles {
+    files: BTreeMap<PathBuf, Arc<Mutex<Vec<u8>>>>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl MemoryFiles {
+    fn dir_exists(&self, dir: &Path) -> bool {
+        self.dirs.contains(dir) || self.files.keys().any(|path| path.starts_with(dir))
+    }
+}
+
+impl MemoryDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 整个文件的内容
+    pub fn read(&self, path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let inner = self.inner.lock().unwrap();
+        let data = inner
+            .files
+            .get(path.as_ref())
+            .ok_or_else(|| not_found(path.as_ref()))?;
+        Ok(data.lock().unwrap().clone())
+    }
+
+    /// 用 data 替换（或创建）整个文件
+    pub fn write(&self, path: impl AsRef<Path>, data: impl Into<Vec<u8>>) {
+        self.inner.lock().unwrap().files.insert(
+            path.as_ref().to_path_buf(),
+            Arc::new(Mutex::new(data.into())),
+        );
+    }
+
+    /// 当前所有文件的路径
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.inner.lock().unwrap().files.keys().cloned().collect()
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("内存设备中没有 {}", path.display()),
+    )
+}
+
+impl BlockDevice for MemoryDevice {
+    /// 读取打开时文件的内容
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let data = Arc::new(Mutex::new(Vec::new()));
+        self.inner
+            .lock()
+            .unwrap()
+            .files
+            .insert(path.to_path_buf(), data.clone());
+        Ok(Box::new(MemoryWriter { data }))
+    }
+
+    fn size(&self, path: &Path) -> io::Result<u64> {
+        let inner = self.inner.lock().unwrap();
+        let data = inner.files.get(path).ok_or_else(|| not_found(path))?;
+        Ok(data.lock().unwrap().len() as u64)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.files.contains_key(path) || inner.dir_exists(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let data = inner.files.remove(from).ok_or_else(|| not_found(from))?;
+        inner.files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            inner.dirs.insert(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.dir_exists(path) {
+            return Err(not_found(path));
+        }
+        inner.files.retain(|file, _| !file.starts_with(path));
+        inner.dirs.retain(|dir| !dir.starts_with(path));
+        Ok(())
+    }
+
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let inner = self.inner.lock().unwrap();
+        if !inner.dir_exists(dir) {
+            return Err(not_found(dir));
+        }
+        Ok(inner
+            .files
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+}
+
+/// 直接追加到内存文件的输出
+struct MemoryWriter {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
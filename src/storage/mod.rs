@@ -0,0 +1,322 @@
+#![allow(unused)]
+//! 外排序（project_2/3/4）共用的存储层：读写经过的设备、按块统计 I/O 的读写包装，以及记录的编码。
+use std::io::{self, BufRead, Read, Write};
+use std::ops::{Add, AddAssign};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod codec;
+mod device;
+mod fault;
+mod memory;
+pub use codec::*;
+pub use device::*;
+pub use fault::*;
+pub use memory::*;
+
+/// 默认块大小（字节）
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// 目录 dir 中编号为 id、以 codec 编码的顺串文件，扩展名取自 codec
+pub fn run_path(dir: impl AsRef<Path>, id: u64, codec: &dyn RecordCodec) -> PathBuf {
+    dir.as_ref()
+        .join(format!("run_{}.{}", id, codec.extension()))
+}
+
+/// run_path 的逆：从文件名中取出顺串编号，扩展名与 codec 不符的文件不算顺串
+pub fn parse_run_id(file_name: &str, codec: &dyn RecordCodec) -> Option<u32> {
+    file_name
+        .strip_prefix("run_")?
+        .strip_suffix(codec.extension())?
+        .strip_suffix('.')?
+        .parse::<u32>()
+        .ok()
+}
+
+/// 一段时间内的 I/O 次数与字节数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoCounters {
+    pub block_reads: u64,
+    pub block_writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub file_opens: u64,
+    /// 设备模型给出的 I/O 时间，见 BlockDevice::modelled_time
+    pub modelled_time: Duration,
+}
+
+impl IoCounters {
+    pub const CSV_HEADER: &'static str =
+        "block_reads,block_writes,bytes_read,bytes_written,file_opens,modelled_ms";
+
+    pub fn to_csv(self) -> String {
+        format!(
+            "{},{},{},{},{},{:.3}",
+            self.block_reads,
+            self.block_writes,
+            self.bytes_read,
+            self.bytes_written,
+            self.file_opens,
+            self.modelled_ms()
+        )
+    }
+
+    pub fn modelled_ms(&self) -> f64 {
+        self.modelled_time.as_secs_f64() * 1000.0
+    }
+
+    /// 从 earlier 到 self 之间新增的计数
+    pub fn since(&self, earlier: &IoCounters) -> IoCounters {
+        IoCounters {
+            block_reads: self.block_reads - earlier.block_reads,
+            block_writes: self.block_writes - earlier.block_writes,
+            bytes_read: self.bytes_read - earlier.bytes_read,
+            bytes_written: self.bytes_written - earlier.bytes_written,
+            file_opens: self.file_opens - earlier.file_opens,
+            modelled_time: self.modelled_time.saturating_sub(earlier.modelled_time),
+        }
+    }
+}
+
+impl Add for IoCounters {
+    type Output = IoCounters;
+
+    fn add(mut self, other: IoCounters) -> IoCounters {
+        self += other;
+        self
+    }
+}
+
+impl AddAssign for IoCounters {
+    fn add_assign(&mut self, other: IoCounters) {
+        self.block_reads += other.block_reads;
+        self.block_writes += other.block_writes;
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.file_opens += other.file_opens;
+        self.modelled_time += other.modelled_time;
+    }
+}
+
+/// 设备上按块读取的输入
+pub type BlockReader = CountingReader<Box<dyn Read + Send>>;
+/// 设备上按块写出的输出
+pub type BlockWriter = CountingWriter<Box<dyn Write + Send>>;
+
+/// 共享的 I/O 计数器。克隆得到的句柄指向同一组计数与同一个设备，可以交给后台读写线程。
+/// 经它打开的文件以 block_size 为单位读写，每次向设备读入或写出一块记一次块 I/O。
+#[derive(Debug, Clone)]
+pub struct IoStats {
+    block_size: usize,
+    device: Arc<dyn BlockDevice>,
+    counters: Arc<Mutex<IoCounters>>,
+}
+
+impl Default for IoStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCK_SIZE)
+    }
+}
+
+impl IoStats {
+    /// 直接读写文件系统
+    pub fn new(block_size: usize) -> Self {
+        Self::with_device(block_size, Arc::new(FileDevice))
+    }
+
+    /// 在文件系统之上按 model 模拟磁盘耗时
+    pub fn simulated(block_size: usize, model: DeviceModel) -> Self {
+        Self::with_device(block_size, Arc::new(SimulatedDisk::over_files(model)))
+    }
+
+    pub fn with_device(block_size: usize, device: Arc<dyn BlockDevice>) -> Self {
+        if block_size == 0 {
+            panic!("块大小必须大于0");
+        }
+        Self {
+            block_size,
+            device,
+            counters: Arc::new(Mutex::new(IoCounters::default())),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn device(&self) -> &Arc<dyn BlockDevice> {
+        &self.device
+    }
+
+    /// 当前累计的计数
+    pub fn snapshot(&self) -> IoCounters {
+        IoCounters {
+            modelled_time: self.device.modelled_time(),
+            ..*self.counters.lock().unwrap()
+        }
+    }
+
+    pub fn open(&self, path: impl AsRef<Path>) -> io::Result<BlockReader> {
+        let inner = self.device.open(path.as_ref())?;
+        self.update(|c| c.file_opens += 1);
+        Ok(self.reader(inner))
+    }
+
+    pub fn create(&self, path: impl AsRef<Path>) -> io::Result<BlockWriter> {
+        let inner = self.device.create(path.as_ref())?;
+        self.update(|c| c.file_opens += 1);
+        Ok(self.writer(inner))
+    }
+
+    /// 包装一个已打开的输入，不计入打开次数
+    pub fn reader<R: Read>(&self, inner: R) -> CountingReader<R> {
+        CountingReader {
+            inner,
+            buffer: vec![0; self.block_size].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+            stats: self.clone(),
+        }
+    }
+
+    /// 包装一个已打开的输出，不计入打开次数
+    pub fn writer<W: Write>(&self, inner: W) -> CountingWriter<W> {
+        CountingWriter {
+            inner,
+            buffer: Vec::with_capacity(self.block_size),
+            stats: self.clone(),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut IoCounters)) {
+        f(&mut self.counters.lock().unwrap());
+    }
+}
+
+/// 按块读取的输入，相当于缓冲区恰为一块的 BufReader
+pub struct CountingReader<R> {
+    inner: R,
+    buffer: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+    stats: IoStats,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.filled {
+            let n = self.inner.read(&mut self.buffer)?;
+            if n > 0 {
+                self.stats.update(|c| {
+                    c.block_reads += 1;
+                    c.bytes_read += n as u64;
+                });
+            }
+            self.pos = 0;
+            self.filled = n;
+        }
+        Ok(&self.buffer[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.filled);
+    }
+}
+
+/// 按块写出的输出：攒满一块才写入底层，flush 时写出最后不满的一块
+pub struct CountingWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    stats: IoStats,
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.inner.write_all(&self.buffer)?;
+        let n = self.buffer.len() as u64;
+        self.stats.update(|c| {
+            c.block_writes += 1;
+            c.bytes_written += n;
+        });
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() == self.stats.block_size {
+            self.write_block()?;
+        }
+        let n = data.len().min(self.stats.block_size - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for CountingWriter<W> {
+    // 与 BufWriter 一样在析构时尽量写出剩余数据，错误被忽略；需要错误时应先调用 flush
+    fn drop(&mut self) {
+        let _ = self.write_block();
+    }
+}
+
+/// 按阶段记录同一个 IoStats 的增量，例如顺串生成与各趟归并
+#[derive(Debug, Clone)]
+pub struct IoPhases {
+    stats: IoStats,
+    last: IoCounters,
+    pub phases: Vec<(String, IoCounters)>,
+}
+
+impl IoPhases {
+    pub fn new(stats: &IoStats) -> Self {
+        Self {
+            stats: stats.clone(),
+            last: stats.snapshot(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// 结束一个阶段，返回该阶段的计数
+    pub fn mark(&mut self, phase: impl Into<String>) -> IoCounters {
+        let now = self.stats.snapshot();
+        let counters = now.since(&self.last);
+        self.last = now;
+        self.phases.push((phase.into(), counters));
+        counters
+    }
+
+    /// 追加在别处按同一个 IoStats 记录的阶段，下一个阶段从当前计数开始
+    pub fn extend(&mut self, phases: impl IntoIterator<Item = (String, IoCounters)>) {
+        self.phases.extend(phases);
+        self.last = self.stats.snapshot();
+    }
+
+    pub fn total(&self) -> IoCounters {
+        self.phases
+            .iter()
+            .fold(IoCounters::default(), |total, (_, counters)| {
+                total + *counters
+            })
+    }
+}